@@ -1,4 +1,5 @@
-use crate::battery::{get_battery_info, ChargingState};
+use crate::backend::BatteryBackend;
+use crate::battery::{get_selected_battery, ChargingState};
 use anyhow::Result;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -7,6 +8,8 @@ use std::time::Duration;
 /// Alert configuration.
 #[derive(Debug, Clone)]
 pub struct AlertConfig {
+    /// Pack to monitor; `None` monitors all packs combined.
+    pub battery: Option<String>,
     pub level_threshold: Option<u8>,
     pub on_full: bool,
     pub check_interval: Duration,
@@ -15,6 +18,7 @@ pub struct AlertConfig {
 impl Default for AlertConfig {
     fn default() -> Self {
         Self {
+            battery: None,
             level_threshold: None,
             on_full: false,
             check_interval: Duration::from_secs(60),
@@ -26,7 +30,11 @@ impl Default for AlertConfig {
 ///
 /// This blocks the current thread and monitors the battery, printing alerts
 /// when conditions are met. Use `running` to signal the loop to stop.
-pub fn run_alert_loop(config: &AlertConfig, running: Arc<AtomicBool>) -> Result<()> {
+pub fn run_alert_loop(
+    backend: &dyn BatteryBackend,
+    config: &AlertConfig,
+    running: Arc<AtomicBool>,
+) -> Result<()> {
     let mut level_alerted = false;
     let mut full_alerted = false;
 
@@ -43,7 +51,7 @@ pub fn run_alert_loop(config: &AlertConfig, running: Arc<AtomicBool>) -> Result<
     }
 
     while running.load(Ordering::Relaxed) {
-        match get_battery_info() {
+        match get_selected_battery(backend, config.battery.as_deref()) {
             Ok(info) => {
                 // Low battery alert
                 if let Some(threshold) = config.level_threshold {
@@ -140,6 +148,7 @@ mod tests {
     #[test]
     fn test_alert_config_default() {
         let config = AlertConfig::default();
+        assert_eq!(config.battery, None);
         assert_eq!(config.level_threshold, None);
         assert!(!config.on_full);
         assert_eq!(config.check_interval, Duration::from_secs(60));
@@ -0,0 +1,416 @@
+use crate::battery::{self, BatteryInfo};
+use crate::charge::{self, ChargeBehaviour, ChargeLimit};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Default location of the Linux `power_supply` class.
+pub const DEFAULT_SYSFS_ROOT: &str = "/sys/class/power_supply";
+
+/// A source of battery readings.
+///
+/// Commands only talk to the battery through this trait, so they can be
+/// pointed at a copied sysfs tree or canned data instead of the real hardware.
+pub trait BatteryBackend {
+    /// Read every battery pack the backend knows about.
+    fn batteries(&self) -> Result<Vec<BatteryInfo>>;
+
+    /// Total system power draw in watts, if the platform reports it.
+    fn power_draw(&self) -> Option<f64>;
+
+    /// Current charge thresholds of a pack, or `None` if the hardware has none.
+    fn charge_limit(&self, _battery: &str) -> Result<Option<ChargeLimit>> {
+        Ok(None)
+    }
+
+    /// Write new charge thresholds to a pack.
+    ///
+    /// Returns a description of each write made, or of each write that would
+    /// be made when `dry_run` is set.
+    fn set_charge_limit(
+        &self,
+        battery: &str,
+        _limit: &ChargeLimit,
+        _dry_run: bool,
+    ) -> Result<Vec<String>> {
+        anyhow::bail!("Charge limits are not supported on {}", battery)
+    }
+
+    /// Charge behaviour modes of a pack, or `None` if the hardware has none.
+    fn charge_behaviour(&self, _battery: &str) -> Result<Option<ChargeBehaviour>> {
+        Ok(None)
+    }
+
+    /// Switch a pack's charge behaviour (`auto`, `inhibit-charge`, ...).
+    fn set_charge_behaviour(
+        &self,
+        battery: &str,
+        _mode: &str,
+        _dry_run: bool,
+    ) -> Result<Vec<String>> {
+        anyhow::bail!("Charge behaviour control is not supported on {}", battery)
+    }
+}
+
+/// Reads `power_supply` entries from a sysfs-style directory.
+///
+/// Pointing `root` at a directory of canned files (one sub-directory per
+/// supply, e.g. `BAT0/capacity`) makes this the directory/fixture backend.
+pub struct SysfsBackend {
+    root: PathBuf,
+}
+
+impl SysfsBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn battery_dir(&self, battery: &str) -> Result<PathBuf> {
+        let dir = self.root.join(battery);
+        if !dir.join("type").exists() {
+            anyhow::bail!(
+                "No battery named '{}' under {}",
+                battery,
+                self.root.display()
+            );
+        }
+        Ok(dir)
+    }
+}
+
+/// Write `value` to a sysfs attribute, or only describe the write when `dry_run`.
+fn write_sysfs(path: &Path, value: &str, dry_run: bool) -> Result<String> {
+    if dry_run {
+        return Ok(format!("would write {} to {}", value, path.display()));
+    }
+    std::fs::write(path, value).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::PermissionDenied {
+            charge::permission_error(path)
+        } else {
+            anyhow::Error::new(e).context(format!("Failed to write {}", path.display()))
+        }
+    })?;
+    Ok(format!("wrote {} to {}", value, path.display()))
+}
+
+impl Default for SysfsBackend {
+    fn default() -> Self {
+        Self::new(DEFAULT_SYSFS_ROOT)
+    }
+}
+
+impl BatteryBackend for SysfsBackend {
+    fn batteries(&self) -> Result<Vec<BatteryInfo>> {
+        battery::read_sysfs_batteries(&self.root)
+    }
+
+    fn power_draw(&self) -> Option<f64> {
+        battery::read_sysfs_power_draw(&self.root)
+    }
+
+    fn charge_limit(&self, battery: &str) -> Result<Option<ChargeLimit>> {
+        Ok(battery::read_sysfs_charge_limit(
+            &self.battery_dir(battery)?,
+        ))
+    }
+
+    fn set_charge_limit(
+        &self,
+        battery: &str,
+        limit: &ChargeLimit,
+        dry_run: bool,
+    ) -> Result<Vec<String>> {
+        let dir = self.battery_dir(battery)?;
+        let current = battery::read_sysfs_charge_limit(&dir)
+            .with_context(|| format!("Charge limits are not supported on {}", battery))?;
+
+        let start_file = battery::find_sysfs_attr(&dir, battery::START_THRESHOLD_FILES);
+        let end_file = battery::find_sysfs_attr(&dir, battery::END_THRESHOLD_FILES);
+
+        let mut writes = Vec::new();
+        if let Some(start) = limit.start {
+            let path = start_file
+                .clone()
+                .with_context(|| format!("{} has no start threshold", battery))?;
+            writes.push((path, start));
+        }
+        if let Some(end) = limit.end {
+            let path = end_file
+                .clone()
+                .with_context(|| format!("{} has no stop threshold", battery))?;
+            writes.push((path, end));
+        }
+
+        // Drivers reject a start >= stop at any moment, so raise the stop
+        // threshold first when the new start would not fit under the old one.
+        let new_start = limit.start.unwrap_or(0);
+        if new_start >= current.end.unwrap_or(100) {
+            writes.reverse();
+        }
+
+        writes
+            .iter()
+            .map(|(path, value)| write_sysfs(path, &value.to_string(), dry_run))
+            .collect()
+    }
+
+    fn charge_behaviour(&self, battery: &str) -> Result<Option<ChargeBehaviour>> {
+        let path = self.battery_dir(battery)?.join("charge_behaviour");
+        Ok(std::fs::read_to_string(path)
+            .ok()
+            .and_then(|s| charge::parse_charge_behaviour(&s)))
+    }
+
+    fn set_charge_behaviour(
+        &self,
+        battery: &str,
+        mode: &str,
+        dry_run: bool,
+    ) -> Result<Vec<String>> {
+        let current = self
+            .charge_behaviour(battery)?
+            .with_context(|| format!("Charge behaviour control is not supported on {}", battery))?;
+        if !current.supports(mode) {
+            anyhow::bail!(
+                "{} does not support '{}'. Supported modes: {}",
+                battery,
+                mode,
+                current.modes().join(", ")
+            );
+        }
+        let path = self.battery_dir(battery)?.join("charge_behaviour");
+        Ok(vec![write_sysfs(&path, mode, dry_run)?])
+    }
+}
+
+/// Reads the internal battery through `pmset` and `system_profiler`.
+pub struct MacosBackend;
+
+impl BatteryBackend for MacosBackend {
+    fn batteries(&self) -> Result<Vec<BatteryInfo>> {
+        Ok(vec![battery::read_macos_battery()?])
+    }
+
+    fn power_draw(&self) -> Option<f64> {
+        crate::power::get_macos_power_draw()
+    }
+}
+
+/// Serves canned readings to unit tests.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct FixtureBackend {
+    pub packs: Vec<BatteryInfo>,
+    pub power_draw: Option<f64>,
+}
+
+#[cfg(test)]
+impl FixtureBackend {
+    pub fn new(packs: Vec<BatteryInfo>) -> Self {
+        let power_draw = packs
+            .iter()
+            .filter_map(|p| p.power_draw_watts)
+            .fold(None, |acc: Option<f64>, p| Some(acc.unwrap_or(0.0) + p));
+        Self { packs, power_draw }
+    }
+}
+
+#[cfg(test)]
+impl BatteryBackend for FixtureBackend {
+    fn batteries(&self) -> Result<Vec<BatteryInfo>> {
+        if self.packs.is_empty() {
+            anyhow::bail!("No battery found. Are you on a laptop?");
+        }
+        Ok(self.packs.clone())
+    }
+
+    fn power_draw(&self) -> Option<f64> {
+        self.power_draw
+    }
+
+    fn charge_limit(&self, battery: &str) -> Result<Option<ChargeLimit>> {
+        let pack = self
+            .packs
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(battery))
+            .with_context(|| format!("No battery named '{}'", battery))?;
+        Ok(pack.charge_limit)
+    }
+}
+
+/// Unsupported platforms: every read fails with a clear message.
+struct UnsupportedBackend;
+
+impl BatteryBackend for UnsupportedBackend {
+    fn batteries(&self) -> Result<Vec<BatteryInfo>> {
+        anyhow::bail!("Unsupported platform. batteryctl supports macOS and Linux.")
+    }
+
+    fn power_draw(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// Pick the backend for this platform.
+///
+/// An explicit `sysfs_root` (from `--sysfs-root` or `BATTERYCTL_SYSFS_ROOT`)
+/// always selects the sysfs backend, on any platform.
+pub fn detect(sysfs_root: Option<&Path>) -> Box<dyn BatteryBackend> {
+    if let Some(root) = sysfs_root {
+        Box::new(SysfsBackend::new(root))
+    } else if cfg!(target_os = "macos") {
+        Box::new(MacosBackend)
+    } else if cfg!(target_os = "linux") {
+        Box::new(SysfsBackend::default())
+    } else {
+        Box::new(UnsupportedBackend)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::ChargingState;
+
+    fn fixture_root(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/sysfs")
+            .join(name)
+    }
+
+    #[test]
+    fn test_sysfs_backend_reads_fixture_tree() {
+        let backend = SysfsBackend::new(fixture_root("mah"));
+        let packs = backend.batteries().unwrap();
+        assert_eq!(packs.len(), 1);
+        assert_eq!(packs[0].name, "BAT0");
+        assert_eq!(packs[0].level, 76);
+        assert_eq!(packs[0].state, ChargingState::Discharging);
+        assert_eq!(packs[0].cycle_count, Some(312));
+        // "Unknown" and blank identity values are treated as missing
+        assert_eq!(packs[0].model_name.as_deref(), Some("DELL 8P6X604"));
+        assert_eq!(packs[0].manufacturer, None);
+        assert_eq!(packs[0].serial_number, None);
+        assert_eq!(packs[0].technology.as_deref(), Some("Li-ion"));
+    }
+
+    #[test]
+    fn test_sysfs_backend_skips_non_batteries() {
+        let backend = SysfsBackend::new(fixture_root("dual"));
+        let names: Vec<String> = backend
+            .batteries()
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, vec!["BAT0", "BAT1"]);
+        assert!(backend.power_draw().is_some());
+    }
+
+    #[test]
+    fn test_missing_root_reports_no_battery() {
+        let backend = SysfsBackend::new(fixture_root("does-not-exist"));
+        let err = backend.batteries().unwrap_err().to_string();
+        assert!(err.contains("No battery found"));
+        assert_eq!(backend.power_draw(), None);
+    }
+
+    fn copy_tree(name: &str) -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = fixture_root(name);
+        for supply in std::fs::read_dir(&src).unwrap().flatten() {
+            let dest = tmp.path().join(supply.file_name());
+            std::fs::create_dir(&dest).unwrap();
+            for file in std::fs::read_dir(supply.path()).unwrap().flatten() {
+                std::fs::copy(file.path(), dest.join(file.file_name())).unwrap();
+            }
+        }
+        tmp
+    }
+
+    #[test]
+    fn test_sysfs_charge_limit_roundtrip() {
+        let tree = copy_tree("wh");
+        let backend = SysfsBackend::new(tree.path());
+        assert_eq!(
+            backend.charge_limit("BAT0").unwrap(),
+            Some(ChargeLimit {
+                start: Some(75),
+                end: Some(80)
+            })
+        );
+
+        let lower = ChargeLimit {
+            start: Some(40),
+            end: Some(50),
+        };
+        let planned = backend.set_charge_limit("BAT0", &lower, true).unwrap();
+        assert_eq!(planned.len(), 2);
+        assert!(planned[0].starts_with("would write 40"));
+        assert_eq!(backend.charge_limit("BAT0").unwrap().unwrap().end, Some(80));
+
+        backend.set_charge_limit("BAT0", &lower, false).unwrap();
+        assert_eq!(backend.charge_limit("BAT0").unwrap(), Some(lower));
+
+        // Raising both thresholds above the old stop writes the stop first
+        let higher = ChargeLimit {
+            start: Some(85),
+            end: Some(90),
+        };
+        let done = backend.set_charge_limit("BAT0", &higher, false).unwrap();
+        assert!(done[0].starts_with("wrote 90"));
+        assert_eq!(backend.charge_limit("BAT0").unwrap(), Some(higher));
+    }
+
+    #[test]
+    fn test_sysfs_charge_limit_unsupported() {
+        let backend = SysfsBackend::new(fixture_root("mah"));
+        assert_eq!(backend.charge_limit("BAT0").unwrap(), None);
+        let limit = ChargeLimit {
+            start: None,
+            end: Some(80),
+        };
+        let err = backend.set_charge_limit("BAT0", &limit, true).unwrap_err();
+        assert!(err.to_string().contains("not supported"));
+        assert!(backend.charge_limit("BAT9").is_err());
+    }
+
+    #[test]
+    fn test_sysfs_charge_behaviour_modes() {
+        let tree = copy_tree("wh");
+        let backend = SysfsBackend::new(tree.path());
+        let path = tree.path().join("BAT0/charge_behaviour");
+
+        // Only the active mode is listed: known modes are tried, others refused
+        std::fs::write(&path, "[auto]\n").unwrap();
+        let err = backend
+            .set_charge_behaviour("BAT0", "bogus", false)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("auto, inhibit-charge, force-discharge"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[auto]\n");
+        backend
+            .set_charge_behaviour("BAT0", "inhibit-charge", false)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "inhibit-charge");
+
+        std::fs::write(&path, "[auto] inhibit-charge\n").unwrap();
+        assert!(backend
+            .set_charge_behaviour("BAT0", "force-discharge", false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_fixture_backend() {
+        let backend = FixtureBackend::new(vec![BatteryInfo {
+            name: "BAT0".to_string(),
+            level: 55,
+            power_draw_watts: Some(7.5),
+            ..Default::default()
+        }]);
+        assert_eq!(backend.power_draw(), Some(7.5));
+        let info = battery::get_battery_info(&backend).unwrap();
+        assert_eq!(info.level, 55);
+        assert!(FixtureBackend::default().batteries().is_err());
+    }
+}
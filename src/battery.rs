@@ -1,18 +1,46 @@
+use crate::backend::BatteryBackend;
+use crate::charge::ChargeLimit;
+use crate::rating::{self, CycleRating, PackIdentity};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::process::Command;
 
 /// Represents the current charging state of the battery.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ChargingState {
     Charging,
     Discharging,
     Full,
     NotCharging,
+    #[default]
     Unknown,
 }
 
+impl ChargingState {
+    /// Stable name used in the history database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChargingState::Charging => "Charging",
+            ChargingState::Discharging => "Discharging",
+            ChargingState::Full => "Full",
+            ChargingState::NotCharging => "NotCharging",
+            ChargingState::Unknown => "Unknown",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "Charging" => Some(ChargingState::Charging),
+            "Discharging" => Some(ChargingState::Discharging),
+            "Full" => Some(ChargingState::Full),
+            "NotCharging" => Some(ChargingState::NotCharging),
+            "Unknown" => Some(ChargingState::Unknown),
+            _ => None,
+        }
+    }
+}
+
 impl std::fmt::Display for ChargingState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -26,12 +54,13 @@ impl std::fmt::Display for ChargingState {
 }
 
 /// Battery condition assessment.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BatteryCondition {
     Normal,
     Replace,
     ServiceRecommended,
     Poor,
+    #[default]
     Unknown,
 }
 
@@ -47,9 +76,60 @@ impl std::fmt::Display for BatteryCondition {
     }
 }
 
+/// Unit a capacity figure is expressed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CapacityUnit {
+    /// Charge in milliampere-hours (`charge_*` in sysfs, macOS).
+    #[default]
+    #[serde(rename = "mAh")]
+    MilliampHours,
+    /// Energy in milliwatt-hours (`energy_*` in sysfs).
+    #[serde(rename = "mWh")]
+    MilliwattHours,
+}
+
+impl CapacityUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CapacityUnit::MilliampHours => "mAh",
+            CapacityUnit::MilliwattHours => "mWh",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "mAh" => Some(CapacityUnit::MilliampHours),
+            "mWh" => Some(CapacityUnit::MilliwattHours),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for CapacityUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Convert a charge to energy at the given voltage (mAh × mV → mWh).
+pub fn mah_to_mwh(mah: u32, voltage_mv: f64) -> u32 {
+    (mah as f64 * voltage_mv / 1000.0).round() as u32
+}
+
+/// Convert an energy to charge at the given voltage (mWh ÷ mV → mAh).
+pub fn mwh_to_mah(mwh: u32, voltage_mv: f64) -> u32 {
+    (mwh as f64 * 1000.0 / voltage_mv).round() as u32
+}
+
+/// Name used for the aggregate view of several battery packs.
+pub const COMBINED_NAME: &str = "combined";
+
 /// Core battery information snapshot.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BatteryInfo {
+    /// Pack identifier (e.g. `BAT0`), or `combined` for the aggregate view.
+    #[serde(default)]
+    pub name: String,
     pub level: u8,
     pub state: ChargingState,
     pub time_remaining_minutes: Option<i64>,
@@ -58,17 +138,49 @@ pub struct BatteryInfo {
     pub max_capacity_mah: Option<u32>,
     pub design_capacity_mah: Option<u32>,
     pub current_capacity_mah: Option<u32>,
+    pub max_energy_mwh: Option<u32>,
+    pub design_energy_mwh: Option<u32>,
+    pub current_energy_mwh: Option<u32>,
+    /// Minimum design voltage, used to convert between charge and energy.
+    pub voltage_min_design_mv: Option<f64>,
+    /// Unit the firmware reports natively; the other one is derived from it.
+    pub capacity_unit: CapacityUnit,
     pub temperature_celsius: Option<f64>,
     pub voltage_mv: Option<f64>,
     pub condition: BatteryCondition,
     pub manufacture_date: Option<String>,
+    /// Identity of the physical pack, as far as the firmware reports it.
+    #[serde(default)]
+    pub model_name: Option<String>,
+    #[serde(default)]
+    pub manufacturer: Option<String>,
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    /// Cell chemistry (e.g. `Li-ion`, `Li-poly`).
+    #[serde(default)]
+    pub technology: Option<String>,
     pub is_present: bool,
+    /// Charge start/stop thresholds, when the hardware supports them.
+    #[serde(default)]
+    pub charge_limit: Option<ChargeLimit>,
+    /// Per-pack readings when this is the combined view of several packs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub packs: Vec<BatteryInfo>,
 }
 
 impl BatteryInfo {
-    /// Calculate the health percentage (max_capacity / design_capacity * 100).
+    /// Max and design capacity in the firmware's native unit.
+    pub fn native_capacity(&self) -> (Option<u32>, Option<u32>) {
+        match self.capacity_unit {
+            CapacityUnit::MilliampHours => (self.max_capacity_mah, self.design_capacity_mah),
+            CapacityUnit::MilliwattHours => (self.max_energy_mwh, self.design_energy_mwh),
+        }
+    }
+
+    /// Calculate the health percentage (max_capacity / design_capacity * 100),
+    /// using the firmware's native unit.
     pub fn health_percent(&self) -> Option<f64> {
-        match (self.max_capacity_mah, self.design_capacity_mah) {
+        match self.native_capacity() {
             (Some(max), Some(design)) if design > 0 => {
                 Some(max as f64 / design as f64 * 100.0)
             }
@@ -84,9 +196,27 @@ impl BatteryInfo {
         }
     }
 
-    /// Estimate remaining cycles based on typical 1000 cycle lifespan.
+    /// Calculate the capacity loss in mWh.
+    pub fn capacity_loss_mwh(&self) -> Option<i32> {
+        match (self.max_energy_mwh, self.design_energy_mwh) {
+            (Some(max), Some(design)) => Some(design as i32 - max as i32),
+            _ => None,
+        }
+    }
+
+    /// Rated cycle life for this pack's chemistry and model.
+    pub fn cycle_rating(&self) -> CycleRating {
+        rating::rating_for(PackIdentity {
+            technology: self.technology.as_deref(),
+            manufacturer: self.manufacturer.as_deref(),
+            model: self.model_name.as_deref(),
+        })
+    }
+
+    /// Estimate remaining cycles from the pack's rated cycle life.
     pub fn estimated_remaining_cycles(&self) -> Option<u32> {
-        self.cycle_count.map(|c| 1000u32.saturating_sub(c))
+        self.cycle_count
+            .map(|c| self.cycle_rating().remaining_cycles(c))
     }
 
     /// Get time remaining as a formatted string.
@@ -106,22 +236,243 @@ impl BatteryInfo {
     }
 }
 
-/// Reads battery information from the current platform.
-pub fn get_battery_info() -> Result<BatteryInfo> {
-    if cfg!(target_os = "macos") {
-        get_battery_info_macos()
-    } else if cfg!(target_os = "linux") {
-        get_battery_info_linux()
+/// Reads battery information through `backend`.
+///
+/// On machines with several packs this is the combined view; the individual
+/// packs are available in [`BatteryInfo::packs`].
+pub fn get_battery_info(backend: &dyn BatteryBackend) -> Result<BatteryInfo> {
+    Ok(combine_batteries(backend.batteries()?))
+}
+
+/// Reads the pack named by `selector`, or the combined view when `None`.
+pub fn get_selected_battery(
+    backend: &dyn BatteryBackend,
+    selector: Option<&str>,
+) -> Result<BatteryInfo> {
+    let info = get_battery_info(backend)?;
+    match selector {
+        Some(name) => select_battery(info, name),
+        None => Ok(info),
+    }
+}
+
+/// Pick a single pack (or the combined view) out of a combined reading.
+pub fn select_battery(info: BatteryInfo, name: &str) -> Result<BatteryInfo> {
+    if info.name.eq_ignore_ascii_case(name) {
+        return Ok(info);
+    }
+    let available: Vec<String> = if info.packs.is_empty() {
+        vec![info.name.clone()]
     } else {
-        anyhow::bail!("Unsupported platform. batteryctl supports macOS and Linux.")
+        std::iter::once(info.name.clone())
+            .chain(info.packs.iter().map(|p| p.name.clone()))
+            .collect()
+    };
+    info.packs
+        .into_iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+        .with_context(|| {
+            format!(
+                "No battery named '{}'. Available: {}",
+                name,
+                available.join(", ")
+            )
+        })
+}
+
+/// Merge several packs into one aggregate reading.
+///
+/// Capacities and power are summed, the level is weighted by capacity and the
+/// time remaining is the combined charge divided by the combined drain rate.
+/// A single pack is returned unchanged.
+pub fn combine_batteries(mut packs: Vec<BatteryInfo>) -> BatteryInfo {
+    if packs.len() == 1 {
+        return packs.remove(0);
+    }
+
+    let sum = |f: fn(&BatteryInfo) -> Option<u32>| -> Option<u32> {
+        packs.iter().map(f).sum::<Option<u32>>()
+    };
+    let max_cap = sum(|p| p.max_capacity_mah);
+    let design_cap = sum(|p| p.design_capacity_mah);
+    let current_cap = sum(|p| p.current_capacity_mah);
+    let max_energy = sum(|p| p.max_energy_mwh);
+    let design_energy = sum(|p| p.design_energy_mwh);
+    let current_energy = sum(|p| p.current_energy_mwh);
+
+    // Energy adds up across packs of different voltages; charge only does
+    // when the voltages match, so prefer energy whenever every pack has it.
+    let capacity_unit = if max_energy.is_some() {
+        CapacityUnit::MilliwattHours
+    } else {
+        CapacityUnit::MilliampHours
+    };
+    let (current, max) = match capacity_unit {
+        CapacityUnit::MilliwattHours => (current_energy, max_energy),
+        CapacityUnit::MilliampHours => (current_cap, max_cap),
+    };
+
+    let level = match (current, max) {
+        (Some(current), Some(max)) if max > 0 => {
+            (current as f64 / max as f64 * 100.0).round().min(100.0) as u8
+        }
+        _ if !packs.is_empty() => {
+            (packs.iter().map(|p| p.level as u32).sum::<u32>() / packs.len() as u32) as u8
+        }
+        _ => 0,
+    };
+
+    let any = |state: ChargingState| packs.iter().any(|p| p.state == state);
+    let state = if any(ChargingState::Charging) {
+        ChargingState::Charging
+    } else if any(ChargingState::Discharging) {
+        ChargingState::Discharging
+    } else if !packs.is_empty() && packs.iter().all(|p| p.state == ChargingState::Full) {
+        ChargingState::Full
+    } else if any(ChargingState::NotCharging) || any(ChargingState::Full) {
+        ChargingState::NotCharging
+    } else {
+        ChargingState::Unknown
+    };
+
+    let time_remaining = combined_time_remaining(&packs, state, capacity_unit);
+
+    let power_draws: Vec<f64> = packs.iter().filter_map(|p| p.power_draw_watts).collect();
+    let power_draw = if power_draws.is_empty() {
+        None
+    } else {
+        Some(power_draws.iter().sum())
+    };
+
+    let cycle_count = packs.iter().filter_map(|p| p.cycle_count).max();
+    let technology = common_value(&packs, |p| p.technology.as_ref());
+    let rating = rating::rating_for(PackIdentity {
+        technology: technology.as_deref(),
+        ..Default::default()
+    });
+    let temperature = packs
+        .iter()
+        .filter_map(|p| p.temperature_celsius)
+        .fold(None, |acc: Option<f64>, t| {
+            Some(acc.map_or(t, |a| a.max(t)))
+        });
+
+    BatteryInfo {
+        name: COMBINED_NAME.to_string(),
+        level,
+        state,
+        time_remaining_minutes: time_remaining,
+        power_draw_watts: power_draw,
+        cycle_count,
+        max_capacity_mah: max_cap,
+        design_capacity_mah: design_cap,
+        current_capacity_mah: current_cap,
+        max_energy_mwh: max_energy,
+        design_energy_mwh: design_energy,
+        current_energy_mwh: current_energy,
+        voltage_min_design_mv: None,
+        capacity_unit,
+        temperature_celsius: temperature,
+        voltage_mv: None,
+        condition: match capacity_unit {
+            CapacityUnit::MilliwattHours => {
+                determine_condition(max_energy, design_energy, cycle_count, &rating)
+            }
+            CapacityUnit::MilliampHours => {
+                determine_condition(max_cap, design_cap, cycle_count, &rating)
+            }
+        },
+        manufacture_date: None,
+        model_name: None,
+        manufacturer: None,
+        serial_number: None,
+        technology,
+        is_present: packs.iter().any(|p| p.is_present),
+        charge_limit: common_charge_limit(&packs),
+        packs,
+    }
+}
+
+/// A string field shared by every pack, if they all report the same one.
+fn common_value(
+    packs: &[BatteryInfo],
+    field: impl Fn(&BatteryInfo) -> Option<&String>,
+) -> Option<String> {
+    let first = field(packs.first()?)?;
+    packs
+        .iter()
+        .all(|p| field(p) == Some(first))
+        .then(|| first.clone())
+}
+
+/// The charge limit shared by every pack, if they all have the same one.
+fn common_charge_limit(packs: &[BatteryInfo]) -> Option<ChargeLimit> {
+    let first = packs.first()?.charge_limit?;
+    packs
+        .iter()
+        .all(|p| p.charge_limit == Some(first))
+        .then_some(first)
+}
+
+fn combined_time_remaining(
+    packs: &[BatteryInfo],
+    state: ChargingState,
+    unit: CapacityUnit,
+) -> Option<i64> {
+    // Each active pack's rate is derived from its own estimate; the combined
+    // estimate spreads the whole remaining charge over the sum of those rates.
+    let mut rate = 0.0;
+    let mut remaining = 0.0;
+    for pack in packs {
+        let (current, max) = match unit {
+            CapacityUnit::MilliwattHours => (pack.current_energy_mwh?, pack.max_energy_mwh),
+            CapacityUnit::MilliampHours => (pack.current_capacity_mah?, pack.max_capacity_mah),
+        };
+        let current = current as f64;
+        let amount = match state {
+            ChargingState::Discharging => current,
+            ChargingState::Charging => (max? as f64 - current).max(0.0),
+            _ => return None,
+        };
+        remaining += amount;
+        if pack.state == state {
+            if let Some(mins) = pack.time_remaining_minutes.filter(|&m| m > 0) {
+                rate += amount / mins as f64;
+            }
+        }
+    }
+    if rate > 0.0 {
+        Some((remaining / rate) as i64)
+    } else {
+        None
     }
 }
 
 // ── Linux implementation ───────────────────────────────────────────────
 
-fn get_battery_info_linux() -> Result<BatteryInfo> {
-    let base = find_linux_battery_path()
-        .context("No battery found. Are you on a laptop?")?;
+/// Read every battery under a sysfs `power_supply` directory.
+pub fn read_sysfs_batteries(root: &Path) -> Result<Vec<BatteryInfo>> {
+    let paths = find_linux_battery_paths(root);
+    if paths.is_empty() {
+        anyhow::bail!("No battery found. Are you on a laptop?");
+    }
+    paths.iter().map(|p| read_linux_battery(p)).collect()
+}
+
+/// Sum `power_now` over every battery under a sysfs `power_supply` directory.
+pub fn read_sysfs_power_draw(root: &Path) -> Option<f64> {
+    find_linux_battery_paths(root)
+        .iter()
+        .filter_map(|path| read_sysfs_u32(&path.join("power_now")))
+        .map(|v| v as f64 / 1_000_000.0) // µW -> W
+        .fold(None, |acc: Option<f64>, w| Some(acc.unwrap_or(0.0) + w))
+}
+
+fn read_linux_battery(base: &Path) -> Result<BatteryInfo> {
+    let name = base
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
 
     let level = read_sysfs_u32(&base.join("capacity")).unwrap_or(0) as u8;
 
@@ -134,28 +485,35 @@ fn get_battery_info_linux() -> Result<BatteryInfo> {
         _ => ChargingState::Unknown,
     };
 
-    // Capacities: Linux reports in µAh or µWh depending on the driver
-    let energy_full = read_sysfs_u32(&base.join("energy_full"));
-    let energy_full_design = read_sysfs_u32(&base.join("energy_full_design"));
-    let energy_now = read_sysfs_u32(&base.join("energy_now"));
-    let charge_full = read_sysfs_u32(&base.join("charge_full"));
-    let charge_full_design = read_sysfs_u32(&base.join("charge_full_design"));
-    let charge_now = read_sysfs_u32(&base.join("charge_now"));
-
-    // Use charge_* (µAh) if available, else energy_* (µWh)
-    let (max_cap, design_cap, current_cap) = if charge_full.is_some() {
-        (
-            charge_full.map(|v| v / 1000),        // µAh -> mAh
-            charge_full_design.map(|v| v / 1000),
-            charge_now.map(|v| v / 1000),
-        )
+    // Capacities: drivers report charge (µAh) or energy (µWh). Keep both,
+    // deriving whichever is missing from the minimum design voltage.
+    let voltage_min_design = read_sysfs_u32(&base.join("voltage_min_design"))
+        .map(|v| v as f64 / 1000.0) // µV -> mV
+        .filter(|&v| v > 0.0);
+    let read_milli = |file: &str| read_sysfs_u32(&base.join(file)).map(|v| v / 1000);
+    let charge = [
+        read_milli("charge_full"),
+        read_milli("charge_full_design"),
+        read_milli("charge_now"),
+    ];
+    let energy = [
+        read_milli("energy_full"),
+        read_milli("energy_full_design"),
+        read_milli("energy_now"),
+    ];
+
+    let capacity_unit = if charge[0].is_none() && energy[0].is_some() {
+        CapacityUnit::MilliwattHours
     } else {
-        (
-            energy_full.map(|v| v / 1000),         // µWh -> mWh (approximate)
-            energy_full_design.map(|v| v / 1000),
-            energy_now.map(|v| v / 1000),
-        )
+        CapacityUnit::MilliampHours
     };
+    let convert = |value: Option<u32>, f: fn(u32, f64) -> u32| {
+        value.zip(voltage_min_design).map(|(v, mv)| f(v, mv))
+    };
+    let [max_cap, design_cap, current_cap] =
+        [0, 1, 2].map(|i| charge[i].or_else(|| convert(energy[i], mwh_to_mah)));
+    let [max_energy, design_energy, current_energy] =
+        [0, 1, 2].map(|i| energy[i].or_else(|| convert(charge[i], mah_to_mwh)));
 
     let power_now = read_sysfs_u32(&base.join("power_now"))
         .map(|v| v as f64 / 1_000_000.0); // µW -> W
@@ -176,15 +534,42 @@ fn get_battery_info_linux() -> Result<BatteryInfo> {
         .map(|v| v as f64 / 10.0); // tenths of degree C
 
     // Time remaining estimation
-    let time_remaining = estimate_time_remaining_linux(
-        &state, energy_now, energy_full, power_now,
-    );
-
-    let condition = determine_condition(max_cap, design_cap, cycle_count);
+    let time_remaining =
+        estimate_time_remaining_linux(&state, current_energy, max_energy, power_draw).or_else(
+            || {
+                estimate_time_remaining_linux(
+                    &state,
+                    current_cap,
+                    max_cap,
+                    current_now.map(|c| c as f64 / 1_000_000.0), // µA -> A
+                )
+            },
+        );
 
     let manufacture_date = read_sysfs_string(&base.join("manufacture_date")).ok();
+    let model_name = read_sysfs_identity(&base.join("model_name"));
+    let manufacturer = read_sysfs_identity(&base.join("manufacturer"));
+    let serial_number = read_sysfs_identity(&base.join("serial_number"));
+    let technology = read_sysfs_identity(&base.join("technology"));
+
+    let rating = rating::rating_for(PackIdentity {
+        technology: technology.as_deref(),
+        manufacturer: manufacturer.as_deref(),
+        model: model_name.as_deref(),
+    });
+    let condition = match capacity_unit {
+        CapacityUnit::MilliwattHours => {
+            determine_condition(max_energy, design_energy, cycle_count, &rating)
+        }
+        CapacityUnit::MilliampHours => {
+            determine_condition(max_cap, design_cap, cycle_count, &rating)
+        }
+    };
+
+    let charge_limit = read_sysfs_charge_limit(base);
 
     Ok(BatteryInfo {
+        name,
         level,
         state,
         time_remaining_minutes: time_remaining,
@@ -193,31 +578,70 @@ fn get_battery_info_linux() -> Result<BatteryInfo> {
         max_capacity_mah: max_cap,
         design_capacity_mah: design_cap,
         current_capacity_mah: current_cap,
+        max_energy_mwh: max_energy,
+        design_energy_mwh: design_energy,
+        current_energy_mwh: current_energy,
+        voltage_min_design_mv: voltage_min_design,
+        capacity_unit,
         temperature_celsius: temperature,
         voltage_mv: voltage_now,
         condition,
         manufacture_date,
+        model_name,
+        manufacturer,
+        serial_number,
+        technology,
         is_present: true,
+        charge_limit,
+        packs: Vec::new(),
     })
 }
 
-fn find_linux_battery_path() -> Option<std::path::PathBuf> {
-    let power_supply = Path::new("/sys/class/power_supply");
-    if !power_supply.exists() {
+/// Start threshold attribute names, newest kernel interface first.
+pub const START_THRESHOLD_FILES: &[&str] =
+    &["charge_control_start_threshold", "charge_start_threshold"];
+/// Stop threshold attribute names, newest kernel interface first.
+pub const END_THRESHOLD_FILES: &[&str] = &["charge_control_end_threshold", "charge_stop_threshold"];
+
+/// First of `names` that exists under `dir`.
+pub fn find_sysfs_attr(dir: &Path, names: &[&str]) -> Option<std::path::PathBuf> {
+    names.iter().map(|n| dir.join(n)).find(|p| p.exists())
+}
+
+/// Read the charge thresholds of a battery directory; `None` if it has none.
+pub fn read_sysfs_charge_limit(base: &Path) -> Option<ChargeLimit> {
+    let start_file = find_sysfs_attr(base, START_THRESHOLD_FILES);
+    let end_file = find_sysfs_attr(base, END_THRESHOLD_FILES);
+    if start_file.is_none() && end_file.is_none() {
         return None;
     }
+    let read = |file: Option<std::path::PathBuf>| {
+        file.and_then(|f| read_sysfs_u32(&f))
+            .map(|v| v.min(100) as u8)
+    };
+    Some(ChargeLimit {
+        start: read(start_file),
+        end: read(end_file),
+    })
+}
 
-    let entries = std::fs::read_dir(power_supply).ok()?;
-    for entry in entries.flatten() {
-        let path = entry.path();
-        let type_file = path.join("type");
-        if let Ok(ptype) = std::fs::read_to_string(&type_file) {
-            if ptype.trim().eq_ignore_ascii_case("battery") {
-                return Some(path);
-            }
-        }
-    }
-    None
+/// All `power_supply` entries of type `Battery`, sorted by name (BAT0, BAT1, ...).
+fn find_linux_battery_paths(power_supply: &Path) -> Vec<std::path::PathBuf> {
+    let Ok(entries) = std::fs::read_dir(power_supply) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<std::path::PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            std::fs::read_to_string(path.join("type"))
+                .map(|ptype| ptype.trim().eq_ignore_ascii_case("battery"))
+                .unwrap_or(false)
+        })
+        .collect();
+    paths.sort();
+    paths
 }
 
 fn read_sysfs_string(path: &Path) -> Result<String> {
@@ -226,31 +650,41 @@ fn read_sysfs_string(path: &Path) -> Result<String> {
         .with_context(|| format!("Failed to read {}", path.display()))
 }
 
+/// An identity string, treating blanks and the `Unknown` placeholder some
+/// drivers report as missing.
+fn read_sysfs_identity(path: &Path) -> Option<String> {
+    read_sysfs_string(path)
+        .ok()
+        .filter(|s| !s.is_empty() && !s.eq_ignore_ascii_case("unknown"))
+}
+
 fn read_sysfs_u32(path: &Path) -> Option<u32> {
     std::fs::read_to_string(path)
         .ok()
         .and_then(|s| s.trim().parse::<u32>().ok())
 }
 
+/// Minutes until empty (or full) from a capacity and a rate in matching units:
+/// mWh with W, or mAh with A.
 fn estimate_time_remaining_linux(
     state: &ChargingState,
-    energy_now: Option<u32>,
-    energy_full: Option<u32>,
-    power_now: Option<f64>,
+    now: Option<u32>,
+    full: Option<u32>,
+    rate: Option<f64>,
 ) -> Option<i64> {
-    let power = power_now.filter(|&p| p > 0.1)?;
+    let rate = rate.filter(|&r| r > 0.1)?;
 
     match state {
         ChargingState::Discharging => {
-            let energy = energy_now? as f64 / 1_000_000.0; // µWh -> Wh
-            Some((energy / power * 60.0) as i64)
+            let amount = now? as f64 / 1000.0; // mWh -> Wh, mAh -> Ah
+            Some((amount / rate * 60.0) as i64)
         }
         ChargingState::Charging => {
-            let energy = energy_now? as f64 / 1_000_000.0;
-            let full = energy_full? as f64 / 1_000_000.0;
-            let remaining = full - energy;
+            let amount = now? as f64 / 1000.0;
+            let full = full? as f64 / 1000.0;
+            let remaining = full - amount;
             if remaining > 0.0 {
-                Some((remaining / power * 60.0) as i64)
+                Some((remaining / rate * 60.0) as i64)
             } else {
                 Some(0)
             }
@@ -261,7 +695,8 @@ fn estimate_time_remaining_linux(
 
 // ── macOS implementation ───────────────────────────────────────────────
 
-fn get_battery_info_macos() -> Result<BatteryInfo> {
+/// Read the internal battery via `pmset` and `system_profiler`.
+pub fn read_macos_battery() -> Result<BatteryInfo> {
     let pmset_output = Command::new("pmset")
         .args(["-g", "batt"])
         .output()
@@ -344,8 +779,12 @@ fn parse_macos_battery(pmset: &str, profiler: &str) -> Result<BatteryInfo> {
     }));
 
     let manufacture_date = extract_profiler_value(profiler, "Manufacture Date");
+    let serial_number = extract_profiler_value(profiler, "Serial Number");
+    let model_name = extract_profiler_value(profiler, "Device Name");
+    let manufacturer = extract_profiler_value(profiler, "Manufacturer");
 
     Ok(BatteryInfo {
+        name: "InternalBattery-0".to_string(),
         level,
         state,
         time_remaining_minutes,
@@ -354,11 +793,22 @@ fn parse_macos_battery(pmset: &str, profiler: &str) -> Result<BatteryInfo> {
         max_capacity_mah,
         design_capacity_mah,
         current_capacity_mah: None,
+        max_energy_mwh: None,
+        design_energy_mwh: None,
+        current_energy_mwh: None,
+        voltage_min_design_mv: None,
+        capacity_unit: CapacityUnit::MilliampHours,
         temperature_celsius: None,
         voltage_mv: None,
         condition,
         manufacture_date,
+        model_name,
+        manufacturer,
+        serial_number,
+        technology: None,
         is_present: true,
+        charge_limit: None,
+        packs: Vec::new(),
     })
 }
 
@@ -405,32 +855,24 @@ fn extract_profiler_value(profiler: &str, key: &str) -> Option<String> {
     None
 }
 
-fn determine_condition(
+/// Condition from health against the pack's end-of-life capacity, or
+/// failing that from cycles against its rated cycle life.
+pub fn determine_condition(
     max_cap: Option<u32>,
     design_cap: Option<u32>,
     cycle_count: Option<u32>,
+    rating: &CycleRating,
 ) -> BatteryCondition {
     if let (Some(max), Some(design)) = (max_cap, design_cap) {
         if design == 0 {
             return BatteryCondition::Unknown;
         }
         let health = max as f64 / design as f64 * 100.0;
-        if health >= 80.0 {
-            return BatteryCondition::Normal;
-        } else if health >= 60.0 {
-            return BatteryCondition::ServiceRecommended;
-        } else {
-            return BatteryCondition::Replace;
-        }
+        return rating.condition_for_health(health);
     }
-    if let Some(cycles) = cycle_count {
-        if cycles < 800 {
-            return BatteryCondition::Normal;
-        } else if cycles < 1000 {
-            return BatteryCondition::ServiceRecommended;
-        } else {
-            return BatteryCondition::Replace;
-        }
+    // A "not supported" sentinel says nothing about wear
+    if let Some(cycles) = cycle_count.filter(|&c| c < crate::cycles::FIRMWARE_SENTINEL) {
+        return rating.condition_for_cycles(cycles);
     }
     BatteryCondition::Unknown
 }
@@ -461,6 +903,7 @@ mod tests {
     #[test]
     fn test_battery_info_health() {
         let info = BatteryInfo {
+            name: "BAT0".to_string(),
             level: 87,
             state: ChargingState::Charging,
             time_remaining_minutes: Some(83),
@@ -474,6 +917,7 @@ mod tests {
             condition: BatteryCondition::Normal,
             manufacture_date: Some("2024-03-15".to_string()),
             is_present: true,
+            ..Default::default()
         };
 
         let health = info.health_percent().unwrap();
@@ -485,18 +929,41 @@ mod tests {
 
     #[test]
     fn test_determine_condition() {
+        let rating = CycleRating::default();
         assert_eq!(
-            determine_condition(Some(4500), Some(5000), None),
+            determine_condition(Some(4500), Some(5000), None, &rating),
             BatteryCondition::Normal
         );
         assert_eq!(
-            determine_condition(Some(3500), Some(5000), None),
+            determine_condition(Some(3500), Some(5000), None, &rating),
             BatteryCondition::ServiceRecommended
         );
         assert_eq!(
-            determine_condition(Some(2000), Some(5000), None),
+            determine_condition(Some(2000), Some(5000), None, &rating),
             BatteryCondition::Replace
         );
+        assert_eq!(
+            determine_condition(None, None, Some(900), &rating),
+            BatteryCondition::ServiceRecommended
+        );
+        assert_eq!(
+            determine_condition(None, None, Some(0xFFFF), &rating),
+            BatteryCondition::Unknown
+        );
+
+        // A 500-cycle Li-ion pack is worn out where the default is not
+        let li_ion = BatteryInfo {
+            technology: Some("Li-ion".to_string()),
+            cycle_count: Some(450),
+            ..Default::default()
+        };
+        let rating = li_ion.cycle_rating();
+        assert_eq!(rating.rated_cycles, 500);
+        assert_eq!(li_ion.estimated_remaining_cycles(), Some(50));
+        assert_eq!(
+            determine_condition(None, None, Some(450), &rating),
+            BatteryCondition::ServiceRecommended
+        );
     }
 
     #[test]
@@ -515,4 +982,67 @@ mod tests {
         assert_eq!(info.time_remaining_minutes, Some(83));
         assert_eq!(info.cycle_count, Some(47));
     }
+
+    fn make_pack(name: &str, level: u8, state: ChargingState, minutes: Option<i64>) -> BatteryInfo {
+        BatteryInfo {
+            name: name.to_string(),
+            level,
+            state,
+            time_remaining_minutes: minutes,
+            power_draw_watts: Some(10.0),
+            max_capacity_mah: Some(2000),
+            design_capacity_mah: Some(2000),
+            current_capacity_mah: Some(2000 * level as u32 / 100),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_combine_batteries() {
+        let combined = combine_batteries(vec![
+            make_pack("BAT0", 50, ChargingState::Discharging, Some(60)),
+            make_pack("BAT1", 100, ChargingState::NotCharging, None),
+        ]);
+        assert_eq!(combined.name, COMBINED_NAME);
+        assert_eq!(combined.packs.len(), 2);
+        assert_eq!(combined.level, 75);
+        assert_eq!(combined.state, ChargingState::Discharging);
+        assert_eq!(combined.max_capacity_mah, Some(4000));
+        assert_eq!(combined.power_draw_watts, Some(20.0));
+        // BAT0 drains 1000 mAh in 60 min; 3000 mAh in total lasts 180 min.
+        assert_eq!(combined.time_remaining_minutes, Some(180));
+    }
+
+    #[test]
+    fn test_combine_single_battery_is_unchanged() {
+        let combined = combine_batteries(vec![make_pack(
+            "BAT0",
+            42,
+            ChargingState::Discharging,
+            Some(30),
+        )]);
+        assert_eq!(combined.name, "BAT0");
+        assert!(combined.packs.is_empty());
+    }
+
+    #[test]
+    fn test_select_battery() {
+        let combined = combine_batteries(vec![
+            make_pack("BAT0", 50, ChargingState::Discharging, Some(60)),
+            make_pack("BAT1", 100, ChargingState::NotCharging, None),
+        ]);
+        let bat1 = select_battery(combined.clone(), "bat1").unwrap();
+        assert_eq!(bat1.name, "BAT1");
+        assert_eq!(bat1.level, 100);
+        assert_eq!(
+            select_battery(combined.clone(), "combined")
+                .unwrap()
+                .packs
+                .len(),
+            2
+        );
+
+        let err = select_battery(combined, "BAT2").unwrap_err().to_string();
+        assert!(err.contains("BAT0, BAT1"));
+    }
 }
@@ -0,0 +1,340 @@
+use crate::backend::BatteryBackend;
+use crate::battery::get_selected_battery;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Charge thresholds: charging starts below `start` and stops at `end` percent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChargeLimit {
+    pub start: Option<u8>,
+    pub end: Option<u8>,
+}
+
+impl ChargeLimit {
+    /// Whether the limit keeps the battery below a full charge.
+    pub fn is_active(&self) -> bool {
+        self.end.is_some_and(|end| end < 100)
+    }
+
+    /// Replace the thresholds given in `update`, keeping the others.
+    pub fn merged(&self, update: &ChargeLimit) -> ChargeLimit {
+        ChargeLimit {
+            start: update.start.or(self.start),
+            end: update.end.or(self.end),
+        }
+    }
+
+    /// Check that both thresholds are percentages and that start < end.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(end) = self.end {
+            if !(1..=100).contains(&end) {
+                anyhow::bail!("Stop threshold must be between 1 and 100 (got {})", end);
+            }
+        }
+        if let Some(start) = self.start {
+            if start > 99 {
+                anyhow::bail!("Start threshold must be between 0 and 99 (got {})", start);
+            }
+        }
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            if start >= end {
+                anyhow::bail!(
+                    "Start threshold ({}%) must be below the stop threshold ({}%)",
+                    start,
+                    end
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ChargeLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => write!(f, "start {}%, stop {}%", start, end),
+            (None, Some(end)) => write!(f, "stop {}%", end),
+            (Some(start), None) => write!(f, "start {}%", start),
+            (None, None) => write!(f, "none"),
+        }
+    }
+}
+
+/// Charge limit state of one pack, as reported by `batteryctl limit`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitStatus {
+    pub battery: String,
+    pub supported: bool,
+    pub limit: Option<ChargeLimit>,
+    /// Writes performed (or, with `--dry-run`, planned).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<String>,
+}
+
+/// Error for a write the current user is not allowed to make.
+pub fn permission_error(path: &std::path::Path) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Permission denied writing {}.\n\
+         Changing charge settings requires root; re-run with sudo, or add a udev rule\n\
+         that makes the file writable for your user.",
+        path.display()
+    )
+}
+
+/// Charge behaviour modes (`charge_behaviour` in sysfs).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChargeBehaviour {
+    pub active: String,
+    pub available: Vec<String>,
+}
+
+impl ChargeBehaviour {
+    /// Modes the pack can be switched to. Some drivers list only the active
+    /// mode, so every mode the kernel defines is offered there.
+    pub fn modes(&self) -> Vec<&str> {
+        if self.available.len() > 1 {
+            self.available.iter().map(String::as_str).collect()
+        } else {
+            CHARGE_MODES.to_vec()
+        }
+    }
+
+    pub fn supports(&self, mode: &str) -> bool {
+        self.modes().contains(&mode)
+    }
+}
+
+/// Modes the kernel defines for `charge_behaviour`.
+pub const CHARGE_MODES: &[&str] = &["auto", "inhibit-charge", "force-discharge"];
+
+/// Parse the sysfs value, where the active mode is bracketed:
+/// `[auto] inhibit-charge force-discharge`.
+pub fn parse_charge_behaviour(s: &str) -> Option<ChargeBehaviour> {
+    let mut active = None;
+    let mut available = Vec::new();
+    for token in s.split_whitespace() {
+        let mode = token.trim_start_matches('[').trim_end_matches(']');
+        if mode.is_empty() {
+            continue;
+        }
+        if token.starts_with('[') && token.ends_with(']') {
+            active = Some(mode.to_string());
+        }
+        available.push(mode.to_string());
+    }
+    // A single unbracketed value is the active mode
+    let active = active.or_else(|| (available.len() == 1).then(|| available[0].clone()))?;
+    Some(ChargeBehaviour { active, available })
+}
+
+/// Charge behaviour state of one pack, as reported by `batteryctl charge`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BehaviourStatus {
+    pub battery: String,
+    pub behaviour: Option<ChargeBehaviour>,
+    /// Writes performed (or, with `--dry-run`, planned).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<String>,
+}
+
+/// When to switch a temporary charge behaviour back.
+#[derive(Debug, Clone, Default)]
+pub struct RevertConfig {
+    /// Battery view whose level is watched; `None` for all packs combined.
+    pub battery: Option<String>,
+    /// Revert after this long.
+    pub after: Option<Duration>,
+    /// Revert once the level reaches this percentage.
+    pub until_level: Option<u8>,
+    /// Mode that was switched to; decides which way the level must move.
+    pub mode: String,
+    pub check_interval: Duration,
+}
+
+impl RevertConfig {
+    pub fn is_set(&self) -> bool {
+        self.after.is_some() || self.until_level.is_some()
+    }
+
+    /// Whether `level` has reached the target for the current mode: modes
+    /// that stop charging wait for the level to fall, `auto` for it to rise.
+    pub fn level_reached(&self, level: u8) -> bool {
+        match self.until_level {
+            Some(target) if self.mode == "auto" => level >= target,
+            Some(target) => level <= target,
+            None => false,
+        }
+    }
+}
+
+/// Wait until a revert condition is met, then restore each pack's previous mode.
+///
+/// Works like the alert monitor: the battery is polled every
+/// `check_interval`, and clearing `running` (Ctrl+C) reverts immediately.
+pub fn run_revert_loop(
+    backend: &dyn BatteryBackend,
+    config: &RevertConfig,
+    previous: &[(String, String)],
+    running: Arc<AtomicBool>,
+) -> Result<()> {
+    let started = Instant::now();
+
+    if let Some(after) = config.after {
+        let after = chrono::Duration::from_std(after).unwrap_or(chrono::Duration::MAX);
+        eprintln!(
+            "  Reverting after {}",
+            crate::history::describe_duration(&after)
+        );
+    }
+    if let Some(level) = config.until_level {
+        eprintln!("  Reverting when battery reaches {}%", level);
+    }
+
+    while running.load(Ordering::Relaxed) {
+        if config.after.is_some_and(|after| started.elapsed() >= after) {
+            eprintln!("Time limit reached.");
+            break;
+        }
+        match get_selected_battery(backend, config.battery.as_deref()) {
+            Ok(info) if config.level_reached(info.level) => {
+                eprintln!("Battery reached {}%.", info.level);
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Warning: Could not read battery info: {}", e),
+        }
+
+        // Sleep in small increments so we can check the running flag
+        let sleep_ms = config.check_interval.as_millis() as u64;
+        let step = 500u64;
+        let mut elapsed = 0u64;
+        while elapsed < sleep_ms && running.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(step.min(sleep_ms - elapsed)));
+            elapsed += step;
+        }
+    }
+
+    restore_behaviours(backend, previous)
+}
+
+/// Switch each pack back to its recorded mode.
+///
+/// A pack that fails to switch back must not keep the others from it, so
+/// every pack is tried and the failures are reported together.
+pub fn restore_behaviours(
+    backend: &dyn BatteryBackend,
+    previous: &[(String, String)],
+) -> Result<()> {
+    let mut failed = Vec::new();
+    for (battery, mode) in previous {
+        match backend.set_charge_behaviour(battery, mode, false) {
+            Ok(actions) => {
+                for action in actions {
+                    eprintln!("Reverted {}: {}", battery, action);
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: Could not revert {} to {}: {:#}", battery, mode, e);
+                failed.push(format!("{} ({})", battery, mode));
+            }
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "Could not revert the charge behaviour of {}; set it back by hand",
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_charge_limit() {
+        let limit = |start, end| ChargeLimit { start, end };
+        assert!(limit(Some(75), Some(80)).validate().is_ok());
+        assert!(limit(None, Some(100)).validate().is_ok());
+        assert!(limit(Some(80), Some(80)).validate().is_err());
+        assert!(limit(None, Some(0)).validate().is_err());
+        assert!(limit(None, Some(101)).validate().is_err());
+        assert!(limit(Some(100), None).validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_charge_behaviour() {
+        let b = parse_charge_behaviour("[auto] inhibit-charge force-discharge\n").unwrap();
+        assert_eq!(b.active, "auto");
+        assert_eq!(
+            b.available,
+            vec!["auto", "inhibit-charge", "force-discharge"]
+        );
+        assert!(b.supports("force-discharge"));
+        assert!(!b.supports("turbo"));
+
+        // Only the active mode is listed: every kernel mode is offered
+        let b = parse_charge_behaviour("[auto]").unwrap();
+        assert!(b.supports("inhibit-charge"));
+        assert!(!b.supports("turbo"));
+
+        let b = parse_charge_behaviour("auto [inhibit-charge]").unwrap();
+        assert_eq!(b.active, "inhibit-charge");
+
+        let b = parse_charge_behaviour("force-discharge").unwrap();
+        assert_eq!(b.active, "force-discharge");
+
+        assert!(parse_charge_behaviour("").is_none());
+        assert!(parse_charge_behaviour("auto inhibit-charge").is_none());
+    }
+
+    #[test]
+    fn test_revert_level_direction() {
+        let mut config = RevertConfig {
+            until_level: Some(50),
+            mode: "force-discharge".to_string(),
+            ..Default::default()
+        };
+        assert!(!config.level_reached(60));
+        assert!(config.level_reached(50));
+
+        config.mode = "auto".to_string();
+        assert!(!config.level_reached(40));
+        assert!(config.level_reached(55));
+    }
+
+    #[test]
+    fn test_revert_tries_every_pack() {
+        let backend = crate::backend::FixtureBackend::default();
+        let previous = vec![
+            ("BAT0".to_string(), "auto".to_string()),
+            ("BAT1".to_string(), "auto".to_string()),
+        ];
+        let running = Arc::new(AtomicBool::new(false));
+        let err = run_revert_loop(&backend, &RevertConfig::default(), &previous, running)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("BAT0 (auto), BAT1 (auto)"), "{}", err);
+    }
+
+    #[test]
+    fn test_merged_and_active() {
+        let current = ChargeLimit {
+            start: Some(0),
+            end: Some(100),
+        };
+        assert!(!current.is_active());
+        let merged = current.merged(&ChargeLimit {
+            start: None,
+            end: Some(80),
+        });
+        assert_eq!(merged.start, Some(0));
+        assert_eq!(merged.end, Some(80));
+        assert!(merged.is_active());
+        assert_eq!(merged.to_string(), "start 0%, stop 80%");
+    }
+}
@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::rating::RatingRule;
+
+/// User settings, read from `batteryctl/config.json` in the config directory.
+///
+/// Every field is optional in the file; missing ones take their defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub retention: RetentionPolicy,
+    /// Cycle ratings tried before the built-in table, in order.
+    pub cycle_ratings: Vec<RatingRule>,
+}
+
+/// How long each granularity of history is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Raw snapshots, in days.
+    pub raw_days: u32,
+    /// Hourly rollups, in days.
+    pub hourly_days: u32,
+    /// Daily rollups, in days; `None` keeps them forever.
+    pub daily_days: Option<u32>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            raw_days: 30,
+            hourly_days: 365,
+            daily_days: None,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Rollups are rebuilt from the last day of raw rows, so raw data must
+    /// outlive that.
+    pub const MIN_RAW_DAYS: u32 = 2;
+
+    pub fn validate(&self) -> Result<()> {
+        if self.raw_days < Self::MIN_RAW_DAYS {
+            anyhow::bail!(
+                "retention.raw_days must be at least {} (got {})",
+                Self::MIN_RAW_DAYS,
+                self.raw_days
+            );
+        }
+        if self.hourly_days < self.raw_days {
+            anyhow::bail!(
+                "retention.hourly_days ({}) must not be shorter than retention.raw_days ({})",
+                self.hourly_days,
+                self.raw_days
+            );
+        }
+        if let Some(daily) = self.daily_days {
+            if daily < self.hourly_days {
+                anyhow::bail!(
+                    "retention.daily_days ({}) must not be shorter than retention.hourly_days ({})",
+                    daily,
+                    self.hourly_days
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Load the config at `path`, or at the default location when `None`.
+/// A missing file gives the defaults.
+pub fn load(path: Option<&Path>) -> Result<Config> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match get_config_path() {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        },
+    };
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config {}", path.display()))?;
+    let config: Config = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid config {}", path.display()))?;
+    config
+        .retention
+        .validate()
+        .and_then(|()| {
+            config
+                .cycle_ratings
+                .iter()
+                .try_for_each(RatingRule::validate)
+        })
+        .with_context(|| format!("Invalid config {}", path.display()))?;
+    Ok(config)
+}
+
+/// `batteryctl/config.json` in the XDG config directory.
+pub fn get_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("batteryctl").join("config.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_partial_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.json");
+        std::fs::write(&path, r#"{"retention": {"raw_days": 7}}"#).unwrap();
+
+        let config = load(Some(&path)).unwrap();
+        assert_eq!(config.retention.raw_days, 7);
+        assert_eq!(config.retention.hourly_days, 365);
+        assert_eq!(config.retention.daily_days, None);
+
+        assert_eq!(
+            load(Some(&tmp.path().join("missing.json"))).unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn test_invalid_retention_is_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.json");
+        std::fs::write(&path, r#"{"retention": {"raw_days": 1}}"#).unwrap();
+        let err = format!("{:#}", load(Some(&path)).unwrap_err());
+        assert!(err.contains("raw_days must be at least 2"), "{}", err);
+
+        let policy = RetentionPolicy {
+            raw_days: 30,
+            hourly_days: 10,
+            daily_days: None,
+        };
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn test_cycle_ratings() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.json");
+        std::fs::write(
+            &path,
+            r#"{"cycle_ratings": [{"model": "FRANGWA", "rated_cycles": 1000}]}"#,
+        )
+        .unwrap();
+        let config = load(Some(&path)).unwrap();
+        assert_eq!(config.cycle_ratings[0].rated_cycles, 1000);
+        assert_eq!(config.cycle_ratings[0].end_of_life_percent, 80.0);
+
+        std::fs::write(
+            &path,
+            r#"{"cycle_ratings": [{"technology": "LFP", "rated_cycles": 0}]}"#,
+        )
+        .unwrap();
+        let err = format!("{:#}", load(Some(&path)).unwrap_err());
+        assert!(
+            err.contains("rated_cycles must be greater than 0"),
+            "{}",
+            err
+        );
+    }
+}
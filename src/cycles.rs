@@ -0,0 +1,209 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::history::BatterySnapshot;
+use crate::rollup::stored_energy;
+use crate::sleep::is_sleep_gap;
+
+/// Firmware that does not track cycles often reports 0xFFFF instead.
+pub const FIRMWARE_SENTINEL: u32 = 0xFFFF;
+
+/// Once this many cycles are counted, a firmware count below half of it is
+/// taken to be stuck rather than behind.
+const STUCK_AFTER_CYCLES: f64 = 10.0;
+
+/// Where a reported cycle count comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CycleSource {
+    /// The battery's own `cycle_count`.
+    Firmware,
+    /// batteryctl's count of the energy discharged (see `CycleCounter`).
+    Throughput,
+}
+
+/// Energy discharged from one pack since batteryctl started counting,
+/// kept in the history database across runs.
+///
+/// One cycle is a full charge's worth of discharge, measured against the
+/// full-charge capacity at the time, however many partial discharges it
+/// takes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CycleCounter {
+    pub battery: String,
+    /// When counting started: the pack's install, or its first reading.
+    pub since: DateTime<Utc>,
+    /// The last reading counted.
+    pub updated: DateTime<Utc>,
+    /// Energy discharged, in mWh, where the readings allow it.
+    pub discharged_mwh: f64,
+    pub cycles: f64,
+}
+
+impl CycleCounter {
+    pub fn new(battery: &str, since: DateTime<Utc>) -> Self {
+        Self {
+            battery: battery.to_string(),
+            since,
+            updated: since,
+            discharged_mwh: 0.0,
+            cycles: 0.0,
+        }
+    }
+
+    /// Count the discharge between two consecutive readings of the pack.
+    pub fn add(&mut self, before: &BatterySnapshot, after: &BatterySnapshot) {
+        if let Some((energy_mwh, cycles)) = discharge_between(before, after) {
+            self.discharged_mwh += energy_mwh.unwrap_or(0.0);
+            self.cycles += cycles;
+        }
+        self.updated = self.updated.max(after.timestamp);
+    }
+}
+
+/// Energy (mWh, if known) and fraction of a full charge discharged between
+/// two readings, the first of them on battery.
+///
+/// The stored energy at either end gives both, and covers suspend gaps and
+/// the drain before a charger was plugged in. Without it, the power drawn
+/// over the interval stands in for the energy, and the level drop for the
+/// fraction.
+fn discharge_between(
+    before: &BatterySnapshot,
+    after: &BatterySnapshot,
+) -> Option<(Option<f64>, f64)> {
+    if before.is_charging {
+        return None;
+    }
+    let hours = after
+        .timestamp
+        .signed_duration_since(before.timestamp)
+        .num_seconds() as f64
+        / 3600.0;
+    if hours <= 0.0 {
+        return None;
+    }
+
+    let energy = match (stored_energy(before), stored_energy(after)) {
+        (Some(from), Some(to)) => Some((from - to).max(0.0)),
+        // Power says nothing about time asleep or on the charger
+        _ if is_sleep_gap(before, after) || after.is_charging => None,
+        _ => before.power_draw.map(|watts| watts * hours * 1000.0),
+    };
+    let full = after
+        .max_energy
+        .or(before.max_energy)
+        .filter(|&f| f > 0)
+        .map(|f| f as f64);
+    let cycles = match (energy, full) {
+        (Some(energy), Some(full)) => energy / full,
+        _ => (before.level as f64 - after.level as f64).max(0.0) / 100.0,
+    };
+    (cycles > 0.0).then_some((energy, cycles))
+}
+
+/// The cycle count to report: the firmware's, unless it is missing or
+/// obviously wrong, in which case the counter's.
+///
+/// Firmware counts are wrong when they are a "not supported" sentinel, or
+/// stay at zero (or far below the counter, which only ever undercounts)
+/// after whole cycles have been counted.
+pub fn effective_cycles(
+    firmware: Option<u32>,
+    counter: Option<&CycleCounter>,
+) -> Option<(u32, CycleSource)> {
+    let counted = counter.map_or(0.0, |c| c.cycles);
+    let bogus = match firmware {
+        None => true,
+        Some(cycles) if cycles >= FIRMWARE_SENTINEL => true,
+        Some(0) => counted >= 1.0,
+        Some(cycles) => counted >= STUCK_AFTER_CYCLES && (cycles as f64) < counted / 2.0,
+    };
+    match (firmware, counter) {
+        (Some(cycles), _) if !bogus => Some((cycles, CycleSource::Firmware)),
+        (_, Some(counter)) => Some((counter.cycles.round() as u32, CycleSource::Throughput)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(minute: i64, level: u8, charging: bool) -> BatterySnapshot {
+        BatterySnapshot {
+            timestamp: DateTime::from_timestamp(1_700_000_000 + minute * 60, 0).unwrap(),
+            battery: Some("BAT0".to_string()),
+            level,
+            is_charging: charging,
+            power_draw: Some(10.0),
+            max_energy: Some(50000),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_counter_integrates_discharge() {
+        let start = reading(0, 0, false).timestamp;
+        let mut counter = CycleCounter::new("BAT0", start);
+
+        // 80% to 30% on battery, then a night asleep losing 10% more
+        counter.add(&reading(0, 80, false), &reading(60, 55, false));
+        counter.add(&reading(60, 55, false), &reading(120, 30, false));
+        counter.add(&reading(120, 30, false), &reading(720, 20, false));
+        // Charging back to full counts for nothing
+        counter.add(&reading(720, 20, false), &reading(780, 100, true));
+        counter.add(&reading(780, 100, true), &reading(840, 100, true));
+        counter.add(&reading(840, 100, false), &reading(900, 40, false));
+
+        assert!((counter.cycles - 1.2).abs() < 1e-9);
+        assert!((counter.discharged_mwh - 60000.0).abs() < 1e-6);
+        assert_eq!(counter.updated, reading(900, 40, false).timestamp);
+
+        // Without capacity, the power drawn stands in for the energy
+        let mut before = reading(0, 80, false);
+        let mut after = reading(30, 75, false);
+        before.max_energy = None;
+        after.max_energy = None;
+        let mut counter = CycleCounter::new("BAT0", start);
+        counter.add(&before, &after);
+        assert_eq!(counter.discharged_mwh, 5000.0);
+        assert!((counter.cycles - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_effective_cycles() {
+        let mut counter = CycleCounter::new("BAT0", Utc::now());
+        counter.cycles = 41.6;
+
+        assert_eq!(
+            effective_cycles(Some(300), Some(&counter)),
+            Some((300, CycleSource::Firmware))
+        );
+        assert_eq!(
+            effective_cycles(None, Some(&counter)),
+            Some((42, CycleSource::Throughput))
+        );
+        assert_eq!(
+            effective_cycles(Some(0), Some(&counter)),
+            Some((42, CycleSource::Throughput))
+        );
+        assert_eq!(
+            effective_cycles(Some(65535), Some(&counter)),
+            Some((42, CycleSource::Throughput))
+        );
+        assert_eq!(
+            effective_cycles(Some(3), Some(&counter)),
+            Some((42, CycleSource::Throughput))
+        );
+        assert_eq!(effective_cycles(Some(65535), None), None);
+
+        // A new pack really is at zero
+        counter.cycles = 0.4;
+        assert_eq!(
+            effective_cycles(Some(0), Some(&counter)),
+            Some((0, CycleSource::Firmware))
+        );
+        assert_eq!(effective_cycles(None, None), None);
+    }
+}
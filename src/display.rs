@@ -1,9 +1,20 @@
-use crate::battery::{BatteryCondition, BatteryInfo, ChargingState};
-use crate::health::HealthReport;
-use crate::history::{BatterySnapshot, HistorySummary};
+use crate::battery::{BatteryCondition, BatteryInfo, CapacityUnit, ChargingState};
+use crate::charge::{BehaviourStatus, LimitStatus};
+use crate::cycles::CycleSource;
+use crate::forecast::{self, DegradationForecast};
+use crate::health::{CapacityDataPoint, HealthReport};
+use crate::history::{
+    BatteryEvent, BatterySnapshot, HistoryStats, HistorySummary, ImportCounts, IntegrityReport,
+    PruneCounts, TimeRange, VacuumReport,
+};
 use crate::optimize::OptimizationReport;
 use crate::power::PowerReport;
+use crate::rollup::Granularity;
+use crate::sessions::{Session, SessionKind};
+use crate::sleep::{SleepReport, SleepSource, ABNORMAL_DRAIN_PER_HOUR};
+use crate::stress::StressReport;
 use colored::Colorize;
+use chrono::{DateTime, Local, Utc};
 
 // ── Battery Status Display ─────────────────────────────────────────────
 
@@ -70,6 +81,18 @@ pub fn print_status(info: &BatteryInfo, detailed: bool) {
         println!("│ {:<20} {:>34} │", "Power Draw:", power_str);
     }
 
+    // Per-pack breakdown on multi-battery machines
+    if !info.packs.is_empty() {
+        println!("│ {:>55} │", "");
+        for pack in &info.packs {
+            println!(
+                "│ {:<20} {:>34} │",
+                format!("{}:", pack.name),
+                format!("{}% ({})", pack.level, pack.state)
+            );
+        }
+    }
+
     if detailed {
         println!("│ {:>55} │", "");
 
@@ -83,24 +106,45 @@ pub fn print_status(info: &BatteryInfo, detailed: bool) {
         };
         println!("│ {:<20} {:>34} │", "Health:", condition_str);
 
+        // Identity
+        if let Some(model) = format_model(info.manufacturer.as_deref(), info.model_name.as_deref())
+        {
+            println!("│ {:<20} {:>34} │", "Model:", model);
+        }
+        if let Some(ref serial) = info.serial_number {
+            println!("│ {:<20} {:>34} │", "Serial Number:", serial);
+        }
+        if let Some(ref technology) = info.technology {
+            println!("│ {:<20} {:>34} │", "Technology:", technology);
+        }
+
         // Max capacity
         if let Some(health) = info.health_percent() {
-            let cap_str = match (info.max_capacity_mah, info.design_capacity_mah) {
+            let cap_str = match info.native_capacity() {
                 (Some(max), Some(design)) => {
-                    format!("{:.0}% ({} / {} mAh)", health, max, design)
+                    format!(
+                        "{:.0}% ({} / {} {})",
+                        health, max, design, info.capacity_unit
+                    )
                 }
                 _ => format!("{:.0}%", health),
             };
             println!("│ {:<20} {:>34} │", "Max Capacity:", cap_str);
         }
 
+        // Charge limit
+        if let Some(limit) = info.charge_limit.filter(|l| l.is_active()) {
+            println!("│ {:<20} {:>34} │", "Charge Limit:", limit.to_string());
+        }
+
         // Cycle count
         if let Some(cycles) = info.cycle_count {
+            let rated = info.cycle_rating().rated_cycles;
             let remaining = info.estimated_remaining_cycles().unwrap_or(0);
             println!(
                 "│ {:<20} {:>34} │",
                 "Cycle Count:",
-                format!("{} / 1,000 (~{} remaining)", cycles, remaining)
+                format!("{} / {} (~{} remaining)", cycles, rated, remaining)
             );
         }
 
@@ -130,54 +174,127 @@ pub fn print_status(info: &BatteryInfo, detailed: bool) {
 // ── Health Report Display ──────────────────────────────────────────────
 
 pub fn print_health_report(report: &HealthReport) {
-    println!("{}", "Battery Health Report:".bold());
+    let title = if report.battery.is_empty() {
+        "Battery Health Report:".to_string()
+    } else {
+        format!("Battery Health Report ({}):", report.battery)
+    };
+    println!("{}", title.bold());
     println!();
 
-    let width = 44;
+    let width = 52;
     let border_top = format!("╭{}╮", "─".repeat(width));
     let border_bot = format!("╰{}╯", "─".repeat(width));
 
     println!("{}", border_top);
 
-    if let Some(design) = report.design_capacity_mah {
-        println!("│ {:<24} {:>17} │", "Design Capacity:", format!("{} mAh", design));
+    let unit = report.capacity_unit;
+    if let Some(design) =
+        format_capacity(unit, report.design_capacity_mah, report.design_energy_mwh)
+    {
+        println!("│ {:<24} {:>25} │", "Design Capacity:", design);
     }
-    if let Some(max) = report.max_capacity_mah {
-        println!("│ {:<24} {:>17} │", "Current Max:", format!("{} mAh", max));
+    if let Some(max) = format_capacity(unit, report.max_capacity_mah, report.max_energy_mwh) {
+        println!("│ {:<24} {:>25} │", "Current Max:", max);
     }
-    if let (Some(loss), Some(pct)) = (report.capacity_loss_mah, report.capacity_loss_percent) {
-        let loss_str = format!("-{} mAh (-{:.0}%)", loss, pct);
-        println!("│ {:<24} {:>17} │", "Capacity Loss:", loss_str);
+    let native_loss = match unit {
+        CapacityUnit::MilliampHours => report.capacity_loss_mah,
+        CapacityUnit::MilliwattHours => report.capacity_loss_mwh,
+    };
+    if let (Some(loss), Some(pct)) = (native_loss, report.capacity_loss_percent) {
+        let loss_str = format!("-{} {} (-{:.0}%)", loss, unit, pct);
+        println!("│ {:<24} {:>25} │", "Capacity Loss:", loss_str);
     }
 
-    println!("│ {:>42} │", "");
+    println!("│ {:>50} │", "");
 
-    if let Some(cycles) = report.cycle_count {
-        println!("│ {:<24} {:>17} │", "Cycle Count:", cycles);
+    match (report.cycle_count, report.cycle_source) {
+        (Some(cycles), Some(CycleSource::Throughput)) => {
+            println!(
+                "│ {:<24} {:>25} │",
+                "Cycle Count:",
+                format!("~{} (counted)", cycles)
+            );
+        }
+        (Some(cycles), _) => println!("│ {:<24} {:>25} │", "Cycle Count:", cycles),
+        _ => {}
+    }
+    if let Some(ref counter) = report.cycle_counter {
+        println!(
+            "│ {:<24} {:>25} │",
+            "Counted Since:",
+            format!(
+                "{} ({:.1})",
+                counter.since.format("%Y-%m-%d"),
+                counter.cycles
+            )
+        );
     }
+    let rating = &report.cycle_rating;
+    println!(
+        "│ {:<24} {:>25} │",
+        "Rated Life:",
+        format!(
+            "{} cycles to {:.0}%",
+            rating.rated_cycles, rating.end_of_life_percent
+        )
+    );
+    println!(
+        "│ {:<24} {:>25} │",
+        "Rated By:",
+        truncate_str(&rating.source, 25)
+    );
     if let Some(remaining) = report.estimated_remaining_cycles {
         println!(
-            "│ {:<24} {:>17} │",
+            "│ {:<24} {:>25} │",
             "Est. Remaining:",
             format!("{} cycles", remaining)
         );
     }
 
-    println!("│ {:>42} │", "");
+    println!("│ {:>50} │", "");
 
+    if let Some(ref resistance) = report.internal_resistance {
+        let value = match resistance.change_percent {
+            Some(change) => format!("{:.0} mΩ ({:+.0}%)", resistance.resistance_mohm, change),
+            None => format!("{:.0} mΩ", resistance.resistance_mohm),
+        };
+        println!("│ {:<24} {:>25} │", "Internal Resistance:", value);
+    }
     let condition_display = match report.condition.as_str() {
         "Normal" => "Normal".green().to_string(),
         "Service Recommended" => "Service Recommended".yellow().to_string(),
         "Replace" => "Replace".red().to_string(),
         _ => report.condition.clone(),
     };
-    println!("│ {:<24} {:>17} │", "Condition:", condition_display);
+    println!("│ {:<24} {:>25} │", "Condition:", condition_display);
 
+    if let Some(limit) = report.charge_limit.filter(|l| l.is_active()) {
+        println!("│ {:<24} {:>25} │", "Charge Limit:", limit.to_string());
+    }
     if let Some(ref date) = report.manufacture_date {
-        println!("│ {:<24} {:>17} │", "Manufactured:", date);
+        println!("│ {:<24} {:>25} │", "Manufactured:", date);
     }
     if let Some(ref age) = report.age_description {
-        println!("│ {:<24} {:>17} │", "Age:", age);
+        println!("│ {:<24} {:>25} │", "Age:", age);
+    }
+
+    if let Some(model) = format_model(report.manufacturer.as_deref(), report.model_name.as_deref())
+    {
+        println!("│ {:<24} {:>25} │", "Model:", model);
+    }
+    if let Some(ref serial) = report.serial_number {
+        println!("│ {:<24} {:>25} │", "Serial Number:", serial);
+    }
+    if let Some(ref technology) = report.technology {
+        println!("│ {:<24} {:>25} │", "Technology:", technology);
+    }
+    if let Some(first_seen) = report.first_seen {
+        println!(
+            "│ {:<24} {:>25} │",
+            "First Seen:",
+            first_seen.format("%Y-%m-%d").to_string()
+        );
     }
 
     println!("{}", border_bot);
@@ -186,7 +303,211 @@ pub fn print_health_report(report: &HealthReport) {
     if !report.capacity_trend.is_empty() {
         println!();
         println!("{}", "Capacity Trend:".bold());
-        print_simple_chart(&report.capacity_trend.iter().map(|p| p.health_percent).collect::<Vec<_>>());
+        print_capacity_trend(&report.capacity_trend);
+        if report.outlier_days > 0 {
+            let note = format!(
+                "{} day{} left out as outliers",
+                report.outlier_days,
+                if report.outlier_days == 1 { "" } else { "s" }
+            );
+            println!("  {}", note.dimmed());
+        }
+    }
+
+    if let Some(ref forecast) = report.forecast {
+        print_forecast(forecast, report.health_percent);
+    }
+}
+
+/// Daily health laid out over time, each column the median of the days in
+/// its share of the period, so gaps in recording don't bend the curve.
+fn print_capacity_trend(trend: &[CapacityDataPoint]) {
+    let (Some(first), Some(last)) = (trend.first(), trend.last()) else {
+        return;
+    };
+    let days = (last.date - first.date).num_days().max(0) as usize + 1;
+    let width = days.min(50);
+    let mut columns = vec![Vec::new(); width];
+    for point in trend {
+        let day = (point.date - first.date).num_days().max(0) as usize;
+        columns[(day * width / days).min(width - 1)].push(point.health_percent);
+    }
+
+    // Empty columns carry the last value on
+    let mut current = first.health_percent;
+    let values: Vec<f64> = columns
+        .into_iter()
+        .map(|mut column| {
+            if !column.is_empty() {
+                column.sort_by(f64::total_cmp);
+                current = column[column.len() / 2];
+            }
+            current
+        })
+        .collect();
+    print_simple_chart(&values);
+
+    let date = |point: &CapacityDataPoint| {
+        point
+            .date
+            .with_timezone(&Local)
+            .format("%Y-%m-%d")
+            .to_string()
+    };
+    if width >= 20 {
+        println!(
+            "  {:>5}  {:<w$}{}",
+            "",
+            date(first),
+            date(last),
+            w = width - 10
+        );
+    } else {
+        println!("  {:>5}  {} to {}", "", date(first), date(last));
+    }
+}
+
+fn print_forecast(forecast: &DegradationForecast, health: Option<f64>) {
+    let month = |date: DateTime<Utc>| date.format("%Y-%m").to_string();
+
+    println!();
+    println!("{}", "Forecast:".bold());
+    println!(
+        "  {:<14} {:.2}% per month ({:.2} to {:.2})",
+        "Fade rate:",
+        forecast.fade_percent_per_month,
+        forecast.fade_percent_per_month_low,
+        forecast.fade_percent_per_month_high
+    );
+    let fixed = |threshold: f64| format!("{:.0}% health:", threshold);
+    let mut thresholds = vec![
+        (
+            fixed(forecast::SERVICE_HEALTH),
+            forecast::SERVICE_HEALTH,
+            &forecast.projected_80_percent,
+            forecast.projected_80_percent_cycles,
+        ),
+        (
+            fixed(forecast::REPLACE_HEALTH),
+            forecast::REPLACE_HEALTH,
+            &forecast.projected_60_percent,
+            forecast.projected_60_percent_cycles,
+        ),
+    ];
+    // A rating with its own end of life gets a line of its own, in order
+    let end_of_life = forecast.end_of_life_percent;
+    if thresholds
+        .iter()
+        .all(|(_, t, _, _)| (t - end_of_life).abs() >= 0.5)
+    {
+        let at = thresholds
+            .iter()
+            .position(|(_, t, _, _)| *t < end_of_life)
+            .unwrap_or(thresholds.len());
+        thresholds.insert(
+            at,
+            (
+                format!("{:.0}% (rated):", end_of_life),
+                end_of_life,
+                &forecast.projected_end_of_life,
+                forecast.projected_end_of_life_cycles,
+            ),
+        );
+    }
+    for (label, threshold, projection, cycles) in thresholds {
+        let when = match projection {
+            Some(p) => {
+                let latest = p.latest.map_or("never".to_string(), month);
+                let at_cycles = cycles.map_or(String::new(), |c| format!(", ~{} cycles", c));
+                format!(
+                    "{} ({} to {}){}",
+                    month(p.date),
+                    month(p.earliest),
+                    latest,
+                    at_cycles
+                )
+            }
+            None if health.is_some_and(|h| h <= threshold) => "already below".to_string(),
+            None => "not in sight".to_string(),
+        };
+        println!("  {:<14} {}", label, when);
+    }
+    if let Some(per_cycles) = forecast.fade_percent_per_100_cycles {
+        println!("  {:<14} {:.1}% per 100 cycles", "By cycles:", per_cycles);
+    }
+    println!(
+        "  {}",
+        format!(
+            "Fitted over {} readings across {} days (R² {:.2}); ranges are 95% confidence.",
+            forecast.points, forecast.span_days, forecast.r_squared
+        )
+        .dimmed()
+    );
+}
+
+/// The stress analysis of `range`, or why there is none. `raw_days` is set
+/// when the range reaches back further than raw readings are kept.
+pub fn print_stress(report: Option<&StressReport>, range: &TimeRange, raw_days: Option<u32>) {
+    println!();
+    println!("{}", "Stress:".bold());
+    let clamp_note = || {
+        if let Some(days) = raw_days {
+            let note = format!(
+                "Raw readings are kept for {} days (retention.raw_days), so nothing older is scored.",
+                days
+            );
+            println!("  {}", note.dimmed());
+        }
+    };
+    let Some(report) = report else {
+        println!("  Not enough readings to score ({}).", range.describe());
+        clamp_note();
+        return;
+    };
+
+    let level = match report.level.as_str() {
+        "low" => report.level.green(),
+        "moderate" => report.level.yellow(),
+        _ => report.level.red(),
+    };
+    println!("  {:<20} {:.0} / 100 ({})", "Index:", report.index, level);
+    for factor in &report.factors {
+        println!(
+            "  {:<20} {:<24} {:>3.0} / 100  +{:.1}",
+            format!("{}:", factor.label),
+            factor.measured,
+            factor.score,
+            factor.contribution
+        );
+    }
+    if let Some(costliest) = report
+        .costliest
+        .as_ref()
+        .and_then(|name| report.factors.iter().find(|f| &f.factor == name))
+    {
+        println!("  {:<20} {}", "Costs the most:", costliest.label.bold());
+        println!("  {:<20} {}", "", costliest.advice);
+    }
+    println!(
+        "  {}",
+        format!(
+            "Over {:.0} hours of readings from {} to {}.",
+            report.hours,
+            report.start.with_timezone(&Local).format("%Y-%m-%d"),
+            report.end.with_timezone(&Local).format("%Y-%m-%d")
+        )
+        .dimmed()
+    );
+    clamp_note();
+}
+
+/// "Manufacturer Model", or whichever of the two is known.
+fn format_model(manufacturer: Option<&str>, model: Option<&str>) -> Option<String> {
+    match (manufacturer, model) {
+        (Some(m), Some(n)) => Some(format!("{} {}", m, n)),
+        (Some(m), None) => Some(m.to_string()),
+        (None, Some(n)) => Some(n.to_string()),
+        (None, None) => None,
     }
 }
 
@@ -204,6 +525,53 @@ pub fn print_health_comparison(comparisons: &[(String, String, String)]) {
     }
 }
 
+// ── Charge Limit Display ───────────────────────────────────────────────
+
+pub fn print_limit_status(statuses: &[LimitStatus]) {
+    println!("{}", "Charge Limits:".bold());
+    for status in statuses {
+        let state = match status.limit {
+            Some(limit) if limit.is_active() => limit.to_string().green().to_string(),
+            Some(limit) => format!("{} (no limit)", limit),
+            None => "not supported by this hardware".dimmed().to_string(),
+        };
+        println!("  {:<10} {}", status.battery, state);
+        for action in &status.actions {
+            println!("    {}", action.dimmed());
+        }
+    }
+}
+
+pub fn print_charge_behaviour(statuses: &[BehaviourStatus]) {
+    println!("{}", "Charge Behaviour:".bold());
+    for status in statuses {
+        match &status.behaviour {
+            Some(behaviour) => {
+                let modes: Vec<String> = behaviour
+                    .available
+                    .iter()
+                    .map(|m| {
+                        if *m == behaviour.active {
+                            format!("[{}]", m).green().to_string()
+                        } else {
+                            m.clone()
+                        }
+                    })
+                    .collect();
+                println!("  {:<10} {}", status.battery, modes.join(" "));
+            }
+            None => println!(
+                "  {:<10} {}",
+                status.battery,
+                "not supported by this hardware".dimmed()
+            ),
+        }
+        for action in &status.actions {
+            println!("    {}", action.dimmed());
+        }
+    }
+}
+
 // ── Power Hogs Display ─────────────────────────────────────────────────
 
 pub fn print_power_report(report: &PowerReport, detailed: bool) {
@@ -310,11 +678,366 @@ pub fn print_history(
         "  On battery: {}h {:02}m",
         discharging_h, discharging_m
     );
+    if summary.total_not_charging_minutes > 0 {
+        println!(
+            "  Plugged in, not charging: {}h {:02}m",
+            summary.total_not_charging_minutes / 60,
+            summary.total_not_charging_minutes % 60
+        );
+    }
+    if summary.total_sleep_minutes > 0 {
+        println!(
+            "  Asleep or off: {}h {:02}m",
+            summary.total_sleep_minutes / 60,
+            summary.total_sleep_minutes % 60
+        );
+    }
     if let Some(rate) = summary.avg_discharge_rate_watts {
         println!("  Avg discharge rate: {:.1} W", rate);
     }
+    if let (Some(avg), Some(max)) = (
+        summary.avg_temperature_celsius,
+        summary.max_temperature_celsius,
+    ) {
+        println!("  Temperature: avg {:.1}C, peak {:.1}C", avg, max);
+    }
     println!("  Cycles completed: {:.2} cycles", summary.estimated_cycles);
     println!("  Snapshots recorded: {}", summary.snapshots_count);
+    if summary.granularity != Granularity::Raw {
+        println!(
+            "  {}",
+            format!("Chart uses {} averages", summary.granularity.as_str()).dimmed()
+        );
+    }
+}
+
+/// List the packs seen in history, marking the one being shown.
+pub fn print_pack_installs(installs: &[BatteryEvent], selected: Option<i64>) {
+    println!();
+    println!("{}", "Battery Packs:".bold());
+    for install in installs {
+        let marker = if Some(install.id) == selected {
+            ">"
+        } else {
+            " "
+        };
+        let serial = install
+            .serial_number
+            .as_deref()
+            .map(|s| format!("serial {}", s))
+            .unwrap_or_default();
+        println!(
+            "  {} #{:<3} {:<6} {}  {:<14} {}",
+            marker,
+            install.id,
+            install.battery,
+            install.timestamp.format("%Y-%m-%d"),
+            serial,
+            install.detail.as_deref().unwrap_or("").dimmed()
+        );
+    }
+    match selected {
+        Some(id) => println!(
+            "  Showing pack #{}. Use --pack <ID> for another pack, or --pack all.",
+            id
+        ),
+        None => println!("  Showing all packs. Use --pack <ID> for a single pack."),
+    }
+}
+
+// ── Sessions Display ───────────────────────────────────────────────────
+
+pub fn print_sessions(sessions: &[Session], period: &str) {
+    println!(
+        "{}",
+        format!("Charge and Discharge Sessions ({}):", period).bold()
+    );
+    println!();
+
+    if sessions.is_empty() {
+        println!("  No sessions recorded in this period.");
+        println!("  Tip: Sessions are built from raw snapshots; run 'batteryctl record'");
+        println!("  every few minutes to collect them.");
+        return;
+    }
+
+    println!(
+        "  {:<16} {:<11} {:>8} {:>11} {:>10} {:>7} {:>7} {:>7}",
+        "Start", "Kind", "Duration", "Level", "Energy", "Avg W", "Peak W", "Temp"
+    );
+    for session in sessions {
+        let kind = format!("{:<11}", session.kind.as_str());
+        let kind = match session.kind {
+            SessionKind::Charge => kind.green(),
+            SessionKind::Discharge => kind.yellow(),
+        };
+        let optional = |value: Option<f64>, precision: usize| {
+            value
+                .map(|v| format!("{:.*}", precision, v))
+                .unwrap_or_else(|| "-".to_string())
+        };
+        println!(
+            "  {:<16} {} {:>8} {:>11} {:>10} {:>7} {:>7} {:>7}",
+            session.start.format("%Y-%m-%d %H:%M"),
+            kind,
+            format!(
+                "{}h {:02}m",
+                session.duration_minutes / 60,
+                session.duration_minutes % 60
+            ),
+            format!("{}% -> {}%", session.start_level, session.end_level),
+            session
+                .energy_mwh
+                .map(|e| format!("{:.1} Wh", e / 1000.0))
+                .unwrap_or_else(|| "-".to_string()),
+            optional(session.avg_power_watts, 1),
+            optional(session.peak_power_watts, 1),
+            session
+                .max_temperature_celsius
+                .map(|t| format!("{:.0}C", t))
+                .unwrap_or_else(|| "-".to_string()),
+        );
+    }
+
+    println!();
+    for kind in [SessionKind::Discharge, SessionKind::Charge] {
+        let of_kind: Vec<&Session> = sessions.iter().filter(|s| s.kind == kind).collect();
+        if of_kind.is_empty() {
+            continue;
+        }
+        let minutes: i64 = of_kind.iter().map(|s| s.duration_minutes).sum();
+        let energy: f64 = of_kind.iter().filter_map(|s| s.energy_mwh).sum();
+        println!(
+            "  {} {} session(s), {}h {:02}m, {:.1} Wh",
+            format!("{:<10}", format!("{}:", kind.as_str())).dimmed(),
+            of_kind.len(),
+            minutes / 60,
+            minutes % 60,
+            energy / 1000.0
+        );
+    }
+}
+
+// ── Sleep Drain Display ────────────────────────────────────────────────
+
+pub fn print_sleep_report(report: &SleepReport, period: &str) {
+    println!("{}", format!("Sleep Drain ({}):", period).bold());
+    println!();
+
+    if report.periods.is_empty() {
+        println!("  No sleeps on battery recorded in this period.");
+        println!("  Tip: Install the 'batteryctl hook' commands as a systemd-sleep hook");
+        println!("  (see 'batteryctl hook --help'), or record snapshots regularly.");
+        return;
+    }
+
+    println!(
+        "  {:<16} {:>8} {:>11} {:>6} {:>7}  Source",
+        "Start", "Duration", "Level", "Lost", "%/h"
+    );
+    for period in &report.periods {
+        let rate = format!("{:>7.2}", period.drain_per_hour);
+        let rate = if period.abnormal {
+            rate.red().bold()
+        } else {
+            rate.normal()
+        };
+        let source = match period.source {
+            SleepSource::Hook => "hook",
+            SleepSource::Gap => "gap",
+        };
+        println!(
+            "  {:<16} {:>8} {:>11} {:>6} {}  {}{}",
+            period.start.format("%Y-%m-%d %H:%M"),
+            format!("{}h {:02}m", period.minutes / 60, period.minutes % 60),
+            format!("{}% -> {}%", period.level_before, period.level_after),
+            format!("{:.0}%", period.drain_percent),
+            rate,
+            source.dimmed(),
+            if period.abnormal {
+                "  abnormal".red().to_string()
+            } else {
+                String::new()
+            }
+        );
+    }
+
+    println!();
+    println!(
+        "  Total: {}h {:02}m asleep, {:.0}% lost",
+        report.total_minutes / 60,
+        report.total_minutes % 60,
+        report.total_drain_percent
+    );
+    if let Some(rate) = report.avg_drain_per_hour {
+        println!("  Average drain: {:.2}%/h", rate);
+    }
+    if report.abnormal > 0 {
+        println!();
+        println!(
+            "  {}",
+            format!(
+                "{} sleep(s) drained more than {:.1}%/h. The machine is probably not reaching",
+                report.abnormal, ABNORMAL_DRAIN_PER_HOUR
+            )
+            .yellow()
+        );
+        println!(
+            "  {}",
+            "a deep sleep state; check /sys/power/mem_sleep and whether s2idle works on this model."
+                .yellow()
+        );
+    }
+}
+
+// ── History Maintenance Display ────────────────────────────────────────
+
+pub fn print_prune_counts(counts: &PruneCounts, older_than: &str, dry_run: bool) {
+    let verb = if dry_run { "Would delete" } else { "Deleted" };
+    println!("{} data older than {}:", verb, older_than);
+    println!("  Snapshots:       {}", counts.raw);
+    println!("  Hourly rollups:  {}", counts.hourly);
+    println!("  Daily rollups:   {}", counts.daily);
+    if !dry_run && counts.raw + counts.hourly + counts.daily > 0 {
+        println!(
+            "  {}",
+            "Run 'batteryctl history vacuum' to shrink the file.".dimmed()
+        );
+    }
+}
+
+pub fn print_vacuum_report(report: &VacuumReport) {
+    match (report.size_before, report.size_after) {
+        (Some(before), Some(after)) => println!(
+            "Vacuumed history database: {} -> {} ({} freed)",
+            format_bytes(before),
+            format_bytes(after),
+            format_bytes(before.saturating_sub(after))
+        ),
+        _ => println!("Vacuumed history database."),
+    }
+}
+
+pub fn print_history_stats(stats: &HistoryStats) {
+    println!("{}", "History Database:".bold());
+    if let Some(path) = &stats.path {
+        println!("  {:<18} {}", "Path:", path.display());
+    }
+    if let Some(size) = stats.file_size_bytes {
+        println!("  {:<18} {}", "File Size:", format_bytes(size));
+    }
+    println!("  {:<18} {}", "Schema Version:", stats.schema_version);
+    println!("  {:<18} {}", "Snapshots:", stats.snapshots);
+    println!(
+        "  {:<18} {} hourly, {} daily",
+        "Rollups:", stats.hourly_rollups, stats.daily_rollups
+    );
+    println!("  {:<18} {}", "Events:", stats.events);
+    if let (Some(first), Some(last)) = (stats.first, stats.last) {
+        println!(
+            "  {:<18} {} to {}",
+            "Date Range:",
+            first.format("%Y-%m-%d %H:%M"),
+            last.format("%Y-%m-%d %H:%M")
+        );
+    }
+
+    if !stats.packs.is_empty() {
+        println!();
+        println!("{}", "Per Pack:".bold());
+        for pack in &stats.packs {
+            let range = match (pack.first, pack.last) {
+                (Some(first), Some(last)) => format!(
+                    "{} to {}",
+                    first.format("%Y-%m-%d"),
+                    last.format("%Y-%m-%d")
+                ),
+                _ => String::new(),
+            };
+            let name = pack.battery.as_deref().unwrap_or("(unnamed)");
+            let name = match &pack.host {
+                Some(host) => format!("{}/{}", host, name),
+                None => name.to_string(),
+            };
+            println!(
+                "  {:<10} {:>8} snapshots  {}",
+                name,
+                pack.snapshots,
+                range.dimmed()
+            );
+        }
+    }
+
+    println!();
+    if stats.gaps.is_empty() {
+        println!("{}", "No gaps in recording.".green());
+    } else {
+        println!("{}", format!("Gaps ({}):", stats.gaps.len()).bold());
+        for gap in &stats.gaps {
+            println!(
+                "  {}{} to {}  {}",
+                gap.host
+                    .as_deref()
+                    .map(|h| format!("{}: ", h))
+                    .unwrap_or_default(),
+                gap.start.format("%Y-%m-%d %H:%M"),
+                gap.end.format("%Y-%m-%d %H:%M"),
+                format!("{}h {:02}m", gap.minutes / 60, gap.minutes % 60).dimmed()
+            );
+        }
+    }
+}
+
+pub fn print_import_counts(counts: &ImportCounts, source: &str) {
+    println!("Imported from {}:", source);
+    println!("  Snapshots:       {}", counts.snapshots);
+    if counts.duplicates > 0 {
+        println!(
+            "  {}",
+            format!("Skipped {} already stored", counts.duplicates).dimmed()
+        );
+    }
+    if counts.rollups > 0 {
+        println!("  Rollups:         {}", counts.rollups);
+    }
+    if counts.events > 0 {
+        println!("  Pack events:     {}", counts.events);
+    }
+}
+
+pub fn print_integrity_report(report: &IntegrityReport) {
+    if report.ok {
+        println!("{}", "History database is healthy.".green());
+        return;
+    }
+
+    println!("{}", "History Database Problems:".bold());
+    if report.sqlite != "ok" {
+        println!("  {} {}", "SQLite integrity check:".red(), report.sqlite);
+    }
+    for issue in &report.issues {
+        println!(
+            "  {} {:<6} {:<16} {}",
+            issue.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            match (&issue.host, &issue.battery) {
+                (Some(host), Some(battery)) => format!("{}/{}", host, battery),
+                (Some(host), None) => host.clone(),
+                (None, battery) => battery.clone().unwrap_or_else(|| "-".to_string()),
+            },
+            issue.kind.yellow(),
+            issue.detail
+        );
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
 }
 
 // ── Optimization Display ───────────────────────────────────────────────
@@ -379,35 +1102,26 @@ pub fn print_optimization_report(report: &OptimizationReport) {
 
 // ── Export Functions ────────────────────────────────────────────────────
 
-pub fn export_snapshots_csv(
-    snapshots: &[BatterySnapshot],
-    path: &str,
-) -> anyhow::Result<()> {
-    let mut wtr = csv::Writer::from_path(path)?;
-    wtr.write_record(["timestamp", "level", "is_charging", "power_draw", "cycle_count", "max_capacity"])?;
-
-    for snap in snapshots {
-        wtr.write_record(&[
-            snap.timestamp.to_rfc3339(),
-            snap.level.to_string(),
-            snap.is_charging.to_string(),
-            snap.power_draw.map_or("".to_string(), |p| format!("{:.2}", p)),
-            snap.cycle_count.map_or("".to_string(), |c| c.to_string()),
-            snap.max_capacity.map_or("".to_string(), |c| c.to_string()),
-        ])?;
-    }
-
-    wtr.flush()?;
-    println!("Exported {} snapshots to {}", snapshots.len(), path);
-    Ok(())
-}
-
 pub fn export_json(info: &BatteryInfo) -> anyhow::Result<String> {
     Ok(serde_json::to_string_pretty(info)?)
 }
 
 // ── Helper Functions ───────────────────────────────────────────────────
 
+/// Capacity in the native unit, with the derived unit in parentheses
+/// (e.g. "57000 mWh (4935 mAh)").
+fn format_capacity(unit: CapacityUnit, mah: Option<u32>, mwh: Option<u32>) -> Option<String> {
+    let (native, derived, derived_unit) = match unit {
+        CapacityUnit::MilliampHours => (mah, mwh, CapacityUnit::MilliwattHours),
+        CapacityUnit::MilliwattHours => (mwh, mah, CapacityUnit::MilliampHours),
+    };
+    let native = native?;
+    Some(match derived {
+        Some(derived) => format!("{} {} ({} {})", native, unit, derived, derived_unit),
+        None => format!("{} {}", native, unit),
+    })
+}
+
 fn print_simple_chart(values: &[f64]) {
     if values.is_empty() {
         return;
@@ -0,0 +1,386 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+
+use crate::history::BatterySnapshot;
+
+/// File formats `history --output` can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A pretty-printed JSON array.
+    Json,
+    /// One compact JSON object per line.
+    Ndjson,
+    /// Every snapshot column, one row per snapshot.
+    Csv,
+    /// InfluxDB / Telegraf line protocol, measurement `battery`.
+    Influx,
+    /// SQL that recreates the rows in a `snapshots` table (`sqlite3 new.db < dump.sql`).
+    Sqlite,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+            "csv" => Ok(ExportFormat::Csv),
+            "influx" | "line" | "lp" => Ok(ExportFormat::Influx),
+            "sqlite" | "sql" => Ok(ExportFormat::Sqlite),
+            _ => anyhow::bail!(
+                "Unknown export format '{}'. Use json, ndjson, csv, influx or sqlite",
+                s
+            ),
+        }
+    }
+
+    /// Format implied by a file name's extension; JSON when there is none
+    /// or it is not recognised.
+    pub fn for_path(path: &str) -> Self {
+        let ext = std::path::Path::new(path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "csv" => ExportFormat::Csv,
+            "ndjson" | "jsonl" => ExportFormat::Ndjson,
+            "lp" | "influx" => ExportFormat::Influx,
+            "sql" => ExportFormat::Sqlite,
+            _ => ExportFormat::Json,
+        }
+    }
+}
+
+/// Write `snapshots` to `path`, or to stdout when `path` is `-`.
+pub fn export_snapshots(
+    snapshots: &[BatterySnapshot],
+    format: ExportFormat,
+    path: &str,
+) -> Result<()> {
+    if path == "-" {
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        write_snapshots(snapshots, format, &mut out)?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    let file = std::fs::File::create(path).with_context(|| format!("Failed to create {}", path))?;
+    let mut out = std::io::BufWriter::new(file);
+    write_snapshots(snapshots, format, &mut out)?;
+    out.flush()?;
+    println!("Exported {} snapshots to {}", snapshots.len(), path);
+    Ok(())
+}
+
+pub fn write_snapshots(
+    snapshots: &[BatterySnapshot],
+    format: ExportFormat,
+    out: &mut dyn Write,
+) -> Result<()> {
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, snapshots)?;
+            writeln!(out)?;
+        }
+        ExportFormat::Ndjson => {
+            for snap in snapshots {
+                serde_json::to_writer(&mut *out, snap)?;
+                writeln!(out)?;
+            }
+        }
+        ExportFormat::Csv => write_csv(snapshots, out)?,
+        ExportFormat::Influx => {
+            for snap in snapshots {
+                writeln!(out, "{}", influx_line(snap))?;
+            }
+        }
+        ExportFormat::Sqlite => write_sql(snapshots, out)?,
+    }
+    Ok(())
+}
+
+/// CSV columns, in order; `history import` reads them back by name.
+pub const CSV_COLUMNS: [&str; 22] = [
+    "timestamp",
+    "level",
+    "is_charging",
+    "power_draw",
+    "cycle_count",
+    "max_capacity_mah",
+    "max_energy_mwh",
+    "capacity_unit",
+    "state",
+    "temperature_c",
+    "voltage_mv",
+    "current_capacity_mah",
+    "current_energy_mwh",
+    "time_remaining_min",
+    "design_capacity_mah",
+    "design_energy_mwh",
+    "battery",
+    "host",
+    "model_name",
+    "manufacturer",
+    "serial_number",
+    "technology",
+];
+
+fn write_csv(snapshots: &[BatterySnapshot], out: &mut dyn Write) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(out);
+    wtr.write_record(CSV_COLUMNS)?;
+
+    let text = |v: &Option<String>| v.clone().unwrap_or_default();
+    for snap in snapshots {
+        wtr.write_record(&[
+            snap.timestamp.to_rfc3339(),
+            snap.level.to_string(),
+            snap.is_charging.to_string(),
+            snap.power_draw
+                .map_or("".to_string(), |p| format!("{:.2}", p)),
+            snap.cycle_count.map_or("".to_string(), |c| c.to_string()),
+            snap.max_capacity.map_or("".to_string(), |c| c.to_string()),
+            snap.max_energy.map_or("".to_string(), |c| c.to_string()),
+            snap.capacity_unit.map_or("".to_string(), |u| u.to_string()),
+            snap.state
+                .map_or("".to_string(), |s| s.as_str().to_string()),
+            snap.temperature_celsius
+                .map_or("".to_string(), |t| format!("{:.1}", t)),
+            snap.voltage_mv
+                .map_or("".to_string(), |v| format!("{:.0}", v)),
+            snap.current_capacity
+                .map_or("".to_string(), |c| c.to_string()),
+            snap.current_energy
+                .map_or("".to_string(), |c| c.to_string()),
+            snap.time_remaining_minutes
+                .map_or("".to_string(), |t| t.to_string()),
+            snap.design_capacity
+                .map_or("".to_string(), |c| c.to_string()),
+            snap.design_energy.map_or("".to_string(), |c| c.to_string()),
+            text(&snap.battery),
+            text(&snap.host),
+            text(&snap.model_name),
+            text(&snap.manufacturer),
+            text(&snap.serial_number),
+            text(&snap.technology),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// One line of line protocol. Pack, host, state and unit are tags; the
+/// readings are fields; the timestamp is in nanoseconds.
+fn influx_line(snap: &BatterySnapshot) -> String {
+    let escape = |v: &str| {
+        v.replace('\\', "\\\\")
+            .replace(',', "\\,")
+            .replace('=', "\\=")
+            .replace(' ', "\\ ")
+    };
+
+    let mut line = "battery".to_string();
+    let tags = [
+        ("battery", snap.battery.clone()),
+        ("host", snap.host.clone()),
+        ("state", snap.state.map(|s| s.as_str().to_string())),
+        ("capacity_unit", snap.capacity_unit.map(|u| u.to_string())),
+    ];
+    for (key, value) in tags {
+        if let Some(value) = value.filter(|v| !v.is_empty()) {
+            line.push_str(&format!(",{}={}", key, escape(&value)));
+        }
+    }
+
+    let integer = |v: Option<u32>| v.map(|v| format!("{}i", v));
+    let float = |v: Option<f64>| v.filter(|v| v.is_finite()).map(|v| v.to_string());
+    let fields = [
+        ("level", Some(format!("{}i", snap.level))),
+        ("is_charging", Some(snap.is_charging.to_string())),
+        ("power_draw", float(snap.power_draw)),
+        ("cycle_count", integer(snap.cycle_count)),
+        ("max_capacity_mah", integer(snap.max_capacity)),
+        ("design_capacity_mah", integer(snap.design_capacity)),
+        ("max_energy_mwh", integer(snap.max_energy)),
+        ("design_energy_mwh", integer(snap.design_energy)),
+        ("current_capacity_mah", integer(snap.current_capacity)),
+        ("current_energy_mwh", integer(snap.current_energy)),
+        ("temperature_c", float(snap.temperature_celsius)),
+        ("voltage_mv", float(snap.voltage_mv)),
+        (
+            "time_remaining_min",
+            snap.time_remaining_minutes.map(|t| format!("{}i", t)),
+        ),
+    ];
+    let fields: Vec<String> = fields
+        .into_iter()
+        .filter_map(|(key, value)| Some(format!("{}={}", key, value?)))
+        .collect();
+
+    let nanos =
+        snap.timestamp.timestamp() * 1_000_000_000 + snap.timestamp.timestamp_subsec_nanos() as i64;
+    format!("{} {} {}", line, fields.join(","), nanos)
+}
+
+/// SQL recreating the rows in a `snapshots` table laid out like the history
+/// database's, so the result can also be read with `history merge`.
+fn write_sql(snapshots: &[BatterySnapshot], out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "BEGIN TRANSACTION;")?;
+    writeln!(
+        out,
+        "CREATE TABLE IF NOT EXISTS snapshots (
+  timestamp INTEGER NOT NULL,
+  level INTEGER NOT NULL,
+  is_charging BOOLEAN NOT NULL,
+  power_draw REAL,
+  cycle_count INTEGER,
+  max_capacity INTEGER,
+  design_capacity INTEGER,
+  battery TEXT,
+  max_energy INTEGER,
+  design_energy INTEGER,
+  capacity_unit TEXT,
+  model_name TEXT,
+  manufacturer TEXT,
+  serial_number TEXT,
+  technology TEXT,
+  state TEXT,
+  temperature REAL,
+  voltage REAL,
+  current_capacity INTEGER,
+  current_energy INTEGER,
+  time_remaining INTEGER,
+  host TEXT
+);"
+    )?;
+
+    let text = |v: Option<&str>| match v {
+        Some(v) => format!("'{}'", v.replace('\'', "''")),
+        None => "NULL".to_string(),
+    };
+    let number = |v: Option<String>| v.unwrap_or_else(|| "NULL".to_string());
+    for snap in snapshots {
+        let values = [
+            snap.timestamp.timestamp().to_string(),
+            snap.level.to_string(),
+            (snap.is_charging as u8).to_string(),
+            number(
+                snap.power_draw
+                    .filter(|v| v.is_finite())
+                    .map(|v| v.to_string()),
+            ),
+            number(snap.cycle_count.map(|v| v.to_string())),
+            number(snap.max_capacity.map(|v| v.to_string())),
+            number(snap.design_capacity.map(|v| v.to_string())),
+            text(snap.battery.as_deref()),
+            number(snap.max_energy.map(|v| v.to_string())),
+            number(snap.design_energy.map(|v| v.to_string())),
+            text(snap.capacity_unit.map(|u| u.as_str())),
+            text(snap.model_name.as_deref()),
+            text(snap.manufacturer.as_deref()),
+            text(snap.serial_number.as_deref()),
+            text(snap.technology.as_deref()),
+            text(snap.state.map(|s| s.as_str())),
+            number(
+                snap.temperature_celsius
+                    .filter(|v| v.is_finite())
+                    .map(|v| v.to_string()),
+            ),
+            number(
+                snap.voltage_mv
+                    .filter(|v| v.is_finite())
+                    .map(|v| v.to_string()),
+            ),
+            number(snap.current_capacity.map(|v| v.to_string())),
+            number(snap.current_energy.map(|v| v.to_string())),
+            number(snap.time_remaining_minutes.map(|v| v.to_string())),
+            text(snap.host.as_deref()),
+        ];
+        writeln!(out, "INSERT INTO snapshots VALUES ({});", values.join(", "))?;
+    }
+    writeln!(out, "COMMIT;")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::{CapacityUnit, ChargingState};
+    use crate::history::HistoryManager;
+    use chrono::DateTime;
+
+    fn sample() -> Vec<BatterySnapshot> {
+        vec![BatterySnapshot {
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            battery: Some("BAT0".to_string()),
+            host: Some("bob's laptop".to_string()),
+            level: 85,
+            is_charging: false,
+            state: Some(ChargingState::Discharging),
+            power_draw: Some(9.5),
+            cycle_count: Some(120),
+            max_energy: Some(50000),
+            design_energy: Some(57000),
+            capacity_unit: Some(CapacityUnit::MilliwattHours),
+            model_name: Some("5B10W13930".to_string()),
+            ..Default::default()
+        }]
+    }
+
+    fn render(format: ExportFormat) -> String {
+        let mut out = Vec::new();
+        write_snapshots(&sample(), format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_format_from_name_and_path() {
+        assert_eq!(ExportFormat::parse("NDJSON").unwrap(), ExportFormat::Ndjson);
+        assert!(ExportFormat::parse("xml").is_err());
+        assert_eq!(ExportFormat::for_path("out.csv"), ExportFormat::Csv);
+        assert_eq!(ExportFormat::for_path("out.jsonl"), ExportFormat::Ndjson);
+        assert_eq!(ExportFormat::for_path("out.sql"), ExportFormat::Sqlite);
+        assert_eq!(ExportFormat::for_path("-"), ExportFormat::Json);
+    }
+
+    #[test]
+    fn test_influx_line() {
+        assert_eq!(
+            render(ExportFormat::Influx).trim(),
+            "battery,battery=BAT0,host=bob's\\ laptop,state=Discharging,capacity_unit=mWh \
+             level=85i,is_charging=false,power_draw=9.5,cycle_count=120i,max_energy_mwh=50000i,\
+             design_energy_mwh=57000i 1700000000000000000"
+        );
+    }
+
+    #[test]
+    fn test_ndjson_and_csv() {
+        let ndjson = render(ExportFormat::Ndjson);
+        assert_eq!(ndjson.lines().count(), 1);
+        let row: BatterySnapshot = serde_json::from_str(ndjson.trim()).unwrap();
+        assert_eq!(row.level, 85);
+
+        let csv = render(ExportFormat::Csv);
+        let mut lines = csv.lines();
+        assert_eq!(lines.next().unwrap().split(',').count(), CSV_COLUMNS.len());
+        assert!(lines.next().unwrap().contains("5B10W13930"));
+    }
+
+    #[test]
+    fn test_sql_dump_can_be_merged() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("dump.db");
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(&render(ExportFormat::Sqlite)).unwrap();
+        drop(conn);
+
+        let dump = HistoryManager::open_at(&path)
+            .unwrap()
+            .with_host(Some("bob's laptop"));
+        let rows = dump
+            .get_raw_snapshots(chrono::Duration::days(100_000))
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].max_energy, Some(50000));
+        assert_eq!(rows[0].model_name.as_deref(), Some("5B10W13930"));
+    }
+}
@@ -0,0 +1,301 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::health::CapacityDataPoint;
+
+/// Fixed health levels every forecast projects, so forecasts of different
+/// packs compare. They match the default cycle rating's end of life and
+/// replacement point; a pack's own rating may put them elsewhere, and its
+/// end of life is projected separately.
+pub const SERVICE_HEALTH: f64 = 80.0;
+pub const REPLACE_HEALTH: f64 = 60.0;
+
+/// Fewer days of trend than this, or fewer points, say more about noise in
+/// the firmware's capacity estimate than about wear.
+const MIN_POINTS: usize = 3;
+const MIN_SPAN_DAYS: f64 = 14.0;
+
+/// Projections further out than this are reported as never.
+const MAX_PROJECTION_DAYS: f64 = 50.0 * 365.0;
+
+/// A month is 30 days, as in `parse_duration_str`.
+const DAYS_PER_MONTH: f64 = 30.0;
+
+/// Straight line fitted by least squares, with the standard error of its
+/// slope.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinearFit {
+    pub slope: f64,
+    pub intercept: f64,
+    pub slope_error: f64,
+    pub r_squared: f64,
+    /// Centre of the points, which the line passes through.
+    pub mean_x: f64,
+    pub mean_y: f64,
+    pub n: usize,
+}
+
+impl LinearFit {
+    /// Fit `(x, y)` points; `None` with fewer than three points or when all
+    /// share one `x`.
+    pub fn fit(points: &[(f64, f64)]) -> Option<Self> {
+        let n = points.len();
+        if n < 3 {
+            return None;
+        }
+        let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n as f64;
+        let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n as f64;
+        let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+        let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+        let syy: f64 = points.iter().map(|p| (p.1 - mean_y).powi(2)).sum();
+        if sxx <= 0.0 {
+            return None;
+        }
+
+        let slope = sxy / sxx;
+        let intercept = mean_y - slope * mean_x;
+        let residual: f64 = points
+            .iter()
+            .map(|p| (p.1 - (intercept + slope * p.0)).powi(2))
+            .sum();
+        Some(Self {
+            slope,
+            intercept,
+            slope_error: (residual / (n - 2) as f64 / sxx).sqrt(),
+            r_squared: if syy > 0.0 { 1.0 - residual / syy } else { 1.0 },
+            mean_x,
+            mean_y,
+            n,
+        })
+    }
+
+    pub fn at(&self, x: f64) -> f64 {
+        self.intercept + self.slope * x
+    }
+
+    /// Slopes at either end of the 95% confidence interval, steepest first.
+    pub fn slope_bounds(&self) -> (f64, f64) {
+        let margin = t_95(self.n - 2) * self.slope_error;
+        (self.slope - margin, self.slope + margin)
+    }
+
+    /// Where a line through the centre of the points with `slope` falls to
+    /// `y`; `None` if it never does.
+    fn falls_to(&self, y: f64, slope: f64) -> Option<f64> {
+        (slope < 0.0).then(|| self.mean_x + (y - self.mean_y) / slope)
+    }
+}
+
+/// Two-sided 95% quantile of Student's t distribution.
+fn t_95(degrees_of_freedom: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    match degrees_of_freedom {
+        0 => f64::INFINITY,
+        df => TABLE.get(df - 1).copied().unwrap_or(1.96),
+    }
+}
+
+/// When the pack is expected to reach a health level, with the range the
+/// 95% confidence interval of the fade rate gives.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Projection {
+    pub date: DateTime<Utc>,
+    pub earliest: DateTime<Utc>,
+    /// `None` if, at the slowest plausible fade, it might never get there.
+    pub latest: Option<DateTime<Utc>>,
+}
+
+/// Capacity fade fitted over the health trend, by calendar time and, where
+/// readings carry a cycle count, by cycles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DegradationForecast {
+    /// Health lost per 30 days, in percentage points; negative if the
+    /// firmware's estimate went up.
+    pub fade_percent_per_month: f64,
+    /// 95% confidence interval of the fade rate.
+    pub fade_percent_per_month_low: f64,
+    pub fade_percent_per_month_high: f64,
+    /// Share of the variation in health the fit explains (0 to 1).
+    pub r_squared: f64,
+    pub points: usize,
+    pub span_days: i64,
+    /// `None` once health is below the threshold, or if it is not falling.
+    pub projected_80_percent: Option<Projection>,
+    pub projected_60_percent: Option<Projection>,
+    pub fade_percent_per_100_cycles: Option<f64>,
+    /// Cycle count at which the pack is expected to reach the threshold.
+    pub projected_80_percent_cycles: Option<u32>,
+    pub projected_60_percent_cycles: Option<u32>,
+    /// Health at the end of the pack's rated life, below which its
+    /// condition is Service Recommended, and when it is expected to get
+    /// there.
+    pub end_of_life_percent: f64,
+    pub projected_end_of_life: Option<Projection>,
+    pub projected_end_of_life_cycles: Option<u32>,
+}
+
+/// Fit the trend, oldest point first, projecting the fixed thresholds and
+/// `end_of_life_percent`. `None` if it is too short to say anything.
+pub fn forecast(
+    trend: &[CapacityDataPoint],
+    end_of_life_percent: f64,
+) -> Option<DegradationForecast> {
+    let (first, last) = (trend.first()?, trend.last()?);
+    let days = |date: DateTime<Utc>| (date - first.date).num_seconds() as f64 / 86400.0;
+    let span = days(last.date);
+    if trend.len() < MIN_POINTS || span < MIN_SPAN_DAYS {
+        return None;
+    }
+
+    let by_time: Vec<(f64, f64)> = trend
+        .iter()
+        .map(|p| (days(p.date), p.health_percent))
+        .collect();
+    let fit = LinearFit::fit(&by_time)?;
+    let (steepest, shallowest) = fit.slope_bounds();
+    let now = fit.at(span);
+
+    let project = |threshold: f64| -> Option<Projection> {
+        if now <= threshold {
+            return None;
+        }
+        let date = |slope: f64| {
+            fit.falls_to(threshold, slope)
+                .filter(|&x| x - span <= MAX_PROJECTION_DAYS)
+                .map(|x| first.date + Duration::seconds((x * 86400.0) as i64))
+        };
+        Some(Projection {
+            date: date(fit.slope)?,
+            earliest: date(steepest)?,
+            latest: date(shallowest),
+        })
+    };
+
+    let by_cycles: Vec<(f64, f64)> = trend
+        .iter()
+        .filter_map(|p| Some((p.cycle_count? as f64, p.health_percent)))
+        .collect();
+    let cycle_fit = LinearFit::fit(&by_cycles);
+    let project_cycles = |threshold: f64| {
+        let fit = cycle_fit?;
+        let current = by_cycles.last()?.0;
+        if fit.at(current) <= threshold {
+            return None;
+        }
+        fit.falls_to(threshold, fit.slope)
+            .map(|cycles| cycles.round() as u32)
+    };
+
+    Some(DegradationForecast {
+        fade_percent_per_month: -fit.slope * DAYS_PER_MONTH,
+        fade_percent_per_month_low: -shallowest * DAYS_PER_MONTH,
+        fade_percent_per_month_high: -steepest * DAYS_PER_MONTH,
+        r_squared: fit.r_squared,
+        points: fit.n,
+        span_days: span.round() as i64,
+        projected_80_percent: project(SERVICE_HEALTH),
+        projected_60_percent: project(REPLACE_HEALTH),
+        fade_percent_per_100_cycles: cycle_fit.map(|f| -f.slope * 100.0),
+        projected_80_percent_cycles: project_cycles(SERVICE_HEALTH),
+        projected_60_percent_cycles: project_cycles(REPLACE_HEALTH),
+        end_of_life_percent,
+        projected_end_of_life: project(end_of_life_percent),
+        projected_end_of_life_cycles: project_cycles(end_of_life_percent),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::CapacityUnit;
+
+    fn point(day: i64, health: f64, cycles: Option<u32>) -> CapacityDataPoint {
+        CapacityDataPoint {
+            date: DateTime::from_timestamp(1_700_000_000 + day * 86400, 0).unwrap(),
+            max_capacity: (health * 500.0) as u32,
+            unit: CapacityUnit::MilliwattHours,
+            health_percent: health,
+            cycle_count: cycles,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_linear_fit() {
+        let fit = LinearFit::fit(&[(0.0, 1.0), (1.0, 3.0), (2.0, 5.0)]).unwrap();
+        assert!((fit.slope - 2.0).abs() < 1e-9);
+        assert!((fit.intercept - 1.0).abs() < 1e-9);
+        assert!((fit.r_squared - 1.0).abs() < 1e-9);
+        assert_eq!(fit.slope_bounds(), (2.0, 2.0));
+
+        let noisy = LinearFit::fit(&[(0.0, 1.0), (1.0, 3.5), (2.0, 4.5), (3.0, 7.0)]).unwrap();
+        let (low, high) = noisy.slope_bounds();
+        assert!(low < noisy.slope && noisy.slope < high);
+
+        assert!(LinearFit::fit(&[(0.0, 1.0), (1.0, 2.0)]).is_none());
+        assert!(LinearFit::fit(&[(1.0, 1.0), (1.0, 2.0), (1.0, 3.0)]).is_none());
+    }
+
+    #[test]
+    fn test_forecast_projects_thresholds() {
+        // One point a month lost, give or take, over a year
+        let trend: Vec<CapacityDataPoint> = (0..=12)
+            .map(|month| {
+                let wobble = if month % 2 == 0 { 0.2 } else { -0.2 };
+                point(
+                    month * 30,
+                    95.0 - month as f64 + wobble,
+                    Some(month as u32 * 25),
+                )
+            })
+            .collect();
+        let forecast = forecast(&trend, 70.0).unwrap();
+
+        assert!((forecast.fade_percent_per_month - 1.0).abs() < 0.05);
+        assert!(forecast.fade_percent_per_month_low < forecast.fade_percent_per_month);
+        assert!(forecast.fade_percent_per_month_high > forecast.fade_percent_per_month);
+        assert_eq!(forecast.points, 13);
+        assert_eq!(forecast.span_days, 360);
+
+        // 83% now, so 80% is about three months out
+        let at_80 = forecast.projected_80_percent.unwrap();
+        let days = (at_80.date - trend[12].date).num_days();
+        assert!((80..=100).contains(&days), "{}", days);
+        assert!(at_80.earliest < at_80.date && at_80.date < at_80.latest.unwrap());
+        let at_60 = forecast.projected_60_percent.unwrap();
+        assert!(at_60.date > at_80.date);
+
+        assert!((forecast.fade_percent_per_100_cycles.unwrap() - 4.0).abs() < 0.2);
+        let cycles = forecast.projected_80_percent_cycles.unwrap();
+        assert!((370..=380).contains(&cycles), "{}", cycles);
+
+        // A pack rated to 70% reaches its end of life between the two
+        let end_of_life = forecast.projected_end_of_life.unwrap();
+        assert!(at_80.date < end_of_life.date && end_of_life.date < at_60.date);
+        assert!(forecast.projected_end_of_life_cycles.unwrap() > cycles);
+    }
+
+    #[test]
+    fn test_forecast_needs_a_falling_trend() {
+        assert!(forecast(
+            &[
+                point(0, 95.0, None),
+                point(3, 94.0, None),
+                point(6, 93.0, None)
+            ],
+            SERVICE_HEALTH
+        )
+        .is_none());
+
+        let flat: Vec<CapacityDataPoint> =
+            (0..10).map(|week| point(week * 7, 90.0, None)).collect();
+        let forecast = forecast(&flat, SERVICE_HEALTH).unwrap();
+        assert_eq!(forecast.fade_percent_per_month, 0.0);
+        assert_eq!(forecast.projected_80_percent, None);
+        assert_eq!(forecast.fade_percent_per_100_cycles, None);
+    }
+}
@@ -1,40 +1,125 @@
-use crate::battery::BatteryInfo;
-use crate::history::HistoryManager;
+use crate::battery::{self, BatteryInfo, CapacityUnit};
+use crate::charge::ChargeLimit;
+use crate::cycles::{self, CycleCounter, CycleSource};
+use crate::forecast::{self, DegradationForecast};
+use crate::history::{CapacityHistory, HistoryManager, TimeRange};
+use crate::rating::CycleRating;
+use crate::resistance::{self, InternalResistance};
+use crate::rollup::{Granularity, Rollup};
+use crate::stress::{self, StressReport};
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// Complete battery health report.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealthReport {
+    /// Pack the report describes (e.g. `BAT0` or `combined`).
+    pub battery: String,
+    /// Unit the firmware reports natively; the other one is derived from it.
+    pub capacity_unit: CapacityUnit,
     pub design_capacity_mah: Option<u32>,
     pub max_capacity_mah: Option<u32>,
     pub capacity_loss_mah: Option<i32>,
+    pub design_energy_mwh: Option<u32>,
+    pub max_energy_mwh: Option<u32>,
+    pub capacity_loss_mwh: Option<i32>,
     pub capacity_loss_percent: Option<f64>,
     pub cycle_count: Option<u32>,
+    /// Whether `cycle_count` is the firmware's or batteryctl's own count.
+    pub cycle_source: Option<CycleSource>,
+    /// Discharge throughput counted from history, if batteryctl records it.
+    pub cycle_counter: Option<CycleCounter>,
+    /// Rated cycle life the remaining cycles and condition are judged by.
+    pub cycle_rating: CycleRating,
     pub estimated_remaining_cycles: Option<u32>,
     pub condition: String,
+    /// DC internal resistance estimated from voltage sag in history.
+    pub internal_resistance: Option<InternalResistance>,
     pub health_percent: Option<f64>,
+    /// Charge thresholds configured in the firmware, if any.
+    pub charge_limit: Option<ChargeLimit>,
     pub manufacture_date: Option<String>,
     pub age_description: Option<String>,
+    pub model_name: Option<String>,
+    pub manufacturer: Option<String>,
+    pub serial_number: Option<String>,
+    pub technology: Option<String>,
+    /// When the pack the trend describes was first seen in history.
+    pub first_seen: Option<DateTime<Utc>>,
     pub capacity_trend: Vec<CapacityDataPoint>,
+    /// Days left out of the trend as outliers.
+    pub outlier_days: usize,
+    /// Health lost per 30 days, fitted over the trend.
+    pub fade_percent_per_month: Option<f64>,
+    /// When health is expected to fall to 80% (service recommended) and to
+    /// 60% (replace), if it is falling.
+    pub projected_80_percent_date: Option<DateTime<Utc>>,
+    pub projected_60_percent_date: Option<DateTime<Utc>>,
+    /// The fit behind the projections, with confidence bounds and the
+    /// projection by cycles.
+    pub forecast: Option<DegradationForecast>,
+    /// How hard the pack has been used, with `--stress`.
+    pub stress: Option<StressReport>,
 }
 
-/// A data point for capacity trending over time.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Health on one local day, from the median of that day's readings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CapacityDataPoint {
+    /// Local midnight starting the day.
     pub date: DateTime<Utc>,
-    pub max_capacity_mah: u32,
+    pub max_capacity: u32,
+    pub unit: CapacityUnit,
     pub health_percent: f64,
+    /// Quartiles of the day's readings.
+    pub health_p25: f64,
+    pub health_p75: f64,
+    /// Readings the day is built from; 0 when it comes from rollups written
+    /// before they counted them.
+    pub samples: u32,
+    /// Firmware cycle count that day, if it reports one.
+    pub cycle_count: Option<u32>,
+    /// What the day is built from. `raw` days use every reading; `hourly`
+    /// days take one median per hour, so their quartiles show how the hours
+    /// differ; a `daily` day is a rollup of the UTC day with that date,
+    /// kept only once the hourly rollups are gone.
+    #[serde(default)]
+    pub source: Granularity,
 }
 
-/// Generate a health report from current battery info and historical data.
+/// How far back the forecast fits the capacity trend unless `--since`
+/// says otherwise.
+pub const TREND_DAYS: i64 = 180;
+
+/// Days the stress analysis covers when not given a start.
+pub const STRESS_DAYS: i64 = 30;
+
+/// Days either side of a day that it is compared with, and the fewest
+/// days that comparison needs.
+const OUTLIER_WINDOW: usize = 3;
+const OUTLIER_MIN_WINDOW: usize = 4;
+
+/// A day is an outlier this many standard deviations from its neighbours'
+/// median, and at least this many points of health away, so the wobble of
+/// an otherwise flat trend stays.
+const OUTLIER_SPREADS: f64 = 3.0;
+const OUTLIER_MIN_POINTS: f64 = 1.5;
+
+/// Generate a health report from current battery info and, when given,
+/// the pack's history: its cycle counter fills in for a missing or bogus
+/// firmware cycle count, and the capacity fade over `trend` is projected
+/// forward. A trend reaching back to the first reading, or none, is only
+/// fitted over its last `TREND_DAYS`. The trend itself is only included
+/// when `trend` is given, and the stress analysis, over its last
+/// `STRESS_DAYS` unless given a start, when `stress` is.
 pub fn generate_health_report(
     info: &BatteryInfo,
     history: Option<&HistoryManager>,
+    trend: Option<TimeRange>,
+    stress: Option<TimeRange>,
 ) -> Result<HealthReport> {
-    let capacity_loss = info.capacity_loss_mah();
-    let capacity_loss_pct = match (info.max_capacity_mah, info.design_capacity_mah) {
+    let capacity_loss_pct = match info.native_capacity() {
         (Some(max), Some(design)) if design > 0 => {
             Some((1.0 - max as f64 / design as f64) * 100.0)
         }
@@ -47,40 +132,123 @@ pub fn generate_health_report(
         .and_then(|d| calculate_age_description(d));
 
     // Build capacity trend from historical data
-    let capacity_trend = if let Some(hist) = history {
-        build_capacity_trend(hist).unwrap_or_default()
-    } else {
-        Vec::new()
+    let fit_range = bounded(trend, TREND_DAYS);
+    let (points, outlier_days) = match history {
+        Some(hist) => build_capacity_trend(hist, trend.unwrap_or(fit_range)).unwrap_or_default(),
+        None => (Vec::new(), 0),
+    };
+    let fit_points: Vec<CapacityDataPoint> = points
+        .iter()
+        .filter(|p| p.date >= fit_range.start - Duration::days(1))
+        .cloned()
+        .collect();
+    let cycle_rating = info.cycle_rating();
+    let forecast = forecast::forecast(&fit_points, cycle_rating.end_of_life_percent);
+    let capacity_trend = if trend.is_some() { points } else { Vec::new() };
+    let first_seen = history
+        .filter(|_| trend.is_some())
+        .and_then(|hist| hist.selected_install().ok().flatten())
+        .map(|install| install.timestamp);
+
+    let cycle_counter = history.and_then(|hist| hist.cycle_counter().ok().flatten());
+    let cycles = cycles::effective_cycles(info.cycle_count, cycle_counter.as_ref());
+    let internal_resistance = history
+        .and_then(|hist| hist.resistance_samples().ok())
+        .and_then(|samples| resistance::summarize(&samples));
+    let stress = match (history, stress) {
+        (Some(hist), Some(range)) => stress::analyze(&hist.get_raw_snapshots(stress_range(range))?),
+        _ => None,
+    };
+    let condition = match cycles {
+        Some((count, CycleSource::Throughput)) => {
+            let (max, design) = info.native_capacity();
+            battery::determine_condition(max, design, Some(count), &cycle_rating)
+        }
+        _ => info.condition,
     };
 
     Ok(HealthReport {
+        battery: info.name.clone(),
+        capacity_unit: info.capacity_unit,
         design_capacity_mah: info.design_capacity_mah,
         max_capacity_mah: info.max_capacity_mah,
-        capacity_loss_mah: capacity_loss,
+        capacity_loss_mah: info.capacity_loss_mah(),
+        design_energy_mwh: info.design_energy_mwh,
+        max_energy_mwh: info.max_energy_mwh,
+        capacity_loss_mwh: info.capacity_loss_mwh(),
         capacity_loss_percent: capacity_loss_pct,
-        cycle_count: info.cycle_count,
-        estimated_remaining_cycles: info.estimated_remaining_cycles(),
-        condition: info.condition.to_string(),
+        cycle_count: cycles.map(|(count, _)| count),
+        cycle_source: cycles.map(|(_, source)| source),
+        cycle_counter,
+        estimated_remaining_cycles: cycles.map(|(count, _)| cycle_rating.remaining_cycles(count)),
+        cycle_rating,
+        condition: condition.to_string(),
+        internal_resistance,
         health_percent: info.health_percent(),
+        charge_limit: info.charge_limit,
         manufacture_date: info.manufacture_date.clone(),
         age_description,
+        model_name: info.model_name.clone(),
+        manufacturer: info.manufacturer.clone(),
+        serial_number: info.serial_number.clone(),
+        technology: info.technology.clone(),
+        first_seen,
         capacity_trend,
+        outlier_days,
+        fade_percent_per_month: forecast.as_ref().map(|f| f.fade_percent_per_month),
+        projected_80_percent_date: forecast
+            .as_ref()
+            .and_then(|f| f.projected_80_percent.as_ref())
+            .map(|p| p.date),
+        projected_60_percent_date: forecast
+            .as_ref()
+            .and_then(|f| f.projected_60_percent.as_ref())
+            .map(|p| p.date),
+        forecast,
+        stress,
     })
 }
 
-/// Compare current capacity with a new battery of the same design.
-pub fn compare_with_new(info: &BatteryInfo) -> Vec<(String, String, String)> {
+/// Period `health --stress` analyses for the given `--since` / `--until`.
+pub fn stress_range(range: TimeRange) -> TimeRange {
+    bounded(Some(range), STRESS_DAYS)
+}
+
+/// `range` itself if it has a start, else its last `days` (up to now
+/// without it).
+fn bounded(range: Option<TimeRange>, days: i64) -> TimeRange {
+    match range {
+        Some(range) if !range.is_open_start() => range,
+        _ => {
+            let end = range.and_then(|range| range.end);
+            TimeRange {
+                start: end.unwrap_or_else(Utc::now) - Duration::days(days),
+                end,
+                last: None,
+            }
+        }
+    }
+}
+
+/// Compare a report with a new battery of the same design. Cycles and
+/// condition are the report's, so counted cycles stand in for the
+/// firmware's here too.
+pub fn compare_with_new(report: &HealthReport) -> Vec<(String, String, String)> {
     let mut comparisons = Vec::new();
 
-    if let (Some(max), Some(design)) = (info.max_capacity_mah, info.design_capacity_mah) {
+    let (max, design) = match report.capacity_unit {
+        CapacityUnit::MilliampHours => (report.max_capacity_mah, report.design_capacity_mah),
+        CapacityUnit::MilliwattHours => (report.max_energy_mwh, report.design_energy_mwh),
+    };
+    if let (Some(max), Some(design)) = (max, design) {
         comparisons.push((
             "Max Capacity".to_string(),
-            format!("{} mAh", design),
-            format!("{} mAh", max),
+            format!("{} {}", design, report.capacity_unit),
+            format!("{} {}", max, report.capacity_unit),
         ));
     }
 
-    if let Some(health) = info.health_percent() {
+    if let Some(health) = report.health_percent {
         comparisons.push((
             "Health".to_string(),
             "100.0%".to_string(),
@@ -88,7 +256,7 @@ pub fn compare_with_new(info: &BatteryInfo) -> Vec<(String, String, String)> {
         ));
     }
 
-    if let Some(cycles) = info.cycle_count {
+    if let Some(cycles) = report.cycle_count {
         comparisons.push((
             "Cycle Count".to_string(),
             "0".to_string(),
@@ -99,7 +267,7 @@ pub fn compare_with_new(info: &BatteryInfo) -> Vec<(String, String, String)> {
     comparisons.push((
         "Condition".to_string(),
         "Normal".to_string(),
-        info.condition.to_string(),
+        report.condition.clone(),
     ));
 
     comparisons
@@ -140,57 +308,195 @@ fn calculate_age_description(date_str: &str) -> Option<String> {
     })
 }
 
-fn build_capacity_trend(history: &HistoryManager) -> Result<Vec<CapacityDataPoint>> {
-    // Get snapshots grouped by day over the last 6 months
-    let snapshots = history.get_snapshots_range(
-        chrono::Duration::days(180),
-    )?;
+/// Readings of health aggregated into one point per local day, with days
+/// that stand out from their neighbours dropped. Returns the points and the
+/// number of days dropped.
+fn build_capacity_trend(
+    history: &HistoryManager,
+    range: TimeRange,
+) -> Result<(Vec<CapacityDataPoint>, usize)> {
+    let readings = history.capacity_history(range)?;
+    Ok(reject_outliers(daily_capacity(&readings)))
+}
+
+/// Health readings falling on one local day.
+#[derive(Default)]
+struct DayReadings {
+    health: Vec<f64>,
+    capacity: Vec<(f64, CapacityUnit)>,
+    samples: u32,
+    cycle_count: Option<u32>,
+    source: Granularity,
+}
 
-    if snapshots.is_empty() {
-        return Ok(Vec::new());
+impl DayReadings {
+    fn add(&mut self, health: f64, capacity: f64, unit: CapacityUnit, samples: u32) {
+        self.health.push(health);
+        self.capacity.push((capacity, unit));
+        self.samples += samples;
     }
 
-    // Group by date and pick the max_capacity value per day
-    let mut daily: std::collections::BTreeMap<String, (u32, u32)> =
-        std::collections::BTreeMap::new();
+    fn finish(mut self, midnight: DateTime<Utc>) -> Option<CapacityDataPoint> {
+        let (_, unit) = *self.capacity.last()?;
+        let mut capacity: Vec<f64> = self
+            .capacity
+            .iter()
+            .filter(|(_, u)| *u == unit)
+            .map(|(c, _)| *c)
+            .collect();
+        self.health.sort_by(f64::total_cmp);
+        capacity.sort_by(f64::total_cmp);
+        Some(CapacityDataPoint {
+            date: midnight,
+            max_capacity: percentile(&capacity, 50.0).round() as u32,
+            unit,
+            health_percent: percentile(&self.health, 50.0),
+            health_p25: percentile(&self.health, 25.0),
+            health_p75: percentile(&self.health, 75.0),
+            samples: self.samples,
+            cycle_count: self.cycle_count,
+            source: self.source,
+        })
+    }
+}
 
-    for snap in &snapshots {
-        let date_key = snap.timestamp.format("%Y-%m-%d").to_string();
-        if let Some(max_cap) = snap.max_capacity {
-            let design = snap.design_capacity.unwrap_or(max_cap);
-            daily.entry(date_key).or_insert((max_cap, design));
-        }
+/// A rollup's median health and the capacity it stands for, in its native
+/// unit. Rollups from before quartiles were kept fall back on their last
+/// reading.
+fn rollup_health(rollup: &Rollup) -> Option<(f64, f64, CapacityUnit)> {
+    let snap = rollup.to_snapshot();
+    let (max, design, unit) = snap.native_capacity()?;
+    let health = rollup.health_median.or_else(|| snap.health_percent())?;
+    let capacity = design.map_or(max as f64, |d| d as f64 * health / 100.0);
+    Some((health, capacity, unit))
+}
+
+fn local_midnight(day: NaiveDate) -> Option<DateTime<Utc>> {
+    let midnight = Local
+        .from_local_datetime(&day.and_hms_opt(0, 0, 0)?)
+        .earliest()?;
+    Some(midnight.with_timezone(&Utc))
+}
+
+/// One point per local day: the median of the day's health readings, with
+/// the quartiles showing how much they scattered. Before the raw rows, each
+/// hourly rollup adds its median to the local day it falls on; before
+/// those, a daily rollup gives its own quartiles.
+fn daily_capacity(history: &CapacityHistory) -> Vec<CapacityDataPoint> {
+    let local_day = |t: DateTime<Utc>| t.with_timezone(&Local).date_naive();
+    let mut days: BTreeMap<NaiveDate, DayReadings> = BTreeMap::new();
+
+    for rollup in &history.hourly {
+        let Some((health, capacity, unit)) = rollup_health(rollup) else {
+            continue;
+        };
+        // Mid-hour, so an hour split by a half-hour offset lands on one day
+        let middle = DateTime::from_timestamp(rollup.bucket + 1800, 0).unwrap_or_default();
+        let day = days.entry(local_day(middle)).or_default();
+        day.add(health, capacity, unit, rollup.health_samples);
+        day.cycle_count = rollup.cycle_count.or(day.cycle_count);
+        day.source = Granularity::Hourly;
+    }
+    for snap in &history.raw {
+        let (Some(health), Some((max, _, unit))) = (snap.health_percent(), snap.native_capacity())
+        else {
+            continue;
+        };
+        let day = days.entry(local_day(snap.timestamp)).or_default();
+        day.add(health, max as f64, unit, 1);
+        day.cycle_count = snap.cycle_count.or(day.cycle_count);
     }
 
-    let trend: Vec<CapacityDataPoint> = daily
+    let mut points: BTreeMap<NaiveDate, CapacityDataPoint> = days
         .into_iter()
-        .filter_map(|(date_str, (max_cap, design_cap))| {
-            let date = chrono::NaiveDate::parse_from_str(&date_str, "%Y-%m-%d").ok()?;
-            let dt = date.and_hms_opt(0, 0, 0)?;
-            let utc = DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc);
-            let health = if design_cap > 0 {
-                max_cap as f64 / design_cap as f64 * 100.0
-            } else {
-                100.0
-            };
-            Some(CapacityDataPoint {
-                date: utc,
-                max_capacity_mah: max_cap,
+        .filter_map(|(day, readings)| Some((day, readings.finish(local_midnight(day)?)?)))
+        .collect();
+
+    // A daily rollup covers a UTC day, so it only stands in for the local
+    // day of the same date when nothing finer does
+    for rollup in &history.daily {
+        let Some(day) = DateTime::from_timestamp(rollup.bucket, 0).map(|t| t.date_naive()) else {
+            continue;
+        };
+        if points.contains_key(&day) {
+            continue;
+        }
+        let (Some((health, capacity, unit)), Some(midnight)) =
+            (rollup_health(rollup), local_midnight(day))
+        else {
+            continue;
+        };
+        points.insert(
+            day,
+            CapacityDataPoint {
+                date: midnight,
+                max_capacity: capacity.round() as u32,
+                unit,
                 health_percent: health,
-            })
-        })
+                health_p25: rollup.health_p25.unwrap_or(health),
+                health_p75: rollup.health_p75.unwrap_or(health),
+                samples: rollup.health_samples,
+                cycle_count: rollup.cycle_count,
+                source: Granularity::Daily,
+            },
+        );
+    }
+    points.into_values().collect()
+}
+
+/// Drop days whose health is far from the median of the days around them:
+/// the firmware's capacity estimate jumps around recalibrations and settles
+/// back within days. A lasting step moves the median with it and is kept.
+fn reject_outliers(points: Vec<CapacityDataPoint>) -> (Vec<CapacityDataPoint>, usize) {
+    let health: Vec<f64> = points.iter().map(|p| p.health_percent).collect();
+    let outlier = |i: usize| {
+        let window =
+            &health[i.saturating_sub(OUTLIER_WINDOW)..(i + OUTLIER_WINDOW + 1).min(health.len())];
+        if window.len() < OUTLIER_MIN_WINDOW {
+            return false;
+        }
+        let mut sorted = window.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let median = percentile(&sorted, 50.0);
+        let mut deviations: Vec<f64> = sorted.iter().map(|h| (h - median).abs()).collect();
+        deviations.sort_by(f64::total_cmp);
+        // Scaled so it estimates the standard deviation of normal noise
+        let spread = percentile(&deviations, 50.0) * 1.4826;
+        (health[i] - median).abs() > (OUTLIER_SPREADS * spread).max(OUTLIER_MIN_POINTS)
+    };
+
+    let keep: Vec<bool> = (0..points.len()).map(|i| !outlier(i)).collect();
+    let dropped = keep.iter().filter(|&&k| !k).count();
+    let kept = points
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(p, k)| k.then_some(p))
         .collect();
+    (kept, dropped)
+}
 
-    Ok(trend)
+/// `p`th percentile of sorted values, interpolating between neighbours;
+/// 0 for no values.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    match sorted.len() {
+        0 => 0.0,
+        n => {
+            let rank = p / 100.0 * (n - 1) as f64;
+            let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+            sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::battery::{BatteryCondition, ChargingState};
+    use crate::history::BatterySnapshot;
 
     fn make_test_info() -> BatteryInfo {
         BatteryInfo {
+            name: "BAT0".to_string(),
             level: 87,
             state: ChargingState::Charging,
             time_remaining_minutes: Some(83),
@@ -204,13 +510,14 @@ mod tests {
             condition: BatteryCondition::Normal,
             manufacture_date: Some("2024-03-15".to_string()),
             is_present: true,
+            ..Default::default()
         }
     }
 
     #[test]
     fn test_generate_health_report() {
         let info = make_test_info();
-        let report = generate_health_report(&info, None).unwrap();
+        let report = generate_health_report(&info, None, None, None).unwrap();
         assert_eq!(report.design_capacity_mah, Some(4500));
         assert_eq!(report.max_capacity_mah, Some(4215));
         assert_eq!(report.capacity_loss_mah, Some(285));
@@ -219,10 +526,239 @@ mod tests {
         assert_eq!(report.condition, "Normal");
     }
 
+    #[test]
+    fn test_health_report_uses_energy_units() {
+        let info = BatteryInfo {
+            max_capacity_mah: None,
+            design_capacity_mah: None,
+            max_energy_mwh: Some(50160),
+            design_energy_mwh: Some(57000),
+            capacity_unit: CapacityUnit::MilliwattHours,
+            ..make_test_info()
+        };
+        let report = generate_health_report(&info, None, None, None).unwrap();
+        assert_eq!(report.capacity_unit, CapacityUnit::MilliwattHours);
+        assert_eq!(report.capacity_loss_mwh, Some(6840));
+        assert_eq!(report.capacity_loss_mah, None);
+        assert!((report.capacity_loss_percent.unwrap() - 12.0).abs() < 0.01);
+
+        let comparison = compare_with_new(&report);
+        assert_eq!(comparison[0].1, "57000 mWh");
+    }
+
+    #[test]
+    fn test_counted_cycles_stand_in_for_firmware() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let info = BatteryInfo {
+            state: ChargingState::Discharging,
+            cycle_count: None,
+            max_capacity_mah: None,
+            design_capacity_mah: None,
+            ..make_test_info()
+        };
+        // Ten full discharges
+        for i in 0..=20 {
+            let level = if i % 2 == 0 { 100 } else { 0 };
+            let state = if i % 2 == 0 {
+                ChargingState::Discharging
+            } else {
+                ChargingState::Charging
+            };
+            let reading = BatteryInfo {
+                level,
+                state,
+                ..info.clone()
+            };
+            hist.record_snapshot_at(now - 3600 * (20 - i), &reading)
+                .unwrap();
+        }
+        let hist = hist.with_battery(Some("BAT0"));
+
+        let report = generate_health_report(&info, Some(&hist), None, None).unwrap();
+        assert_eq!(report.cycle_count, Some(10));
+        assert_eq!(report.cycle_source, Some(CycleSource::Throughput));
+        assert_eq!(report.estimated_remaining_cycles, Some(990));
+        assert!(report.capacity_trend.is_empty());
+        let comparison = compare_with_new(&report);
+        assert!(comparison.contains(&(
+            "Cycle Count".to_string(),
+            "0".to_string(),
+            "10".to_string()
+        )));
+
+        // A plausible firmware count wins
+        let firmware = BatteryInfo {
+            cycle_count: Some(47),
+            ..info
+        };
+        let report = generate_health_report(&firmware, Some(&hist), None, None).unwrap();
+        assert_eq!(report.cycle_count, Some(47));
+        assert_eq!(report.cycle_source, Some(CycleSource::Firmware));
+    }
+
+    #[test]
+    fn test_daily_median_rejects_outliers() {
+        // Five readings a day, one of them a glitch; a recalibration spike on
+        // day 8 and a lasting step up from day 14
+        let start = Local.with_ymd_and_hms(2026, 6, 1, 9, 0, 0).unwrap();
+        let mut readings = Vec::new();
+        for day in 0..21 {
+            let mut health = 90.0 - 0.05 * day as f64;
+            if day == 8 {
+                health += 6.0;
+            } else if day >= 14 {
+                health += 3.0;
+            }
+            for (hour, offset) in [(0, 0.0), (1, -0.1), (2, 0.1), (3, 0.0), (4, 9.0)] {
+                readings.push(BatterySnapshot {
+                    timestamp: (start + Duration::days(day) + Duration::hours(hour))
+                        .with_timezone(&Utc),
+                    max_capacity: Some(((health + offset) * 50.0).round() as u32),
+                    design_capacity: Some(5000),
+                    capacity_unit: Some(CapacityUnit::MilliampHours),
+                    cycle_count: Some(100 + day as u32),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let days = daily_capacity(&CapacityHistory {
+            raw: readings,
+            ..Default::default()
+        });
+        assert_eq!(days.len(), 21);
+        assert_eq!(days[0].source, Granularity::Raw);
+        assert!((days[0].health_percent - 90.0).abs() < 0.05);
+        assert_eq!(days[0].max_capacity, 4500);
+        assert_eq!(days[0].samples, 5);
+        assert!(
+            days[0].health_p25 <= days[0].health_percent
+                && days[0].health_percent < days[0].health_p75
+        );
+        assert_eq!(
+            days[0].date,
+            start
+                .date_naive()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_local_timezone(Local)
+                .unwrap()
+        );
+        assert_eq!(days[20].cycle_count, Some(120));
+
+        let (trend, dropped) = reject_outliers(days);
+        assert_eq!(dropped, 1);
+        assert_eq!(trend.len(), 20);
+        assert!(trend.iter().all(|p| p.health_percent < 93.0));
+        assert!((trend[13].health_percent - 92.3).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_daily_capacity_from_rollups() {
+        let rollup = |bucket: i64, quartiles: Option<[f64; 3]>, samples: u32| Rollup {
+            bucket,
+            battery: "BAT0".to_string(),
+            max_capacity: Some(4400),
+            design_capacity: Some(5000),
+            capacity_unit: Some(CapacityUnit::MilliampHours),
+            health_p25: quartiles.map(|q| q[0]),
+            health_median: quartiles.map(|q| q[1]),
+            health_p75: quartiles.map(|q| q[2]),
+            health_samples: samples,
+            ..Default::default()
+        };
+        let day = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let utc_midnight =
+            |date: NaiveDate| date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+
+        // Three hours of one local day, whatever the timezone
+        let morning = Local
+            .with_ymd_and_hms(2026, 6, 1, 10, 0, 0)
+            .unwrap()
+            .timestamp();
+        let hourly: Vec<Rollup> = [90.0, 91.0, 92.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &h)| rollup(morning + i as i64 * 3600, Some([h - 1.0, h, h + 1.0]), 12))
+            .collect();
+        let daily = vec![
+            // From before quartiles were kept: the last reading stands in
+            rollup(utc_midnight(day(2026, 5, 19)), None, 0),
+            rollup(utc_midnight(day(2026, 5, 20)), Some([88.0, 89.0, 90.0]), 48),
+            // Covered by the hourly rollups
+            rollup(utc_midnight(day(2026, 6, 1)), Some([50.0, 50.0, 50.0]), 48),
+        ];
+
+        let points = daily_capacity(&CapacityHistory {
+            daily,
+            hourly,
+            raw: Vec::new(),
+        });
+        assert_eq!(points.len(), 3);
+
+        assert_eq!(points[0].source, Granularity::Daily);
+        assert_eq!(points[0].date, local_midnight(day(2026, 5, 19)).unwrap());
+        assert_eq!((points[0].health_percent, points[0].samples), (88.0, 0));
+        assert_eq!((points[0].health_p25, points[0].health_p75), (88.0, 88.0));
+
+        assert_eq!(points[1].date, local_midnight(day(2026, 5, 20)).unwrap());
+        assert_eq!(
+            (points[1].health_p25, points[1].health_percent),
+            (88.0, 89.0)
+        );
+        assert_eq!((points[1].max_capacity, points[1].samples), (4450, 48));
+
+        assert_eq!(points[2].source, Granularity::Hourly);
+        assert_eq!(points[2].date, local_midnight(day(2026, 6, 1)).unwrap());
+        assert_eq!(points[2].health_percent, 91.0);
+        assert_eq!((points[2].health_p25, points[2].health_p75), (90.5, 91.5));
+        assert_eq!((points[2].max_capacity, points[2].samples), (4550, 36));
+    }
+
+    #[test]
+    fn test_internal_resistance_from_history() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let info = BatteryInfo {
+            state: ChargingState::Discharging,
+            ..make_test_info()
+        };
+        // Alternating between 0.5 A and 2 A every two minutes, 100 mΩ
+        for i in 0..6 {
+            let (watts, millivolts) = if i % 2 == 0 {
+                (6.0, 12000.0)
+            } else {
+                (23.7, 11850.0)
+            };
+            let reading = BatteryInfo {
+                power_draw_watts: Some(watts),
+                voltage_mv: Some(millivolts),
+                ..info.clone()
+            };
+            hist.record_snapshot_at(now - 120 * (6 - i), &reading)
+                .unwrap();
+        }
+        let hist = hist.with_battery(Some("BAT0"));
+        assert_eq!(hist.resistance_samples().unwrap().len(), 5);
+
+        let report = generate_health_report(&info, Some(&hist), None, None).unwrap();
+        let resistance = report.internal_resistance.unwrap();
+        assert!((resistance.resistance_mohm - 100.0).abs() < 1.0);
+        assert_eq!(resistance.samples, 5);
+        assert_eq!(resistance.baseline, None);
+        assert!(generate_health_report(&info, None, None, None)
+            .unwrap()
+            .internal_resistance
+            .is_none());
+    }
+
     #[test]
     fn test_compare_with_new() {
-        let info = make_test_info();
-        let comparison = compare_with_new(&info);
+        let report = generate_health_report(&make_test_info(), None, None, None).unwrap();
+        let comparison = compare_with_new(&report);
         assert!(!comparison.is_empty());
         assert_eq!(comparison[0].0, "Max Capacity");
     }
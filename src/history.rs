@@ -1,21 +1,213 @@
 use anyhow::{Context, Result};
-use chrono::{DateTime, Duration, Utc};
-use rusqlite::{params, Connection};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use rusqlite::backup::Backup;
+use rusqlite::{params, Connection, ErrorCode, OpenFlags, TransactionBehavior};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-use crate::battery::BatteryInfo;
+use crate::battery::{BatteryInfo, CapacityUnit, ChargingState};
+use crate::config::RetentionPolicy;
+use crate::cycles::CycleCounter;
+use crate::export::ExportFormat;
+use crate::migrations;
+use crate::resistance::{self, ResistanceSample};
+use crate::rollup::{self, Granularity, Rollup};
+use crate::sleep;
 
 /// A historical snapshot of battery state.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BatterySnapshot {
     pub timestamp: DateTime<Utc>,
+    /// Pack the row describes; `None` for rows recorded before packs were tracked.
+    #[serde(default)]
+    pub battery: Option<String>,
+    /// Machine the row was recorded on; `None` for this one.
+    #[serde(default)]
+    pub host: Option<String>,
     pub level: u8,
+    /// On AC power (charging or full); kept for rows without `state`.
     pub is_charging: bool,
+    /// `None` for rows recorded before the full state was stored.
+    #[serde(default)]
+    pub state: Option<ChargingState>,
     pub power_draw: Option<f64>,
     pub cycle_count: Option<u32>,
+    /// Full-charge capacity in mAh.
     pub max_capacity: Option<u32>,
+    /// Design capacity in mAh.
     pub design_capacity: Option<u32>,
+    /// Full-charge energy in mWh.
+    #[serde(default)]
+    pub max_energy: Option<u32>,
+    /// Design energy in mWh.
+    #[serde(default)]
+    pub design_energy: Option<u32>,
+    /// Unit the firmware reported natively; `None` for rows recorded before
+    /// units were tracked, whose `max_capacity` may hold mWh.
+    #[serde(default)]
+    pub capacity_unit: Option<CapacityUnit>,
+    /// Identity of the physical pack at the time of the reading.
+    #[serde(default)]
+    pub model_name: Option<String>,
+    #[serde(default)]
+    pub manufacturer: Option<String>,
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    #[serde(default)]
+    pub technology: Option<String>,
+    #[serde(default)]
+    pub temperature_celsius: Option<f64>,
+    #[serde(default)]
+    pub voltage_mv: Option<f64>,
+    /// Remaining charge in mAh.
+    #[serde(default)]
+    pub current_capacity: Option<u32>,
+    /// Remaining energy in mWh.
+    #[serde(default)]
+    pub current_energy: Option<u32>,
+    #[serde(default)]
+    pub time_remaining_minutes: Option<i64>,
+}
+
+impl BatterySnapshot {
+    /// The recorded state, or one inferred from `is_charging` for older rows.
+    pub fn effective_state(&self) -> ChargingState {
+        match self.state {
+            Some(state) if state != ChargingState::Unknown => state,
+            _ if self.is_charging => ChargingState::Charging,
+            _ => ChargingState::Discharging,
+        }
+    }
+
+    /// The reading as a `BatteryInfo`, for reports built from history
+    /// rather than a live reading.
+    pub fn to_battery_info(&self) -> BatteryInfo {
+        let capacity_unit = self.capacity_unit.unwrap_or_default();
+        let (max, design) = match capacity_unit {
+            CapacityUnit::MilliwattHours => (self.max_energy, self.design_energy),
+            CapacityUnit::MilliampHours => (self.max_capacity, self.design_capacity),
+        };
+        let rating = crate::rating::rating_for(crate::rating::PackIdentity {
+            technology: self.technology.as_deref(),
+            manufacturer: self.manufacturer.as_deref(),
+            model: self.model_name.as_deref(),
+        });
+        BatteryInfo {
+            name: self.battery.clone().unwrap_or_default(),
+            level: self.level,
+            state: self.effective_state(),
+            time_remaining_minutes: self.time_remaining_minutes,
+            power_draw_watts: self.power_draw,
+            cycle_count: self.cycle_count,
+            max_capacity_mah: self.max_capacity,
+            design_capacity_mah: self.design_capacity,
+            current_capacity_mah: self.current_capacity,
+            max_energy_mwh: self.max_energy,
+            design_energy_mwh: self.design_energy,
+            current_energy_mwh: self.current_energy,
+            capacity_unit,
+            temperature_celsius: self.temperature_celsius,
+            voltage_mv: self.voltage_mv,
+            condition: crate::battery::determine_condition(max, design, self.cycle_count, &rating),
+            model_name: self.model_name.clone(),
+            manufacturer: self.manufacturer.clone(),
+            serial_number: self.serial_number.clone(),
+            technology: self.technology.clone(),
+            is_present: true,
+            ..Default::default()
+        }
+    }
+
+    /// Max capacity as a percentage of design, in the row's native unit. A
+    /// row without a design capacity counts as 100%.
+    pub fn health_percent(&self) -> Option<f64> {
+        let (max, design, _) = self.native_capacity()?;
+        let design = design.unwrap_or(max);
+        Some(if design > 0 {
+            max as f64 / design as f64 * 100.0
+        } else {
+            100.0
+        })
+    }
+
+    /// Max and design capacity in the row's native unit.
+    pub fn native_capacity(&self) -> Option<(u32, Option<u32>, CapacityUnit)> {
+        match self.capacity_unit {
+            Some(CapacityUnit::MilliwattHours) => Some((
+                self.max_energy?,
+                self.design_energy,
+                CapacityUnit::MilliwattHours,
+            )),
+            _ => Some((
+                self.max_capacity?,
+                self.design_capacity,
+                CapacityUnit::MilliampHours,
+            )),
+        }
+    }
+}
+
+/// What a capacity trend is built from, oldest first: daily rollups from
+/// before the hourly ones are kept, then hourly rollups from before the raw
+/// rows are, then the raw rows.
+#[derive(Debug, Clone, Default)]
+pub struct CapacityHistory {
+    pub daily: Vec<Rollup>,
+    pub hourly: Vec<Rollup>,
+    pub raw: Vec<BatterySnapshot>,
+}
+
+/// Event kind recorded when a pack is first seen or replaced.
+pub const EVENT_INSTALLED: &str = "installed";
+
+/// Detail of the install event recorded for a pack's oldest row.
+const FIRST_SEEN: &str = "first seen";
+
+/// Something that happened to a pack, such as being installed, or to the
+/// machine, such as going to sleep.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatteryEvent {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub battery: String,
+    pub kind: String,
+    pub serial_number: Option<String>,
+    pub model_name: Option<String>,
+    /// Why the event was recorded (e.g. `serial number changed from 1234 to 5678`).
+    pub detail: Option<String>,
+    /// Machine the event was recorded on; `None` for this one.
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+/// Which physical pack's readings history queries cover.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PackScope {
+    /// The pack installed most recently.
+    #[default]
+    Current,
+    /// The pack recorded by the `installed` event with this id.
+    Install(i64),
+    /// Every reading, across replacements.
+    All,
+}
+
+impl PackScope {
+    /// Parse `current`, `all`, or an install id such as `3` or `#3`.
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "current" => Ok(PackScope::Current),
+            "all" => Ok(PackScope::All),
+            other => other
+                .trim_start_matches('#')
+                .parse()
+                .map(PackScope::Install)
+                .map_err(|_| {
+                    anyhow::anyhow!("Invalid pack '{}'. Use current, all, or a pack number", s)
+                }),
+        }
+    }
 }
 
 /// Summary statistics for a time period.
@@ -29,19 +221,140 @@ pub struct HistorySummary {
     pub charging_periods: u32,
     pub total_charging_minutes: i64,
     pub total_discharging_minutes: i64,
+    /// Time on AC power without charging (full, or held by a charge limit).
+    #[serde(default)]
+    pub total_not_charging_minutes: i64,
+    /// Time in suspend gaps between readings (asleep or off).
+    #[serde(default)]
+    pub total_sleep_minutes: i64,
     pub avg_discharge_rate_watts: Option<f64>,
     pub estimated_cycles: f64,
+    #[serde(default)]
+    pub avg_temperature_celsius: Option<f64>,
+    #[serde(default)]
+    pub max_temperature_celsius: Option<f64>,
+    /// Resolution of the rows the summary was computed from.
+    #[serde(default)]
+    pub granularity: Granularity,
+}
+
+/// File size around a `VACUUM`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct VacuumReport {
+    pub size_before: Option<u64>,
+    pub size_after: Option<u64>,
+}
+
+/// Overview of what the database holds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryStats {
+    pub path: Option<PathBuf>,
+    pub file_size_bytes: Option<u64>,
+    pub schema_version: u32,
+    pub snapshots: usize,
+    pub hourly_rollups: usize,
+    pub daily_rollups: usize,
+    pub events: usize,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    pub packs: Vec<PackStats>,
+    /// Periods with no snapshots at all, oldest first.
+    pub gaps: Vec<Gap>,
+}
+
+/// Raw snapshot counts for one pack.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackStats {
+    /// `None` for this machine.
+    pub host: Option<String>,
+    /// `None` for rows recorded before packs were tracked.
+    pub battery: Option<String>,
+    pub snapshots: usize,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gap {
+    pub host: Option<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub minutes: i64,
+}
+
+/// Result of `HistoryManager::check`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub ok: bool,
+    /// Output of SQLite's `PRAGMA integrity_check` (`ok` when healthy).
+    pub sqlite: String,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityIssue {
+    /// `out_of_order`, `impossible_value` or `duplicate`.
+    pub kind: String,
+    pub timestamp: DateTime<Utc>,
+    pub host: Option<String>,
+    pub battery: Option<String>,
+    pub detail: String,
+}
+
+impl IntegrityIssue {
+    fn new(
+        kind: &str,
+        timestamp: i64,
+        host: Option<String>,
+        battery: Option<String>,
+        detail: String,
+    ) -> Self {
+        Self {
+            kind: kind.to_string(),
+            timestamp: DateTime::from_timestamp(timestamp, 0).unwrap_or_default(),
+            host,
+            battery,
+            detail,
+        }
+    }
+}
+
+/// Rows removed by a retention pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PruneCounts {
+    pub raw: usize,
+    pub hourly: usize,
+    pub daily: usize,
+}
+
+/// Rows added by `HistoryManager::import_snapshots` or `merge`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportCounts {
+    pub snapshots: usize,
+    /// Snapshots already in the database (same host, pack and timestamp),
+    /// or in the rollup of an hour whose raw rows are pruned.
+    pub duplicates: usize,
+    pub events: usize,
+    pub rollups: usize,
 }
 
 /// Manages the SQLite history database.
 pub struct HistoryManager {
     conn: Connection,
+    host: Option<String>,
+    battery: Option<String>,
+    pack: PackScope,
+    retention: RetentionPolicy,
 }
 
 impl HistoryManager {
-    /// Open or create the history database.
-    pub fn open() -> Result<Self> {
-        let db_path = get_db_path()?;
+    /// Open or create the history database at `db`, or at the default
+    /// location when `None` (see `get_db_path`).
+    pub fn open(db: Option<&Path>) -> Result<Self> {
+        let db_path = match db {
+            Some(path) => path.to_path_buf(),
+            None => get_db_path()?,
+        };
 
         // Ensure parent directory exists
         if let Some(parent) = db_path.parent() {
@@ -49,188 +362,726 @@ impl HistoryManager {
                 .with_context(|| format!("Failed to create directory {}", parent.display()))?;
         }
 
-        let conn = Connection::open(&db_path)
-            .with_context(|| format!("Failed to open database at {}", db_path.display()))?;
+        Self::open_at(&db_path)
+    }
 
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS snapshots (
-                timestamp INTEGER NOT NULL,
-                level INTEGER NOT NULL,
-                is_charging BOOLEAN NOT NULL,
-                power_draw REAL,
-                cycle_count INTEGER,
-                max_capacity INTEGER,
-                design_capacity INTEGER
-            );
-            CREATE INDEX IF NOT EXISTS idx_snapshots_timestamp ON snapshots(timestamp);",
-        )
-        .context("Failed to initialize database schema")?;
-
-        Ok(Self { conn })
-    }
-
-    /// Open with a specific path (for testing).
-    pub fn open_at(path: &std::path::Path) -> Result<Self> {
-        let conn = Connection::open(path)?;
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS snapshots (
-                timestamp INTEGER NOT NULL,
-                level INTEGER NOT NULL,
-                is_charging BOOLEAN NOT NULL,
-                power_draw REAL,
-                cycle_count INTEGER,
-                max_capacity INTEGER,
-                design_capacity INTEGER
-            );
-            CREATE INDEX IF NOT EXISTS idx_snapshots_timestamp ON snapshots(timestamp);",
-        )?;
-        Ok(Self { conn })
+    /// Open or create the database at `path`, applying pending migrations.
+    ///
+    /// The database is put in WAL mode, so readers (`status --watch`, the
+    /// alert monitor) and a writer (a cron `record`) can use it at the same
+    /// time. Writers wait for each other up to `BUSY_TIMEOUT`, and the
+    /// writes batteryctl makes on its own are retried beyond that.
+    pub fn open_at(path: &Path) -> Result<Self> {
+        let mut conn = Connection::open(path)
+            .with_context(|| format!("Failed to open database at {}", path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // Take the write lock when a transaction starts, so it waits its
+        // turn instead of failing when its first write finds another
+        // writer got there first
+        conn.set_transaction_behavior(TransactionBehavior::Immediate);
+
+        retry_busy(|| {
+            conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| {
+                row.get::<_, String>(0)
+            })?;
+            migrations::migrate(&conn)
+        })?;
+        // Safe with WAL: a power cut may lose the last commits, never
+        // corrupt the file
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+
+        Ok(Self {
+            conn,
+            host: None,
+            battery: None,
+            pack: PackScope::Current,
+            retention: RetentionPolicy::default(),
+        })
+    }
+
+    /// Restrict queries to rows merged from another machine. `None` (the
+    /// default) is this machine's own history.
+    pub fn with_host(mut self, host: Option<&str>) -> Self {
+        self.host = host.map(|h| h.to_string());
+        self
+    }
+
+    /// Restrict queries to a single pack (e.g. `BAT1` or `combined`).
+    ///
+    /// Rows recorded before packs were tracked have no pack name and are
+    /// included in every scope.
+    pub fn with_battery(mut self, battery: Option<&str>) -> Self {
+        self.battery = battery.map(|b| b.to_string());
+        self
+    }
+
+    /// Restrict queries to one physical pack. Defaults to the pack installed
+    /// most recently, so a replaced battery's readings are left out.
+    pub fn with_pack(mut self, pack: PackScope) -> Self {
+        self.pack = pack;
+        self
+    }
+
+    /// Retention policy applied by `maintain`.
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = retention;
+        self
     }
 
     /// Record a battery snapshot to the database.
+    ///
+    /// A combined reading also records one row per pack, all sharing the
+    /// same timestamp. Rollups and retention are kept up to date as a side
+    /// effect.
     pub fn record_snapshot(&self, info: &BatteryInfo) -> Result<()> {
-        let now = Utc::now().timestamp();
+        self.record_snapshot_at(Utc::now().timestamp(), info)?;
+        self.maintain()?;
+        Ok(())
+    }
+
+    /// Record a snapshot taken at `timestamp` (Unix seconds).
+    pub fn record_snapshot_at(&self, timestamp: i64, info: &BatteryInfo) -> Result<()> {
+        if let Some(host) = &self.host {
+            anyhow::bail!("Cannot record readings for another host ({})", host);
+        }
+        retry_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            if info.packs.is_empty() {
+                self.track_install(timestamp, info)?;
+            }
+            for pack in &info.packs {
+                self.track_install(timestamp, pack)?;
+            }
+
+            for row in std::iter::once(info).chain(&info.packs) {
+                let previous = self.latest_snapshot(&row.name)?;
+                self.insert_snapshot(timestamp, row)?;
+                let Some(current) = self.latest_snapshot(&row.name)? else {
+                    continue;
+                };
+                self.count_cycles(&row.name, previous.as_ref(), &current)?;
+                if let Some(sample) = previous
+                    .as_ref()
+                    .and_then(|previous| resistance::sample_between(previous, &current))
+                {
+                    self.save_resistance_sample(&sample)?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Record a reading and a `sleep` or `wake` event at the same moment,
+    /// for the system sleep hooks.
+    pub fn record_sleep_event(&self, kind: &str, info: &BatteryInfo) -> Result<()> {
+        let timestamp = Utc::now().timestamp();
+        self.record_snapshot_at(timestamp, info)?;
+        self.insert_event(timestamp, info, kind, None)?;
+        self.maintain()?;
+        Ok(())
+    }
+
+    /// Record an `installed` event when a pack is first seen in its slot,
+    /// or when the reading shows the pack has been replaced.
+    fn track_install(&self, timestamp: i64, info: &BatteryInfo) -> Result<()> {
+        let seen = self.all_installs()?.iter().any(|e| e.battery == info.name);
+
+        if !seen {
+            // The pack has been there since the oldest row we have for it
+            let first_row: Option<i64> = self.conn.query_row(
+                "SELECT MIN(timestamp) FROM snapshots
+                 WHERE host IS NULL AND (battery IS NULL OR battery = ?1)",
+                params![info.name],
+                |row| row.get(0),
+            )?;
+            self.insert_event(
+                first_row.unwrap_or(timestamp),
+                info,
+                EVENT_INSTALLED,
+                Some(FIRST_SEEN),
+            )?;
+        }
+
+        if let Some(previous) = self.latest_snapshot(&info.name)? {
+            if let Some(reason) = replacement_reason(&previous, info) {
+                self.insert_event(timestamp, info, EVENT_INSTALLED, Some(&reason))?;
+                let since = DateTime::from_timestamp(timestamp, 0).unwrap_or_default();
+                self.save_cycle_counter(&CycleCounter::new(&info.name, since))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_event(
+        &self,
+        timestamp: i64,
+        info: &BatteryInfo,
+        kind: &str,
+        detail: Option<&str>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO events (timestamp, battery, kind, serial_number, model_name, detail)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                timestamp,
+                info.name,
+                kind,
+                info.serial_number,
+                info.model_name,
+                detail
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The most recent row recorded for a pack.
+    fn latest_snapshot(&self, battery: &str) -> Result<Option<BatterySnapshot>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM snapshots WHERE battery = ?1 AND host IS ?2
+             ORDER BY timestamp DESC LIMIT 1",
+            SNAPSHOT_COLUMNS
+        ))?;
+        let mut rows = stmt.query_map(params![battery, self.host], snapshot_from_row)?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Add the discharge since `previous` to the pack's cycle counter,
+    /// starting one if there is none yet.
+    fn count_cycles(
+        &self,
+        battery: &str,
+        previous: Option<&BatterySnapshot>,
+        current: &BatterySnapshot,
+    ) -> Result<()> {
+        let mut counter = match self.load_cycle_counter(battery)? {
+            Some(counter) => counter,
+            None => self.seed_cycle_counter(battery, current.timestamp)?,
+        };
+        // A reading from before the counter started belongs to the old pack
+        if let Some(previous) = previous.filter(|p| p.timestamp >= counter.since) {
+            counter.add(previous, current);
+        }
+        self.save_cycle_counter(&counter)
+    }
+
+    /// A new counter for a pack. History recorded before there were
+    /// counters is carried over from the daily rollups' level drops, as far
+    /// back as the pack's install.
+    fn seed_cycle_counter(&self, battery: &str, now: DateTime<Utc>) -> Result<CycleCounter> {
+        let install = self
+            .all_installs()?
+            .into_iter()
+            .rfind(|e| e.battery == battery);
+        let mut counter = CycleCounter::new(battery, install.map_or(now, |e| e.timestamp));
+
+        let (level_discharged, energy_out): (f64, f64) = self.conn.query_row(
+            "SELECT COALESCE(SUM(level_discharged), 0), COALESCE(SUM(energy_out_mwh), 0)
+             FROM rollups_daily
+             WHERE host = '' AND battery = ?1 AND bucket + 86400 > ?2",
+            params![battery, counter.since.timestamp()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        counter.cycles = level_discharged / 100.0;
+        counter.discharged_mwh = energy_out;
+        Ok(counter)
+    }
+
+    fn load_cycle_counter(&self, battery: &str) -> Result<Option<CycleCounter>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT battery, since, updated, discharged_mwh, cycles FROM cycle_counters
+             WHERE battery = ?1 COLLATE NOCASE AND host = COALESCE(?2, '')",
+        )?;
+        let mut rows = stmt.query_map(params![battery, self.host], |row| {
+            Ok(CycleCounter {
+                battery: row.get(0)?,
+                since: DateTime::from_timestamp(row.get(1)?, 0).unwrap_or_default(),
+                updated: DateTime::from_timestamp(row.get(2)?, 0).unwrap_or_default(),
+                discharged_mwh: row.get(3)?,
+                cycles: row.get(4)?,
+            })
+        })?;
+        Ok(rows.next().transpose()?)
+    }
+
+    fn save_cycle_counter(&self, counter: &CycleCounter) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO cycle_counters (host, battery, since, updated, discharged_mwh, cycles)
+             VALUES (COALESCE(?1, ''), ?2, ?3, ?4, ?5, ?6)",
+            params![
+                self.host,
+                counter.battery,
+                counter.since.timestamp(),
+                counter.updated.timestamp(),
+                counter.discharged_mwh,
+                counter.cycles
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The cycle counter of the pack in scope (see `with_battery`), unless
+    /// `with_pack` selects a pack that has since been replaced.
+    pub fn cycle_counter(&self) -> Result<Option<CycleCounter>> {
+        let Some(battery) = self.battery.as_deref() else {
+            return Ok(None);
+        };
+        let (_, pack_end) = self.pack_window()?;
+        Ok(self
+            .load_cycle_counter(battery)?
+            .filter(|c| pack_end.is_none_or(|end| c.since.timestamp() < end)))
+    }
+
+    fn save_resistance_sample(&self, sample: &ResistanceSample) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO resistance_samples
+                 (host, battery, timestamp, resistance_mohm, current_step_ma, temperature)
+             VALUES (COALESCE(?1, ''), ?2, ?3, ?4, ?5, ?6)",
+            params![
+                self.host,
+                sample.battery,
+                sample.timestamp.timestamp(),
+                sample.resistance_mohm,
+                sample.current_step_ma,
+                sample.temperature_celsius
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Internal resistance samples of the pack in scope (see `with_battery`
+    /// and `with_pack`), oldest first.
+    pub fn resistance_samples(&self) -> Result<Vec<ResistanceSample>> {
+        let Some(battery) = self.battery.as_deref() else {
+            return Ok(Vec::new());
+        };
+        let (pack_start, pack_end) = self.pack_window()?;
+        let mut stmt = self.conn.prepare(
+            "SELECT battery, timestamp, resistance_mohm, current_step_ma, temperature
+             FROM resistance_samples
+             WHERE battery = ?1 COLLATE NOCASE AND host = COALESCE(?2, '')
+               AND (?3 IS NULL OR timestamp >= ?3)
+               AND (?4 IS NULL OR timestamp < ?4)
+             ORDER BY timestamp ASC",
+        )?;
+        let samples = stmt
+            .query_map(params![battery, self.host, pack_start, pack_end], |row| {
+                Ok(ResistanceSample {
+                    battery: row.get(0)?,
+                    timestamp: DateTime::from_timestamp(row.get(1)?, 0).unwrap_or_default(),
+                    resistance_mohm: row.get(2)?,
+                    current_step_ma: row.get(3)?,
+                    temperature_celsius: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(samples)
+    }
+
+    /// Packs installed in the current battery scope, oldest first.
+    ///
+    /// A pack scope (e.g. `BAT0`) lists that slot's packs; the combined view
+    /// lists every slot's.
+    pub fn installs(&self) -> Result<Vec<BatteryEvent>> {
+        let all = self.all_installs()?;
+        let Some(battery) = self.battery.as_deref() else {
+            return Ok(all);
+        };
+        let own: Vec<BatteryEvent> = all
+            .iter()
+            .filter(|e| e.battery.eq_ignore_ascii_case(battery))
+            .cloned()
+            .collect();
+        Ok(if own.is_empty() { all } else { own })
+    }
+
+    fn all_installs(&self) -> Result<Vec<BatteryEvent>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, timestamp, battery, kind, serial_number, model_name, detail, host
+             FROM events
+             WHERE kind = ?1 AND host IS ?2
+             ORDER BY timestamp ASC, id ASC",
+        )?;
+        let installs = stmt
+            .query_map(params![EVENT_INSTALLED, self.host], event_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(installs)
+    }
+
+    /// `sleep` and `wake` events recorded by the hooks within a period (or
+    /// a duration from now), oldest first.
+    pub fn sleep_events(&self, range: impl Into<TimeRange>) -> Result<Vec<BatteryEvent>> {
+        let range = range.into();
+        let mut stmt = self.conn.prepare(
+            "SELECT id, timestamp, battery, kind, serial_number, model_name, detail, host
+             FROM events
+             WHERE kind IN (?1, ?2) AND timestamp >= ?3 AND host IS ?4
+               AND (?5 IS NULL OR timestamp < ?5)
+             ORDER BY timestamp ASC, id ASC",
+        )?;
+        let events = stmt
+            .query_map(
+                params![
+                    sleep::EVENT_SLEEP,
+                    sleep::EVENT_WAKE,
+                    range.start.timestamp(),
+                    self.host,
+                    range.end.map(|end| end.timestamp())
+                ],
+                event_from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(events)
+    }
+
+    /// The install event of the pack selected with `with_pack`, if any.
+    pub fn selected_install(&self) -> Result<Option<BatteryEvent>> {
+        let installs = self.installs()?;
+        match self.pack {
+            PackScope::All => Ok(None),
+            PackScope::Current => Ok(installs.last().cloned()),
+            PackScope::Install(id) => installs
+                .into_iter()
+                .find(|e| e.id == id)
+                .map(Some)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "No battery pack #{} in this history. Run 'batteryctl history' to list packs.",
+                        id
+                    )
+                }),
+        }
+    }
+
+    /// Time window `[start, end)` covered by the selected pack.
+    fn pack_window(&self) -> Result<(Option<i64>, Option<i64>)> {
+        let Some(install) = self.selected_install()? else {
+            return Ok((None, None));
+        };
+        let end = self
+            .installs()?
+            .into_iter()
+            .filter(|e| e.battery == install.battery && e.timestamp > install.timestamp)
+            .map(|e| e.timestamp.timestamp())
+            .next();
+        Ok((Some(install.timestamp.timestamp()), end))
+    }
+
+    fn insert_snapshot(&self, timestamp: i64, info: &BatteryInfo) -> Result<()> {
         let is_charging = matches!(
             info.state,
             crate::battery::ChargingState::Charging | crate::battery::ChargingState::Full
         );
 
         self.conn.execute(
-            "INSERT INTO snapshots (timestamp, level, is_charging, power_draw, cycle_count, max_capacity, design_capacity)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT INTO snapshots (timestamp, level, is_charging, power_draw, cycle_count, max_capacity, design_capacity, battery,
+                                    max_energy, design_energy, capacity_unit,
+                                    model_name, manufacturer, serial_number, technology,
+                                    state, temperature, voltage, current_capacity, current_energy, time_remaining)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+                     ?16, ?17, ?18, ?19, ?20, ?21)",
             params![
-                now,
+                timestamp,
                 info.level as i32,
                 is_charging,
                 info.power_draw_watts,
                 info.cycle_count.map(|c| c as i32),
                 info.max_capacity_mah.map(|c| c as i32),
                 info.design_capacity_mah.map(|c| c as i32),
+                info.name,
+                info.max_energy_mwh.map(|c| c as i32),
+                info.design_energy_mwh.map(|c| c as i32),
+                info.capacity_unit.as_str(),
+                info.model_name,
+                info.manufacturer,
+                info.serial_number,
+                info.technology,
+                info.state.as_str(),
+                info.temperature_celsius,
+                info.voltage_mv,
+                info.current_capacity_mah.map(|c| c as i32),
+                info.current_energy_mwh.map(|c| c as i32),
+                info.time_remaining_minutes,
             ],
         )?;
         Ok(())
     }
 
-    /// Get snapshots within a duration from now.
-    pub fn get_snapshots_range(&self, duration: Duration) -> Result<Vec<BatterySnapshot>> {
-        let since = (Utc::now() - duration).timestamp();
-        let mut stmt = self.conn.prepare(
-            "SELECT timestamp, level, is_charging, power_draw, cycle_count, max_capacity, design_capacity
+    /// Get snapshots within a period (or a duration from now).
+    ///
+    /// Long or old periods read hourly or daily rollups instead of raw rows
+    /// (see `Granularity::for_range`), one snapshot per bucket.
+    pub fn get_snapshots_range(&self, range: impl Into<TimeRange>) -> Result<Vec<BatterySnapshot>> {
+        let range = range.into();
+        match self.granularity(&range) {
+            Granularity::Raw => self.get_raw_snapshots(range),
+            granularity => Ok(self
+                .get_rollups(granularity, range)?
+                .iter()
+                .map(|r| BatterySnapshot {
+                    host: self.host.clone(),
+                    ..r.to_snapshot()
+                })
+                .collect()),
+        }
+    }
+
+    /// Readings for a capacity trend over a period of any length: raw
+    /// snapshots where they are still kept, before them the hourly rollups,
+    /// and before those the daily ones.
+    pub fn capacity_history(&self, range: TimeRange) -> Result<CapacityHistory> {
+        let raw = self.get_raw_snapshots(range)?;
+        let mut cutoff = raw
+            .first()
+            .map_or_else(|| range.end.unwrap_or_else(Utc::now), |s| s.timestamp);
+
+        // Each granularity ends where the finer one before it starts
+        let mut older = |granularity: Granularity| -> Result<Vec<Rollup>> {
+            let end = DateTime::from_timestamp(granularity.bucket_start(cutoff.timestamp()), 0)
+                .unwrap_or_default();
+            if end <= range.start {
+                return Ok(Vec::new());
+            }
+            let rollups = self.get_rollups(
+                granularity,
+                TimeRange {
+                    start: range.start,
+                    end: Some(end),
+                    last: None,
+                },
+            )?;
+            if let Some(first) = rollups.first() {
+                cutoff = DateTime::from_timestamp(first.bucket, 0).unwrap_or_default();
+            }
+            Ok(rollups)
+        };
+        let hourly = older(Granularity::Hourly)?;
+        let daily = older(Granularity::Daily)?;
+
+        Ok(CapacityHistory { daily, hourly, raw })
+    }
+
+    /// Rows a period reads: raw snapshots, or rollups for long or old periods.
+    pub fn granularity(&self, range: &TimeRange) -> Granularity {
+        Granularity::for_range(range.span(), range.age(), &self.retention)
+    }
+
+    /// Raw snapshots within a period (or a duration from now), whatever
+    /// its length. Rows older than the raw retention period are no longer
+    /// there.
+    pub fn get_raw_snapshots(&self, range: impl Into<TimeRange>) -> Result<Vec<BatterySnapshot>> {
+        let range = range.into();
+        let (pack_start, pack_end) = self.pack_window()?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}
              FROM snapshots
              WHERE timestamp >= ?1
+               AND (?2 IS NULL OR battery IS NULL OR battery = ?2 COLLATE NOCASE)
+               AND (?3 IS NULL OR timestamp >= ?3)
+               AND (?4 IS NULL OR timestamp < ?4)
+               AND host IS ?5
+               AND (?6 IS NULL OR timestamp < ?6)
              ORDER BY timestamp ASC",
-        )?;
+            SNAPSHOT_COLUMNS
+        ))?;
 
         let snapshots = stmt
-            .query_map(params![since], |row| {
-                let ts: i64 = row.get(0)?;
-                let level: i32 = row.get(1)?;
-                let is_charging: bool = row.get(2)?;
-                let power_draw: Option<f64> = row.get(3)?;
-                let cycle_count: Option<i32> = row.get(4)?;
-                let max_capacity: Option<i32> = row.get(5)?;
-                let design_capacity: Option<i32> = row.get(6)?;
-
-                Ok(BatterySnapshot {
-                    timestamp: DateTime::from_timestamp(ts, 0).unwrap_or_default(),
-                    level: level.clamp(0, 100) as u8,
-                    is_charging,
-                    power_draw,
-                    cycle_count: cycle_count.map(|c| c as u32),
-                    max_capacity: max_capacity.map(|c| c as u32),
-                    design_capacity: design_capacity.map(|c| c as u32),
-                })
-            })?
+            .query_map(
+                params![
+                    range.start.timestamp(),
+                    self.battery,
+                    pack_start,
+                    pack_end,
+                    self.host,
+                    range.end.map(|end| end.timestamp())
+                ],
+                snapshot_from_row,
+            )?
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(snapshots)
     }
 
-    /// Compute a summary of battery usage over a time period.
-    pub fn get_summary(&self, duration: Duration) -> Result<HistorySummary> {
-        let snapshots = self.get_snapshots_range(duration)?;
-
-        if snapshots.is_empty() {
-            return Ok(HistorySummary {
-                period_description: format_duration(&duration),
-                snapshots_count: 0,
-                avg_level: 0.0,
-                min_level: 0,
-                max_level: 0,
-                charging_periods: 0,
-                total_charging_minutes: 0,
-                total_discharging_minutes: 0,
-                avg_discharge_rate_watts: None,
-                estimated_cycles: 0.0,
-            });
-        }
+    /// Rollups of one granularity within a period (or a duration from
+    /// now), refreshing them first if raw rows have been recorded since the
+    /// last refresh.
+    pub fn get_rollups(
+        &self,
+        granularity: Granularity,
+        range: impl Into<TimeRange>,
+    ) -> Result<Vec<Rollup>> {
+        let Some(table) = granularity.table() else {
+            anyhow::bail!("Raw history has no rollups");
+        };
+        self.refresh_stale_rollups()?;
 
-        let levels: Vec<f64> = snapshots.iter().map(|s| s.level as f64).collect();
-        let avg_level = levels.iter().sum::<f64>() / levels.len() as f64;
-        let min_level = snapshots.iter().map(|s| s.level).min().unwrap_or(0);
-        let max_level = snapshots.iter().map(|s| s.level).max().unwrap_or(100);
+        let range = range.into();
+        let since = granularity.bucket_start(range.start.timestamp());
+        let (pack_start, pack_end) = self.pack_window()?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}
+             FROM {}
+             WHERE bucket >= ?1
+               AND (?2 IS NULL OR battery = '' OR battery = ?2 COLLATE NOCASE)
+               AND (?3 IS NULL OR bucket + ?5 > ?3)
+               AND (?4 IS NULL OR bucket < ?4)
+               AND host = COALESCE(?6, '')
+               AND (?7 IS NULL OR bucket < ?7)
+             ORDER BY bucket ASC",
+            ROLLUP_COLUMNS, table
+        ))?;
+        let rollups = stmt
+            .query_map(
+                params![
+                    since,
+                    self.battery,
+                    pack_start,
+                    pack_end,
+                    granularity.bucket_secs(),
+                    self.host,
+                    range.end.map(|end| end.timestamp())
+                ],
+                rollup_from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rollups)
+    }
 
-        // Count charging periods (transitions from not-charging to charging)
-        let mut charging_periods = 0u32;
-        let mut charging_minutes = 0i64;
-        let mut discharging_minutes = 0i64;
-        let mut was_charging = false;
+    /// Rebuild the hourly and daily rollups of every bucket from the one
+    /// containing `from` onwards, using the raw rows still stored.
+    ///
+    /// Buckets without raw rows (already pruned) are left as they are.
+    pub fn refresh_rollups(&self, from: i64) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for granularity in [Granularity::Hourly, Granularity::Daily] {
+            let Some(table) = granularity.table() else {
+                continue;
+            };
+            let from_bucket = granularity.bucket_start(from);
+            let since = from_bucket - granularity.bucket_secs();
 
-        for i in 0..snapshots.len() {
-            if snapshots[i].is_charging && !was_charging {
-                charging_periods += 1;
-            }
-            was_charging = snapshots[i].is_charging;
-
-            if i + 1 < snapshots.len() {
-                let dt = snapshots[i + 1]
-                    .timestamp
-                    .signed_duration_since(snapshots[i].timestamp)
-                    .num_minutes();
-                if snapshots[i].is_charging {
-                    charging_minutes += dt;
-                } else {
-                    discharging_minutes += dt;
+            let mut stmt =
+                tx.prepare("SELECT DISTINCT host FROM snapshots WHERE timestamp >= ?1")?;
+            let hosts = stmt
+                .query_map(params![since], |row| row.get::<_, Option<String>>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for host in hosts {
+                // One bucket earlier, so the first row has a predecessor
+                let mut stmt = tx.prepare(&format!(
+                    "SELECT {} FROM snapshots WHERE timestamp >= ?1 AND host IS ?2
+                     ORDER BY timestamp ASC",
+                    SNAPSHOT_COLUMNS
+                ))?;
+                let rows = stmt
+                    .query_map(params![since, host], snapshot_from_row)?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                for r in rollup::compute_rollups(&rows, granularity, from_bucket) {
+                    insert_rollup(&tx, table, host.as_deref(), &r, true)?;
                 }
             }
         }
 
-        // Average discharge rate
-        let discharge_powers: Vec<f64> = snapshots
-            .iter()
-            .filter(|s| !s.is_charging)
-            .filter_map(|s| s.power_draw)
-            .collect();
-        let avg_discharge_rate = if !discharge_powers.is_empty() {
-            Some(discharge_powers.iter().sum::<f64>() / discharge_powers.len() as f64)
-        } else {
-            None
-        };
+        let latest: Option<i64> =
+            tx.query_row("SELECT MAX(timestamp) FROM snapshots", [], |row| row.get(0))?;
+        if let Some(latest) = latest {
+            tx.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+                params![META_ROLLUPS_THROUGH, latest.to_string()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Refresh rollups if raw rows newer than the last refresh exist.
+    fn refresh_stale_rollups(&self) -> Result<()> {
+        let through: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = ?1",
+                params![META_ROLLUPS_THROUGH],
+                |row| row.get::<_, String>(0),
+            )
+            .ok()
+            .and_then(|v| v.parse().ok());
+        let latest: Option<i64> =
+            self.conn
+                .query_row("SELECT MAX(timestamp) FROM snapshots", [], |row| row.get(0))?;
 
-        // Estimate cycles: sum of |level changes| / 100
-        let mut total_level_change: f64 = 0.0;
-        for i in 1..snapshots.len() {
-            let diff = (snapshots[i].level as f64 - snapshots[i - 1].level as f64).abs();
-            if !snapshots[i].is_charging && !snapshots[i - 1].is_charging {
-                total_level_change += diff;
+        match (through, latest) {
+            (_, None) => Ok(()),
+            (Some(through), Some(latest)) if latest <= through => Ok(()),
+            (Some(through), Some(_)) => self.refresh_rollups(through),
+            (None, Some(_)) => {
+                let first: i64 =
+                    self.conn
+                        .query_row("SELECT MIN(timestamp) FROM snapshots", [], |row| row.get(0))?;
+                self.refresh_rollups(first)
             }
         }
-        let estimated_cycles = total_level_change / 100.0;
-
-        Ok(HistorySummary {
-            period_description: format_duration(&duration),
-            snapshots_count: snapshots.len(),
-            avg_level,
-            min_level,
-            max_level,
-            charging_periods,
-            total_charging_minutes: charging_minutes,
-            total_discharging_minutes: discharging_minutes,
-            avg_discharge_rate_watts: avg_discharge_rate,
-            estimated_cycles,
+    }
+
+    /// Bring rollups up to date, then drop rows older than the retention policy.
+    pub fn maintain(&self) -> Result<PruneCounts> {
+        retry_busy(|| {
+            self.refresh_stale_rollups()?;
+            self.apply_retention(Utc::now().timestamp())
         })
     }
 
+    /// Oldest timestamp the retention policy keeps raw rows from, relative
+    /// to `now`. Raw rows go by whole days, so every daily bucket that still
+    /// has raw rows has all of them.
+    pub fn raw_kept_since(&self, now: i64) -> i64 {
+        Granularity::Daily.bucket_start(now - self.retention.raw_days as i64 * 86400)
+    }
+
+    /// Delete raw rows and rollups older than the retention policy allows,
+    /// relative to `now`.
+    pub fn apply_retention(&self, now: i64) -> Result<PruneCounts> {
+        let days = |d: u32| d as i64 * 86400;
+        let policy = &self.retention;
+
+        let raw_cutoff = self.raw_kept_since(now);
+        let raw = self.conn.execute(
+            "DELETE FROM snapshots WHERE timestamp < ?1",
+            params![raw_cutoff],
+        )?;
+        let hourly = self.conn.execute(
+            "DELETE FROM rollups_hourly WHERE bucket < ?1",
+            params![now - days(policy.hourly_days)],
+        )?;
+        let daily = match policy.daily_days {
+            Some(d) => {
+                // Resistance samples are kept as long as the daily rollups
+                self.conn.execute(
+                    "DELETE FROM resistance_samples WHERE timestamp < ?1",
+                    params![now - days(d)],
+                )?;
+                self.conn.execute(
+                    "DELETE FROM rollups_daily WHERE bucket < ?1",
+                    params![now - days(d)],
+                )?
+            }
+            None => 0,
+        };
+        Ok(PruneCounts { raw, hourly, daily })
+    }
+
+    /// Compute a summary of battery usage over a time period.
+    pub fn get_summary(&self, range: impl Into<TimeRange>) -> Result<HistorySummary> {
+        let range = range.into();
+        let granularity = self.granularity(&range);
+        if granularity != Granularity::Raw {
+            let rollups = self.get_rollups(granularity, range)?;
+            return Ok(summarize_rollups(&rollups, &range, granularity));
+        }
+
+        Ok(summarize_snapshots(&self.get_raw_snapshots(range)?, &range))
+    }
+
     /// Get the total number of snapshots stored.
     pub fn snapshot_count(&self) -> Result<usize> {
         let count: i64 = self.conn.query_row(
@@ -241,81 +1092,1462 @@ impl HistoryManager {
         Ok(count as usize)
     }
 
-    /// Prune old snapshots beyond a retention period.
-    pub fn prune(&self, keep_duration: Duration) -> Result<usize> {
+    /// Delete raw snapshots and rollups older than `keep_duration`. With
+    /// `dry_run`, only count what would be deleted.
+    pub fn prune(&self, keep_duration: Duration, dry_run: bool) -> Result<PruneCounts> {
         let cutoff = (Utc::now() - keep_duration).timestamp();
-        let deleted = self.conn.execute(
-            "DELETE FROM snapshots WHERE timestamp < ?1",
-            params![cutoff],
-        )?;
-        Ok(deleted)
+        retry_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            let run = |table: &str, column: &str| -> Result<usize> {
+                Ok(if dry_run {
+                    tx.query_row(
+                        &format!("SELECT COUNT(*) FROM {} WHERE {} < ?1", table, column),
+                        params![cutoff],
+                        |row| row.get::<_, i64>(0),
+                    )? as usize
+                } else {
+                    tx.execute(
+                        &format!("DELETE FROM {} WHERE {} < ?1", table, column),
+                        params![cutoff],
+                    )?
+                })
+            };
+            let counts = PruneCounts {
+                raw: run("snapshots", "timestamp")?,
+                hourly: run("rollups_hourly", "bucket")?,
+                daily: run("rollups_daily", "bucket")?,
+            };
+            tx.commit()?;
+            Ok(counts)
+        })
     }
-}
 
-fn get_db_path() -> Result<PathBuf> {
-    let home = dirs::home_dir().context("Could not determine home directory")?;
-    Ok(home.join(".batteryctl").join("history.db"))
-}
+    /// Path of the database file; `None` for in-memory databases.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.conn
+            .path()
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+    }
 
-fn format_duration(d: &Duration) -> String {
-    let hours = d.num_hours();
-    if hours < 24 {
-        format!("Last {} hour{}", hours, if hours != 1 { "s" } else { "" })
-    } else {
-        let days = d.num_days();
-        if days < 7 {
-            format!("Last {} day{}", days, if days != 1 { "s" } else { "" })
-        } else if days < 30 {
-            let weeks = days / 7;
-            format!("Last {} week{}", weeks, if weeks != 1 { "s" } else { "" })
-        } else {
-            let months = days / 30;
-            format!(
-                "Last {} month{}",
-                months,
-                if months != 1 { "s" } else { "" }
-            )
-        }
+    /// Move the write-ahead log's contents into the database file, so its
+    /// size is that of the whole database.
+    fn checkpoint(&self) -> Result<()> {
+        self.conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(())
     }
-}
 
-/// Parse a duration string like "24h", "7d", "30d", "1w", "1m" into a chrono Duration.
-pub fn parse_duration_str(s: &str) -> Result<Duration> {
-    let s = s.trim().to_lowercase();
-    let (num_str, unit) = if s.ends_with('h') {
-        (&s[..s.len() - 1], 'h')
-    } else if s.ends_with('d') {
-        (&s[..s.len() - 1], 'd')
-    } else if s.ends_with('w') {
-        (&s[..s.len() - 1], 'w')
-    } else if s.ends_with('m') {
-        (&s[..s.len() - 1], 'm')
-    } else {
-        anyhow::bail!("Invalid duration format '{}'. Use e.g. 24h, 7d, 4w, 1m", s);
-    };
+    fn file_size(&self) -> Option<u64> {
+        self.path()
+            .and_then(|p| std::fs::metadata(p).ok())
+            .map(|m| m.len())
+    }
 
-    let num: i64 = num_str
-        .parse()
-        .with_context(|| format!("Invalid number in duration '{}'", s))?;
+    /// Rebuild the database file to reclaim space left by deleted rows.
+    pub fn vacuum(&self) -> Result<VacuumReport> {
+        self.checkpoint()?;
+        let size_before = self.file_size();
+        retry_busy(|| Ok(self.conn.execute_batch("VACUUM")?))?;
+        self.checkpoint()?;
+        Ok(VacuumReport {
+            size_before,
+            size_after: self.file_size(),
+        })
+    }
 
-    let duration = match unit {
-        'h' => Duration::hours(num),
-        'd' => Duration::days(num),
-        'w' => Duration::weeks(num),
-        'm' => Duration::days(num * 30),
-        _ => unreachable!(),
-    };
+    /// Row counts, date range, file size and recording gaps longer than `min_gap`.
+    pub fn stats(&self, min_gap: Duration) -> Result<HistoryStats> {
+        let count = |table: &str| -> Result<usize> {
+            Ok(self
+                .conn
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get::<_, i64>(0)
+                })? as usize)
+        };
+        let to_date = |ts: Option<i64>| ts.and_then(|t| DateTime::from_timestamp(t, 0));
 
-    Ok(duration)
-}
+        let (first, last): (Option<i64>, Option<i64>) = self.conn.query_row(
+            "SELECT MIN(timestamp), MAX(timestamp) FROM snapshots",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::battery::{BatteryCondition, ChargingState};
+        let mut stmt = self.conn.prepare(
+            "SELECT host, battery, COUNT(*), MIN(timestamp), MAX(timestamp)
+             FROM snapshots GROUP BY host, battery ORDER BY host, battery",
+        )?;
+        let packs = stmt
+            .query_map([], |row| {
+                Ok(PackStats {
+                    host: row.get(0)?,
+                    battery: row.get(1)?,
+                    snapshots: row.get::<_, i64>(2)? as usize,
+                    first: to_date(row.get(3)?),
+                    last: to_date(row.get(4)?),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
 
-    fn make_test_info(level: u8, charging: bool) -> BatteryInfo {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT host, timestamp FROM snapshots ORDER BY host, timestamp")?;
+        let timestamps = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, Option<String>>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let gaps = timestamps
+            .windows(2)
+            .filter(|w| w[0].0 == w[1].0 && w[1].1 - w[0].1 >= min_gap.num_seconds())
+            .map(|w| Gap {
+                host: w[0].0.clone(),
+                start: DateTime::from_timestamp(w[0].1, 0).unwrap_or_default(),
+                end: DateTime::from_timestamp(w[1].1, 0).unwrap_or_default(),
+                minutes: (w[1].1 - w[0].1) / 60,
+            })
+            .collect();
+
+        Ok(HistoryStats {
+            path: self.path(),
+            file_size_bytes: self.file_size(),
+            schema_version: migrations::schema_version(&self.conn)?,
+            snapshots: count("snapshots")?,
+            hourly_rollups: count("rollups_hourly")?,
+            daily_rollups: count("rollups_daily")?,
+            events: count("events")?,
+            first: to_date(first),
+            last: to_date(last),
+            packs,
+            gaps,
+        })
+    }
+
+    /// Look for corruption, out-of-order timestamps, impossible values and
+    /// duplicate rows.
+    pub fn check(&self) -> Result<IntegrityReport> {
+        let sqlite: String = self
+            .conn
+            .query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+        let mut issues = Vec::new();
+
+        // Recorded rows are appended, so a timestamp going backwards within
+        // a pack means the clock was changed. Imported rows arrive in any
+        // order and are left out.
+        let mut stmt = self.conn.prepare(
+            "SELECT host, battery, timestamp FROM snapshots WHERE NOT imported
+             ORDER BY host, battery, rowid",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        for pair in rows.windows(2) {
+            let ((prev_host, prev_battery, prev_ts), (host, battery, ts)) = (&pair[0], &pair[1]);
+            if host == prev_host && battery == prev_battery && ts < prev_ts {
+                issues.push(IntegrityIssue::new(
+                    "out_of_order",
+                    *ts,
+                    host.clone(),
+                    battery.clone(),
+                    format!("recorded after a row from {}s later", prev_ts - ts),
+                ));
+            }
+        }
+
+        // Checked column by column so every problem on a row is reported
+        let future = (Utc::now() + Duration::days(1)).timestamp();
+        let future_condition = format!("timestamp > {} OR timestamp < 0", future);
+        let checks: [(&str, &str); 9] = [
+            ("timestamp in the future or before 1970", &future_condition),
+            ("level outside 0-100%", "level < 0 OR level > 100"),
+            ("negative cycle count", "cycle_count < 0"),
+            (
+                "negative capacity",
+                "max_capacity < 0 OR design_capacity < 0 OR max_energy < 0 OR design_energy < 0",
+            ),
+            (
+                "max capacity above twice the design capacity",
+                "max_capacity > 2 * design_capacity",
+            ),
+            (
+                "max energy above twice the design energy",
+                "max_energy > 2 * design_energy",
+            ),
+            (
+                "temperature outside -40-100C",
+                "temperature < -40 OR temperature > 100",
+            ),
+            ("voltage outside 0-30V", "voltage <= 0 OR voltage > 30000"),
+            ("power draw above 500 W", "ABS(power_draw) > 500"),
+        ];
+        type Row = (i64, Option<String>, Option<String>);
+        let mut impossible: BTreeMap<i64, (Row, Vec<&str>)> = BTreeMap::new();
+        for (problem, condition) in checks {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT rowid, timestamp, host, battery FROM snapshots WHERE {}",
+                condition
+            ))?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        (row.get(1)?, row.get(2)?, row.get(3)?),
+                    ))
+                })?
+                .collect::<Result<Vec<(i64, Row)>, _>>()?;
+            for (rowid, row) in rows {
+                impossible
+                    .entry(rowid)
+                    .or_insert_with(|| (row, Vec::new()))
+                    .1
+                    .push(problem);
+            }
+        }
+        for ((ts, host, battery), problems) in impossible.into_values() {
+            issues.push(IntegrityIssue::new(
+                "impossible_value",
+                ts,
+                host,
+                battery,
+                problems.join(", "),
+            ));
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT timestamp, host, battery, COUNT(*) FROM snapshots
+             GROUP BY host, battery, timestamp HAVING COUNT(*) > 1
+             ORDER BY timestamp",
+        )?;
+        let duplicates = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, i64>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (ts, host, battery, n) in duplicates {
+            issues.push(IntegrityIssue::new(
+                "duplicate",
+                ts,
+                host,
+                battery,
+                format!("{} rows with the same pack and timestamp", n),
+            ));
+        }
+
+        Ok(IntegrityReport {
+            ok: sqlite == "ok" && issues.is_empty(),
+            sqlite,
+            issues,
+        })
+    }
+
+    /// Add exported snapshots, skipping rows already stored for the same
+    /// host, pack and timestamp. Rows without a host are tagged with `host`;
+    /// `None` files them under this machine.
+    ///
+    /// Rows older than the raw retention period are also skipped when their
+    /// hour already has a rollup: its raw rows are gone, so it could not be
+    /// rebuilt to include them.
+    pub fn import_snapshots(
+        &self,
+        snapshots: &[BatterySnapshot],
+        host: Option<&str>,
+    ) -> Result<ImportCounts> {
+        let counts = retry_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            let counts = self.insert_snapshots(&tx, snapshots, host)?;
+            tx.commit()?;
+            Ok(counts)
+        })?;
+
+        if let Some(first) = snapshots.iter().map(|s| s.timestamp.timestamp()).min() {
+            self.finish_import(first)?;
+        }
+        Ok(counts)
+    }
+
+    /// Insert the snapshots `import_snapshots` would, within `tx`.
+    fn insert_snapshots(
+        &self,
+        tx: &Connection,
+        snapshots: &[BatterySnapshot],
+        host: Option<&str>,
+    ) -> Result<ImportCounts> {
+        let mut counts = ImportCounts::default();
+        let raw_cutoff = self.raw_kept_since(Utc::now().timestamp());
+        for snap in snapshots {
+            let host = snap.host.as_deref().or(host);
+            let timestamp = snap.timestamp.timestamp();
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM snapshots
+                                WHERE timestamp = ?1 AND battery IS ?2 AND host IS ?3)
+                     OR (?1 < ?4 AND EXISTS (SELECT 1 FROM rollups_hourly
+                                             WHERE bucket = ?5 AND battery = COALESCE(?2, '')
+                                               AND host = COALESCE(?3, '')))",
+                params![
+                    timestamp,
+                    snap.battery,
+                    host,
+                    raw_cutoff,
+                    Granularity::Hourly.bucket_start(timestamp)
+                ],
+                |row| row.get(0),
+            )?;
+            if exists {
+                counts.duplicates += 1;
+            } else {
+                insert_snapshot_row(tx, snap, host)?;
+                counts.snapshots += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Copy the history database at `path` into this one, tagging rows
+    /// recorded on that machine with `host`. Pack events, cycle counters and
+    /// rollups of periods whose raw rows are already pruned come along too,
+    /// all in one transaction.
+    ///
+    /// The other database is read from a copy migrated to this build's
+    /// schema; the file itself is left as it was.
+    pub fn merge(&self, path: &Path, host: &str) -> Result<ImportCounts> {
+        if host.trim().is_empty() {
+            anyhow::bail!("Host name for merged rows must not be empty");
+        }
+        let same_file = |a: &Path, b: &Path| match (a.canonicalize(), b.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        };
+        if self.path().is_some_and(|own| same_file(&own, path)) {
+            anyhow::bail!("Cannot merge the history database into itself");
+        }
+        if !path.exists() {
+            anyhow::bail!("No history database at {}", path.display());
+        }
+        let other = open_copy(path)?;
+
+        let mut stmt = other.prepare(
+            "SELECT timestamp, battery, kind, serial_number, model_name, detail, host
+             FROM events ORDER BY id",
+        )?;
+        let events = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Rebuilt from raw rows where the other database still has them;
+        // older buckets only exist as rollups
+        let mut rollups = Vec::new();
+        for granularity in [Granularity::Hourly, Granularity::Daily] {
+            let Some(table) = granularity.table() else {
+                continue;
+            };
+            let mut stmt =
+                other.prepare(&format!("SELECT {}, host FROM {}", ROLLUP_COLUMNS, table))?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((table, rollup_from_row(row)?, row.get::<_, String>("host")?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            rollups.extend(rows);
+        }
+
+        let mut stmt = other.prepare(
+            "SELECT host, battery, since, updated, discharged_mwh, cycles FROM cycle_counters",
+        )?;
+        let counters = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, f64>(4)?,
+                    row.get::<_, f64>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut stmt = other.prepare(
+            "SELECT host, battery, timestamp, resistance_mohm, current_step_ma, temperature
+             FROM resistance_samples",
+        )?;
+        let samples = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, f64>(3)?,
+                    row.get::<_, f64>(4)?,
+                    row.get::<_, Option<f64>>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut stmt = other.prepare(&format!(
+            "SELECT {} FROM snapshots ORDER BY timestamp, rowid",
+            SNAPSHOT_COLUMNS
+        ))?;
+        let snapshots = stmt
+            .query_map([], snapshot_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let counts = retry_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            let mut counts = ImportCounts::default();
+            for (timestamp, battery, kind, serial, model, detail, event_host) in &events {
+                let event_host = event_host.as_deref().unwrap_or(host);
+                counts.events += tx.execute(
+                    "INSERT INTO events (timestamp, battery, kind, serial_number, model_name, detail, host)
+                     SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7
+                     WHERE NOT EXISTS (SELECT 1 FROM events
+                                       WHERE timestamp = ?1 AND battery = ?2 AND kind = ?3 AND host IS ?7)",
+                    params![timestamp, battery, kind, serial, model, detail, event_host],
+                )?;
+            }
+            for (table, r, rollup_host) in &rollups {
+                let rollup_host = if rollup_host.is_empty() {
+                    host
+                } else {
+                    rollup_host
+                };
+                counts.rollups += insert_rollup(&tx, table, Some(rollup_host), r, false)?;
+            }
+            for (counter_host, battery, since, updated, discharged, cycles) in &counters {
+                let counter_host = if counter_host.is_empty() {
+                    host
+                } else {
+                    counter_host
+                };
+                tx.execute(
+                    "INSERT OR REPLACE INTO cycle_counters (host, battery, since, updated, discharged_mwh, cycles)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![counter_host, battery, since, updated, discharged, cycles],
+                )?;
+            }
+            for (sample_host, battery, timestamp, mohm, step, temperature) in &samples {
+                let sample_host = if sample_host.is_empty() {
+                    host
+                } else {
+                    sample_host
+                };
+                tx.execute(
+                    "INSERT OR IGNORE INTO resistance_samples
+                         (host, battery, timestamp, resistance_mohm, current_step_ma, temperature)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![sample_host, battery, timestamp, mohm, step, temperature],
+                )?;
+            }
+            let inserted = self.insert_snapshots(&tx, &snapshots, Some(host))?;
+            tx.commit()?;
+            Ok(ImportCounts {
+                snapshots: inserted.snapshots,
+                duplicates: inserted.duplicates,
+                ..counts
+            })
+        })?;
+
+        if let Some(first) = snapshots.first() {
+            self.finish_import(first.timestamp.timestamp())?;
+        }
+        Ok(counts)
+    }
+
+    /// Let each pack's first-seen install cover imported rows older than
+    /// it, and rebuild rollups from `from` onwards.
+    fn finish_import(&self, from: i64) -> Result<()> {
+        retry_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute(
+                "UPDATE events
+                 SET timestamp = (SELECT MIN(s.timestamp) FROM snapshots s
+                                  WHERE s.host IS events.host
+                                    AND (s.battery IS NULL OR s.battery = events.battery))
+                 WHERE kind = ?1 AND detail = ?2
+                   AND timestamp > (SELECT MIN(s.timestamp) FROM snapshots s
+                                    WHERE s.host IS events.host
+                                      AND (s.battery IS NULL OR s.battery = events.battery))",
+                params![EVENT_INSTALLED, FIRST_SEEN],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })?;
+
+        retry_busy(|| self.refresh_rollups(from))?;
+        self.maintain()?;
+        Ok(())
+    }
+
+    /// Latest reading in the current scope, for reports on another host
+    /// where no live reading is available.
+    pub fn latest_reading(&self) -> Result<Option<BatterySnapshot>> {
+        let (pack_start, pack_end) = self.pack_window()?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}
+             FROM snapshots
+             WHERE (?1 IS NULL OR battery IS NULL OR battery = ?1 COLLATE NOCASE)
+               AND (?2 IS NULL OR timestamp >= ?2)
+               AND (?3 IS NULL OR timestamp < ?3)
+               AND host IS ?4
+             ORDER BY timestamp DESC, battery = 'combined' DESC
+             LIMIT 1",
+            SNAPSHOT_COLUMNS
+        ))?;
+        let mut rows = stmt.query_map(
+            params![self.battery, pack_start, pack_end, self.host],
+            snapshot_from_row,
+        )?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Hosts with merged or imported rows, besides this machine.
+    pub fn hosts(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT host FROM snapshots WHERE host IS NOT NULL ORDER BY host")?;
+        let hosts = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(hosts)
+    }
+}
+
+/// How long a statement waits for another connection's write lock before
+/// failing with "database is locked".
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Attempts at an operation that still finds the database locked after
+/// `BUSY_TIMEOUT`, pausing `RETRY_DELAY` (doubling each time) in between.
+const BUSY_ATTEMPTS: u32 = 4;
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Run `op`, trying again if it fails because another process holds the
+/// database. `op` must be safe to repeat, e.g. a single transaction.
+fn retry_busy<T>(mut op: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut delay = RETRY_DELAY;
+    for _ in 1..BUSY_ATTEMPTS {
+        match op() {
+            Err(err) if is_busy(&err) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    op()
+}
+
+fn is_busy(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<rusqlite::Error>(),
+            Some(rusqlite::Error::SqliteFailure(failure, _))
+                if matches!(failure.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+        )
+    })
+}
+
+/// An in-memory copy of the history database at `path`, migrated to this
+/// build's schema. The file is only read, so it may be on read-only media
+/// or belong to a batteryctl that cannot open a newer schema.
+fn open_copy(path: &Path) -> Result<Connection> {
+    // Even read-only, SQLite leaves a -wal and -shm next to a WAL database.
+    // Without a -wal the file is complete on its own (a copy taken from
+    // another machine), so it is opened as immutable and nothing is added.
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    let immutable = if Path::new(&wal).exists() {
+        ""
+    } else {
+        "?immutable=1"
+    };
+    let uri = format!("file:{}{}", uri_path(path), immutable);
+    let source = Connection::open_with_flags(
+        uri,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )
+    .with_context(|| format!("Failed to open database at {}", path.display()))?;
+    source.busy_timeout(BUSY_TIMEOUT)?;
+    let mut copy = Connection::open_in_memory()?;
+    Backup::new(&source, &mut copy)?
+        .run_to_completion(1024, std::time::Duration::ZERO, None)
+        .with_context(|| format!("Failed to read database at {}", path.display()))?;
+    migrations::migrate(&copy)?;
+    Ok(copy)
+}
+
+/// `path` escaped for a `file:` URI.
+fn uri_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '%' | '?' | '#' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Insert a snapshot read back from an export or another database.
+fn insert_snapshot_row(
+    conn: &Connection,
+    snap: &BatterySnapshot,
+    host: Option<&str>,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO snapshots (timestamp, level, is_charging, power_draw, cycle_count, max_capacity, design_capacity, battery,
+                                max_energy, design_energy, capacity_unit,
+                                model_name, manufacturer, serial_number, technology,
+                                state, temperature, voltage, current_capacity, current_energy, time_remaining, host,
+                                imported)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+                 ?16, ?17, ?18, ?19, ?20, ?21, ?22, 1)",
+        params![
+            snap.timestamp.timestamp(),
+            snap.level as i32,
+            snap.is_charging,
+            snap.power_draw,
+            snap.cycle_count.map(|c| c as i32),
+            snap.max_capacity.map(|c| c as i32),
+            snap.design_capacity.map(|c| c as i32),
+            snap.battery,
+            snap.max_energy.map(|c| c as i32),
+            snap.design_energy.map(|c| c as i32),
+            snap.capacity_unit.map(|u| u.as_str()),
+            snap.model_name,
+            snap.manufacturer,
+            snap.serial_number,
+            snap.technology,
+            snap.state.map(|s| s.as_str()),
+            snap.temperature_celsius,
+            snap.voltage_mv,
+            snap.current_capacity.map(|c| c as i32),
+            snap.current_energy.map(|c| c as i32),
+            snap.time_remaining_minutes,
+            host,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Read snapshots written by `history --output`: CSV or NDJSON by the
+/// file's extension, otherwise JSON (the exported array, or `history
+/// --json` output).
+pub fn read_export(path: &Path) -> Result<Vec<BatterySnapshot>> {
+    let format = ExportFormat::for_path(&path.to_string_lossy());
+    if format == ExportFormat::Csv {
+        return read_csv_export(path);
+    }
+
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if format == ExportFormat::Ndjson {
+        return text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!(
+                        "{} line {}: not a batteryctl snapshot",
+                        path.display(),
+                        i + 1
+                    )
+                })
+            })
+            .collect();
+    }
+    let mut value: serde_json::Value = serde_json::from_str(&text)
+        .with_context(|| format!("{} is not valid JSON", path.display()))?;
+    if let Some(snapshots) = value.get_mut("snapshots") {
+        value = snapshots.take();
+    }
+    serde_json::from_value(value)
+        .with_context(|| format!("{} does not hold batteryctl snapshots", path.display()))
+}
+
+fn read_csv_export(path: &Path) -> Result<Vec<BatterySnapshot>> {
+    let mut reader = csv::Reader::from_path(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h == name);
+    let Some(timestamp_col) = column("timestamp") else {
+        anyhow::bail!("{} has no timestamp column", path.display());
+    };
+
+    let mut snapshots = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+        let field = |name: &str| {
+            column(name)
+                .and_then(|i| record.get(i))
+                .filter(|v| !v.is_empty())
+        };
+        let number = |name: &str| field(name).and_then(|v| v.parse::<f64>().ok());
+        let count = |name: &str| field(name).and_then(|v| v.parse::<u32>().ok());
+
+        let timestamp = record.get(timestamp_col).unwrap_or_default();
+        let timestamp = DateTime::parse_from_rfc3339(timestamp)
+            .with_context(|| {
+                format!(
+                    "{} line {}: bad timestamp '{}'",
+                    path.display(),
+                    line + 2,
+                    timestamp
+                )
+            })?
+            .with_timezone(&Utc);
+        let level = field("level")
+            .and_then(|v| v.parse::<u8>().ok())
+            .with_context(|| format!("{} line {}: bad level", path.display(), line + 2))?;
+
+        snapshots.push(BatterySnapshot {
+            timestamp,
+            battery: field("battery").map(str::to_string),
+            host: field("host").map(str::to_string),
+            level,
+            is_charging: field("is_charging") == Some("true"),
+            state: field("state").and_then(ChargingState::parse),
+            power_draw: number("power_draw"),
+            cycle_count: count("cycle_count"),
+            max_capacity: count("max_capacity_mah"),
+            design_capacity: count("design_capacity_mah"),
+            max_energy: count("max_energy_mwh"),
+            design_energy: count("design_energy_mwh"),
+            capacity_unit: field("capacity_unit").and_then(CapacityUnit::parse),
+            temperature_celsius: number("temperature_c"),
+            voltage_mv: number("voltage_mv"),
+            current_capacity: count("current_capacity_mah"),
+            current_energy: count("current_energy_mwh"),
+            time_remaining_minutes: field("time_remaining_min").and_then(|v| v.parse().ok()),
+            model_name: field("model_name").map(str::to_string),
+            manufacturer: field("manufacturer").map(str::to_string),
+            serial_number: field("serial_number").map(str::to_string),
+            technology: field("technology").map(str::to_string),
+        });
+    }
+    Ok(snapshots)
+}
+
+/// Write one rollup row, replacing or keeping an existing row for the same
+/// bucket depending on `replace`.
+fn insert_rollup(
+    conn: &Connection,
+    table: &str,
+    host: Option<&str>,
+    r: &Rollup,
+    replace: bool,
+) -> Result<usize> {
+    Ok(conn.execute(
+        &format!(
+            "INSERT OR {} INTO {} ({}, host) VALUES
+             (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+              ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30,
+              ?31, ?32, ?33)",
+            if replace { "REPLACE" } else { "IGNORE" },
+            table,
+            ROLLUP_COLUMNS
+        ),
+        params![
+            r.bucket,
+            r.battery,
+            r.samples,
+            r.min_level,
+            r.max_level,
+            r.avg_level,
+            r.level_discharged,
+            r.charging_periods,
+            r.charging_minutes,
+            r.discharging_minutes,
+            r.not_charging_minutes,
+            r.energy_in_mwh,
+            r.energy_out_mwh,
+            r.avg_power,
+            r.avg_discharge_power,
+            r.discharge_power_samples,
+            r.avg_temperature,
+            r.max_temperature,
+            r.temperature_samples,
+            r.state.map(|s| s.as_str()),
+            r.is_charging,
+            r.cycle_count,
+            r.max_capacity,
+            r.design_capacity,
+            r.max_energy,
+            r.design_energy,
+            r.capacity_unit.map(|u| u.as_str()),
+            r.sleep_minutes,
+            r.health_p25,
+            r.health_median,
+            r.health_p75,
+            r.health_samples,
+            host.unwrap_or(""),
+        ],
+    )?)
+}
+
+/// Newest raw timestamp included in the rollups.
+const META_ROLLUPS_THROUGH: &str = "rollups_through";
+
+const ROLLUP_COLUMNS: &str =
+    "bucket, battery, samples, min_level, max_level, avg_level, level_discharged,
+     charging_periods, charging_minutes, discharging_minutes, not_charging_minutes,
+     energy_in_mwh, energy_out_mwh, avg_power, avg_discharge_power, discharge_power_samples,
+     avg_temperature, max_temperature, temperature_samples, state, is_charging, cycle_count,
+     max_capacity, design_capacity, max_energy, design_energy, capacity_unit, sleep_minutes,
+     health_p25, health_median, health_p75, health_samples";
+
+fn rollup_from_row(row: &rusqlite::Row) -> rusqlite::Result<Rollup> {
+    let state: Option<String> = row.get(19)?;
+    let capacity_unit: Option<String> = row.get(26)?;
+    Ok(Rollup {
+        bucket: row.get(0)?,
+        battery: row.get(1)?,
+        samples: row.get(2)?,
+        min_level: row.get(3)?,
+        max_level: row.get(4)?,
+        avg_level: row.get(5)?,
+        level_discharged: row.get(6)?,
+        charging_periods: row.get(7)?,
+        charging_minutes: row.get(8)?,
+        discharging_minutes: row.get(9)?,
+        not_charging_minutes: row.get(10)?,
+        energy_in_mwh: row.get(11)?,
+        energy_out_mwh: row.get(12)?,
+        avg_power: row.get(13)?,
+        avg_discharge_power: row.get(14)?,
+        discharge_power_samples: row.get(15)?,
+        avg_temperature: row.get(16)?,
+        max_temperature: row.get(17)?,
+        temperature_samples: row.get(18)?,
+        state: state.as_deref().and_then(ChargingState::parse),
+        is_charging: row.get(20)?,
+        cycle_count: row.get(21)?,
+        max_capacity: row.get(22)?,
+        design_capacity: row.get(23)?,
+        max_energy: row.get(24)?,
+        design_energy: row.get(25)?,
+        capacity_unit: capacity_unit.as_deref().and_then(CapacityUnit::parse),
+        sleep_minutes: row.get(27)?,
+        health_p25: row.get(28)?,
+        health_median: row.get(29)?,
+        health_p75: row.get(30)?,
+        health_samples: row.get(31)?,
+    })
+}
+
+fn event_from_row(row: &rusqlite::Row) -> rusqlite::Result<BatteryEvent> {
+    let ts: i64 = row.get(1)?;
+    Ok(BatteryEvent {
+        id: row.get(0)?,
+        timestamp: DateTime::from_timestamp(ts, 0).unwrap_or_default(),
+        battery: row.get(2)?,
+        kind: row.get(3)?,
+        serial_number: row.get(4)?,
+        model_name: row.get(5)?,
+        detail: row.get(6)?,
+        host: row.get(7)?,
+    })
+}
+
+/// Summary of raw snapshots, oldest first.
+fn summarize_snapshots(snapshots: &[BatterySnapshot], range: &TimeRange) -> HistorySummary {
+    if snapshots.is_empty() {
+        return HistorySummary {
+            period_description: range.describe(),
+            snapshots_count: 0,
+            avg_level: 0.0,
+            min_level: 0,
+            max_level: 0,
+            charging_periods: 0,
+            total_charging_minutes: 0,
+            total_discharging_minutes: 0,
+            total_not_charging_minutes: 0,
+            total_sleep_minutes: 0,
+            avg_discharge_rate_watts: None,
+            estimated_cycles: 0.0,
+            avg_temperature_celsius: None,
+            max_temperature_celsius: None,
+            granularity: Granularity::Raw,
+        };
+    }
+
+    let levels: Vec<f64> = snapshots.iter().map(|s| s.level as f64).collect();
+    let avg_level = levels.iter().sum::<f64>() / levels.len() as f64;
+    let min_level = snapshots.iter().map(|s| s.level).min().unwrap_or(0);
+    let max_level = snapshots.iter().map(|s| s.level).max().unwrap_or(100);
+
+    // Count charging periods (transitions from not-charging to charging)
+    let mut charging_periods = 0u32;
+    let mut charging_minutes = 0i64;
+    let mut discharging_minutes = 0i64;
+    let mut not_charging_minutes = 0i64;
+    let mut sleep_minutes = 0i64;
+    let mut was_charging = false;
+
+    for i in 0..snapshots.len() {
+        if snapshots[i].is_charging && !was_charging {
+            charging_periods += 1;
+        }
+        was_charging = snapshots[i].is_charging;
+
+        if i + 1 < snapshots.len() {
+            let dt = snapshots[i + 1]
+                .timestamp
+                .signed_duration_since(snapshots[i].timestamp)
+                .num_minutes();
+            if sleep::is_sleep_gap(&snapshots[i], &snapshots[i + 1]) {
+                sleep_minutes += dt;
+                continue;
+            }
+            match snapshots[i].effective_state() {
+                ChargingState::Charging => charging_minutes += dt,
+                ChargingState::Full | ChargingState::NotCharging => not_charging_minutes += dt,
+                _ => discharging_minutes += dt,
+            }
+        }
+    }
+
+    // Average discharge rate
+    let discharge_powers: Vec<f64> = snapshots
+        .iter()
+        .filter(|s| !s.is_charging)
+        .filter_map(|s| s.power_draw)
+        .collect();
+    let avg_discharge_rate = if !discharge_powers.is_empty() {
+        Some(discharge_powers.iter().sum::<f64>() / discharge_powers.len() as f64)
+    } else {
+        None
+    };
+
+    // Estimate cycles: sum of |level changes| / 100
+    let mut total_level_change: f64 = 0.0;
+    for i in 1..snapshots.len() {
+        let diff = (snapshots[i].level as f64 - snapshots[i - 1].level as f64).abs();
+        if !snapshots[i].is_charging && !snapshots[i - 1].is_charging {
+            total_level_change += diff;
+        }
+    }
+    let estimated_cycles = total_level_change / 100.0;
+
+    let temperatures: Vec<f64> = snapshots
+        .iter()
+        .filter_map(|s| s.temperature_celsius)
+        .collect();
+    let avg_temperature = if temperatures.is_empty() {
+        None
+    } else {
+        Some(temperatures.iter().sum::<f64>() / temperatures.len() as f64)
+    };
+    let max_temperature = temperatures.iter().copied().reduce(f64::max);
+
+    HistorySummary {
+        period_description: range.describe(),
+        snapshots_count: snapshots.len(),
+        avg_level,
+        min_level,
+        max_level,
+        charging_periods,
+        total_charging_minutes: charging_minutes,
+        total_discharging_minutes: discharging_minutes,
+        total_not_charging_minutes: not_charging_minutes,
+        total_sleep_minutes: sleep_minutes,
+        avg_discharge_rate_watts: avg_discharge_rate,
+        estimated_cycles,
+        avg_temperature_celsius: avg_temperature,
+        max_temperature_celsius: max_temperature,
+        granularity: Granularity::Raw,
+    }
+}
+
+/// Combine rollups into a summary matching what the raw rows would give.
+fn summarize_rollups(
+    rollups: &[Rollup],
+    range: &TimeRange,
+    granularity: Granularity,
+) -> HistorySummary {
+    let samples: u32 = rollups.iter().map(|r| r.samples).sum();
+    let weighted = |value: fn(&Rollup) -> Option<f64>, weight: fn(&Rollup) -> u32| {
+        let total: u32 = rollups
+            .iter()
+            .filter(|r| value(r).is_some())
+            .map(weight)
+            .sum();
+        (total > 0).then(|| {
+            rollups
+                .iter()
+                .filter_map(|r| value(r).map(|v| v * weight(r) as f64))
+                .sum::<f64>()
+                / total as f64
+        })
+    };
+
+    HistorySummary {
+        period_description: range.describe(),
+        snapshots_count: samples as usize,
+        avg_level: weighted(|r| Some(r.avg_level), |r| r.samples).unwrap_or(0.0),
+        min_level: rollups.iter().map(|r| r.min_level).min().unwrap_or(0),
+        max_level: rollups.iter().map(|r| r.max_level).max().unwrap_or(0),
+        charging_periods: rollups.iter().map(|r| r.charging_periods).sum(),
+        total_charging_minutes: rollups.iter().map(|r| r.charging_minutes).sum(),
+        total_discharging_minutes: rollups.iter().map(|r| r.discharging_minutes).sum(),
+        total_not_charging_minutes: rollups.iter().map(|r| r.not_charging_minutes).sum(),
+        total_sleep_minutes: rollups.iter().map(|r| r.sleep_minutes).sum(),
+        avg_discharge_rate_watts: weighted(
+            |r| r.avg_discharge_power,
+            |r| r.discharge_power_samples,
+        ),
+        estimated_cycles: rollups.iter().map(|r| r.level_discharged).sum::<f64>() / 100.0,
+        avg_temperature_celsius: weighted(|r| r.avg_temperature, |r| r.temperature_samples),
+        max_temperature_celsius: rollups
+            .iter()
+            .filter_map(|r| r.max_temperature)
+            .reduce(f64::max),
+        granularity,
+    }
+}
+
+const SNAPSHOT_COLUMNS: &str =
+    "timestamp, level, is_charging, power_draw, cycle_count, max_capacity, design_capacity, battery,
+     max_energy, design_energy, capacity_unit, model_name, manufacturer, serial_number, technology,
+     state, temperature, voltage, current_capacity, current_energy, time_remaining, host";
+
+fn snapshot_from_row(row: &rusqlite::Row) -> rusqlite::Result<BatterySnapshot> {
+    let ts: i64 = row.get(0)?;
+    let level: i32 = row.get(1)?;
+    let is_charging: bool = row.get(2)?;
+    let power_draw: Option<f64> = row.get(3)?;
+    let cycle_count: Option<i32> = row.get(4)?;
+    let max_capacity: Option<i32> = row.get(5)?;
+    let design_capacity: Option<i32> = row.get(6)?;
+    let battery: Option<String> = row.get(7)?;
+    let max_energy: Option<i32> = row.get(8)?;
+    let design_energy: Option<i32> = row.get(9)?;
+    let capacity_unit: Option<String> = row.get(10)?;
+    let state: Option<String> = row.get(15)?;
+    let current_capacity: Option<i32> = row.get(18)?;
+    let current_energy: Option<i32> = row.get(19)?;
+
+    Ok(BatterySnapshot {
+        timestamp: DateTime::from_timestamp(ts, 0).unwrap_or_default(),
+        battery,
+        level: level.clamp(0, 100) as u8,
+        is_charging,
+        power_draw,
+        cycle_count: cycle_count.map(|c| c as u32),
+        max_capacity: max_capacity.map(|c| c as u32),
+        design_capacity: design_capacity.map(|c| c as u32),
+        max_energy: max_energy.map(|c| c as u32),
+        design_energy: design_energy.map(|c| c as u32),
+        capacity_unit: capacity_unit.as_deref().and_then(CapacityUnit::parse),
+        model_name: row.get(11)?,
+        manufacturer: row.get(12)?,
+        serial_number: row.get(13)?,
+        technology: row.get(14)?,
+        state: state.as_deref().and_then(ChargingState::parse),
+        temperature_celsius: row.get(16)?,
+        voltage_mv: row.get(17)?,
+        current_capacity: current_capacity.map(|c| c as u32),
+        current_energy: current_energy.map(|c| c as u32),
+        time_remaining_minutes: row.get(20)?,
+        host: row.get(21)?,
+    })
+}
+
+/// Why `info` looks like a different physical pack than the one that
+/// produced `previous`, if it does.
+fn replacement_reason(previous: &BatterySnapshot, info: &BatteryInfo) -> Option<String> {
+    if let (Some(old), Some(new)) = (&previous.serial_number, &info.serial_number) {
+        if old != new {
+            return Some(format!("serial number changed from {} to {}", old, new));
+        }
+    }
+
+    // Rows without a unit may hold either unit, so only compare like with like
+    if previous.capacity_unit == Some(info.capacity_unit) {
+        let (old, new) = match info.capacity_unit {
+            CapacityUnit::MilliampHours => (previous.design_capacity, info.design_capacity_mah),
+            CapacityUnit::MilliwattHours => (previous.design_energy, info.design_energy_mwh),
+        };
+        if let (Some(old), Some(new)) = (old, new) {
+            // Allow for rounding in firmware that derives one unit from the other
+            if old.abs_diff(new) as f64 > old as f64 * 0.01 {
+                return Some(format!(
+                    "design capacity changed from {} to {} {}",
+                    old, new, info.capacity_unit
+                ));
+            }
+        }
+    }
+
+    if let (Some(old), Some(new)) = (previous.cycle_count, info.cycle_count) {
+        if new < old {
+            return Some(format!("cycle count reset from {} to {}", old, new));
+        }
+    }
+
+    None
+}
+
+/// Default database location: `batteryctl/history.db` in the XDG data
+/// directory (`~/.local/share` on Linux, `~/Library/Application Support` on
+/// macOS).
+///
+/// A database left at the legacy `~/.batteryctl/history.db` is moved there
+/// the first time it is looked up.
+pub fn get_db_path() -> Result<PathBuf> {
+    let data_dir = dirs::data_dir().context("Could not determine data directory")?;
+    let path = data_dir.join("batteryctl").join("history.db");
+
+    if let Some(legacy) = dirs::home_dir().map(|h| h.join(".batteryctl").join("history.db")) {
+        if legacy.exists() && !path.exists() {
+            move_database(&legacy, &path)?;
+            eprintln!(
+                "Moved history database from {} to {}",
+                legacy.display(),
+                path.display()
+            );
+        }
+    }
+    Ok(path)
+}
+
+/// Move a database and its journal files, copying when `rename` cannot
+/// cross filesystems.
+fn move_database(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    for suffix in ["", "-journal", "-wal", "-shm"] {
+        let src = PathBuf::from(format!("{}{}", from.display(), suffix));
+        let dest = PathBuf::from(format!("{}{}", to.display(), suffix));
+        if !src.exists() {
+            continue;
+        }
+        if std::fs::rename(&src, &dest).is_err() {
+            std::fs::copy(&src, &dest).with_context(|| {
+                format!("Failed to move {} to {}", src.display(), dest.display())
+            })?;
+            std::fs::remove_file(&src)?;
+        }
+    }
+    // Drop the legacy directory if the database was all it held
+    if let Some(dir) = from.parent() {
+        let _ = std::fs::remove_dir(dir);
+    }
+    Ok(())
+}
+
+/// Human description of a period, e.g. "Last 3 days", "Last 1 week" (for
+/// 7d) or "Last 1 day 12 hours".
+pub fn format_duration(d: &Duration) -> String {
+    format!("Last {}", describe_duration(d))
+}
+
+/// A length of time in words, e.g. "30 seconds" or "1 day 12 hours".
+///
+/// Every non-zero unit is kept, so the period is exactly the one asked for,
+/// with a month being 30 days and a year 365, the same as
+/// `parse_duration_str`.
+pub fn describe_duration(d: &Duration) -> String {
+    const UNITS: [(&str, i64); 7] = [
+        ("year", 365 * 86400),
+        ("month", 30 * 86400),
+        ("week", 7 * 86400),
+        ("day", 86400),
+        ("hour", 3600),
+        ("minute", 60),
+        ("second", 1),
+    ];
+
+    let mut seconds = d.num_seconds().max(0);
+    let mut parts = Vec::new();
+    for (name, size) in UNITS {
+        let count = seconds / size;
+        seconds %= size;
+        if count > 0 {
+            parts.push(format!(
+                "{} {}{}",
+                count,
+                name,
+                if count != 1 { "s" } else { "" }
+            ));
+        }
+    }
+    if parts.is_empty() {
+        parts.push("0 minutes".to_string());
+    }
+    parts.join(" ")
+}
+
+/// Parse a duration: one or more `<n><unit>` terms (e.g. `24h`, `1d12h`,
+/// `90min`), or an ISO-8601 duration (e.g. `P7D`, `PT36H`, `P1DT12H`).
+///
+/// Units are `s`, `min`, `h`, `d`, `w`, `m` or `mo` (30 days) and `y`
+/// (365 days), with long forms such as `hours` also accepted. `m` means
+/// months, as it always has; use `min` for minutes.
+pub fn parse_duration_str(s: &str) -> Result<Duration> {
+    let s = s.trim().to_lowercase();
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid duration '{}'. Use e.g. 24h, 7d, 1d12h, 90min, 1m (month) or P1DT12H",
+            s
+        )
+    };
+    if s.is_empty() {
+        return Err(invalid());
+    }
+    if let Some(iso) = s.strip_prefix('p') {
+        return parse_iso_duration(iso).ok_or_else(invalid);
+    }
+
+    let mut total = Duration::zero();
+    let mut rest = s.as_str();
+    while !rest.is_empty() {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let num: i64 = rest[..digits]
+            .parse()
+            .with_context(|| format!("Invalid number in duration '{}'", s))?;
+        rest = rest[digits..].trim_start();
+        let letters = rest.len()
+            - rest
+                .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+                .len();
+        let unit = match &rest[..letters] {
+            "s" | "sec" | "secs" | "second" | "seconds" => Duration::seconds(1),
+            "min" | "mins" | "minute" | "minutes" => Duration::minutes(1),
+            "h" | "hr" | "hrs" | "hour" | "hours" => Duration::hours(1),
+            "d" | "day" | "days" => Duration::days(1),
+            "w" | "wk" | "wks" | "week" | "weeks" => Duration::weeks(1),
+            "m" | "mo" | "month" | "months" => Duration::days(30),
+            "y" | "yr" | "yrs" | "year" | "years" => Duration::days(365),
+            _ => return Err(invalid()),
+        };
+        total = i32::try_from(num)
+            .ok()
+            .and_then(|num| unit.checked_mul(num))
+            .and_then(|term| total.checked_add(&term))
+            .ok_or_else(|| anyhow::anyhow!("Duration '{}' is too long", s))?;
+        rest = rest[letters..].trim_start();
+    }
+    Ok(total)
+}
+
+/// The part of an ISO-8601 duration after the `P`: `[nY][nM][nW][nD][T[nH][nM][nS]]`.
+fn parse_iso_duration(s: &str) -> Option<Duration> {
+    let (date, time) = match s.split_once('t') {
+        Some((date, time)) if !time.is_empty() => (date, Some(time)),
+        Some(_) => return None,
+        None => (s, None),
+    };
+
+    let mut total = Duration::zero();
+    let mut terms = 0;
+    let mut add = |part: &str, units: &[(char, Duration)]| -> Option<()> {
+        let mut rest = part;
+        // Units must appear in order, each at most once
+        let mut next_unit = 0;
+        while !rest.is_empty() {
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let num: i32 = rest[..digits].parse().ok()?;
+            let unit = rest[digits..].chars().next()?;
+            let index = units[next_unit..].iter().position(|(u, _)| *u == unit)? + next_unit;
+            total = total.checked_add(&units[index].1.checked_mul(num)?)?;
+            terms += 1;
+            next_unit = index + 1;
+            rest = &rest[digits + 1..];
+        }
+        Some(())
+    };
+    add(
+        date,
+        &[
+            ('y', Duration::days(365)),
+            ('m', Duration::days(30)),
+            ('w', Duration::weeks(1)),
+            ('d', Duration::days(1)),
+        ],
+    )?;
+    if let Some(time) = time {
+        add(
+            time,
+            &[
+                ('h', Duration::hours(1)),
+                ('m', Duration::minutes(1)),
+                ('s', Duration::seconds(1)),
+            ],
+        )?;
+    }
+    (terms > 0).then_some(total)
+}
+
+/// Parse a point in time: RFC 3339 (`2026-09-01T08:00:00Z`), a local date
+/// and time (`2026-09-01 08:00`), a local date (midnight), or a duration
+/// ago (`36h`).
+pub fn parse_time_str(s: &str) -> Result<DateTime<Utc>> {
+    let s = s.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let local = |naive: NaiveDateTime| {
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+    };
+    for format in [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Some(time) = NaiveDateTime::parse_from_str(s, format)
+            .ok()
+            .and_then(local)
+        {
+            return Ok(time);
+        }
+    }
+    if let Some(time) = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(local)
+    {
+        return Ok(time);
+    }
+    if let Ok(ago) = parse_duration_str(s) {
+        return Ok(Utc::now() - ago);
+    }
+    anyhow::bail!(
+        "Invalid time '{}'. Use e.g. 2026-09-01, '2026-09-01 08:00', 2026-09-01T08:00:00Z or 36h",
+        s
+    )
+}
+
+/// Period a history query covers: the last `<duration>` up to now, or an
+/// absolute window from `--since` / `--until`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: DateTime<Utc>,
+    /// `None` runs up to now, including rows recorded while reading.
+    pub end: Option<DateTime<Utc>>,
+    /// Set for ranges given as a duration back from now.
+    pub last: Option<Duration>,
+}
+
+impl TimeRange {
+    pub fn last(duration: Duration) -> Self {
+        Self {
+            start: Utc::now() - duration,
+            end: None,
+            last: Some(duration),
+        }
+    }
+
+    /// From `since` (or the first reading ever) to `until` (or now).
+    pub fn between(since: Option<&str>, until: Option<&str>) -> Result<Self> {
+        let range = Self {
+            start: since
+                .map(parse_time_str)
+                .transpose()?
+                .unwrap_or(DateTime::UNIX_EPOCH),
+            end: until.map(parse_time_str).transpose()?,
+            last: None,
+        };
+        if range.end.unwrap_or_else(Utc::now) <= range.start {
+            anyhow::bail!("The start of the period must be before its end");
+        }
+        Ok(range)
+    }
+
+    /// Whether the period reaches back to the first reading.
+    pub fn is_open_start(&self) -> bool {
+        self.start <= DateTime::UNIX_EPOCH
+    }
+
+    /// Build the range for `--duration`, `--since` and `--until`: from
+    /// `since` to `until` (or now), or `duration` back from `until` (or now).
+    pub fn parse(duration: &str, since: Option<&str>, until: Option<&str>) -> Result<Self> {
+        let end = until.map(parse_time_str).transpose()?;
+        let range = match (since, end) {
+            (None, None) => Self::last(parse_duration_str(duration)?),
+            (Some(since), end) => Self {
+                start: parse_time_str(since)?,
+                end,
+                last: None,
+            },
+            (None, Some(end)) => Self {
+                start: end - parse_duration_str(duration)?,
+                end: Some(end),
+                last: None,
+            },
+        };
+        if range.end.unwrap_or_else(Utc::now) <= range.start {
+            anyhow::bail!("The start of the period must be before its end");
+        }
+        Ok(range)
+    }
+
+    /// Length of the period.
+    pub fn span(&self) -> Duration {
+        self.end.unwrap_or_else(Utc::now) - self.start
+    }
+
+    /// How long ago the period starts, which decides what data is still kept.
+    pub fn age(&self) -> Duration {
+        Utc::now() - self.start
+    }
+
+    /// "Last 3 days", "Since 2026-09-01 08:00" or "2026-09-01 00:00 to 2026-09-08 00:00",
+    /// in local time.
+    pub fn describe(&self) -> String {
+        let local = |time: DateTime<Utc>| time.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+        match (self.last, self.end) {
+            (Some(duration), None) => format_duration(&duration),
+            (_, None) if self.is_open_start() => "All history".to_string(),
+            (_, Some(end)) if self.is_open_start() => format!("Until {}", local(end)),
+            (_, None) => format!("Since {}", local(self.start)),
+            (_, Some(end)) => format!("{} to {}", local(self.start), local(end)),
+        }
+    }
+}
+
+impl From<Duration> for TimeRange {
+    fn from(duration: Duration) -> Self {
+        Self::last(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::{BatteryCondition, ChargingState};
+    use crate::export;
+
+    fn make_test_info(level: u8, charging: bool) -> BatteryInfo {
         BatteryInfo {
+            name: "BAT0".to_string(),
             level,
             state: if charging {
                 ChargingState::Charging
@@ -333,23 +2565,857 @@ mod tests {
             condition: BatteryCondition::Normal,
             manufacture_date: None,
             is_present: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_and_retrieve() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+
+        let info = make_test_info(80, false);
+        hist.record_snapshot(&info).unwrap();
+        hist.record_snapshot(&info).unwrap();
+
+        assert_eq!(hist.snapshot_count().unwrap(), 2);
+
+        let snapshots = hist.get_snapshots_range(Duration::hours(1)).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].level, 80);
+    }
+
+    #[test]
+    fn test_concurrent_writers_and_readers() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Barrier};
+
+        const WRITERS: usize = 4;
+        const READERS: usize = 3;
+        const RECORDS: usize = 25;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        // Every thread opens the new file at once, racing to migrate it
+        let start = Arc::new(Barrier::new(WRITERS + READERS + 1));
+        let writing = Arc::new(AtomicBool::new(true));
+
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|w| {
+                let (path, start) = (path.clone(), start.clone());
+                std::thread::spawn(move || {
+                    start.wait();
+                    let hist = HistoryManager::open_at(&path).unwrap();
+                    for i in 0..RECORDS {
+                        let info = make_test_info((w * RECORDS + i) as u8 % 100, i % 2 == 0);
+                        hist.record_snapshot(&info).unwrap();
+                    }
+                })
+            })
+            .collect();
+        // Imports write alongside the recorders too
+        let importer = {
+            let (path, start) = (path.clone(), start.clone());
+            std::thread::spawn(move || {
+                start.wait();
+                let hist = HistoryManager::open_at(&path).unwrap();
+                let now = Utc::now().timestamp();
+                for i in 0..RECORDS {
+                    let snap = BatterySnapshot {
+                        timestamp: DateTime::from_timestamp(now - 86400 + i as i64 * 60, 0)
+                            .unwrap(),
+                        battery: Some("BAT0".to_string()),
+                        level: 50,
+                        ..Default::default()
+                    };
+                    hist.import_snapshots(&[snap], None).unwrap();
+                }
+            })
+        };
+        let readers: Vec<_> = (0..READERS)
+            .map(|_| {
+                let (path, start, writing) = (path.clone(), start.clone(), writing.clone());
+                std::thread::spawn(move || {
+                    start.wait();
+                    let hist = HistoryManager::open_at(&path).unwrap();
+                    let mut reads = 0;
+                    loop {
+                        hist.get_snapshots_range(Duration::hours(1)).unwrap();
+                        hist.get_summary(Duration::days(30)).unwrap();
+                        reads += 1;
+                        if !writing.load(Ordering::Relaxed) {
+                            return reads;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for writer in writers {
+            writer.join().unwrap();
         }
+        importer.join().unwrap();
+        writing.store(false, Ordering::Relaxed);
+        for reader in readers {
+            assert!(reader.join().unwrap() > 0);
+        }
+
+        let hist = HistoryManager::open_at(&path).unwrap();
+        assert_eq!(hist.snapshot_count().unwrap(), (WRITERS + 1) * RECORDS);
+        assert_eq!(hist.installs().unwrap().len(), 1);
+        assert_eq!(hist.check().unwrap().sqlite, "ok");
+        let mode: String = hist
+            .conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
     }
 
     #[test]
-    fn test_record_and_retrieve() {
+    fn test_snapshots_scoped_by_battery() {
         let tmp = tempfile::NamedTempFile::new().unwrap();
         let hist = HistoryManager::open_at(tmp.path()).unwrap();
 
-        let info = make_test_info(80, false);
+        let mut bat0 = make_test_info(80, false);
+        bat0.name = "BAT0".to_string();
+        let mut bat1 = make_test_info(40, false);
+        bat1.name = "BAT1".to_string();
+        let combined = crate::battery::combine_batteries(vec![bat0, bat1]);
+        hist.record_snapshot(&combined).unwrap();
+
+        assert_eq!(hist.snapshot_count().unwrap(), 3);
+
+        let hist = hist.with_battery(Some("bat1"));
+        let snapshots = hist.get_snapshots_range(Duration::hours(1)).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].level, 40);
+        assert_eq!(snapshots[0].battery.as_deref(), Some("BAT1"));
+    }
+
+    #[test]
+    fn test_energy_units_are_stored() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+
+        let info = BatteryInfo {
+            max_capacity_mah: None,
+            design_capacity_mah: None,
+            max_energy_mwh: Some(50160),
+            design_energy_mwh: Some(57000),
+            capacity_unit: CapacityUnit::MilliwattHours,
+            ..make_test_info(64, false)
+        };
         hist.record_snapshot(&info).unwrap();
+
+        let snap = &hist.get_snapshots_range(Duration::hours(1)).unwrap()[0];
+        assert_eq!(snap.max_capacity, None);
+        assert_eq!(snap.max_energy, Some(50160));
+        assert_eq!(snap.capacity_unit, Some(CapacityUnit::MilliwattHours));
+        assert_eq!(
+            snap.native_capacity(),
+            Some((50160, Some(57000), CapacityUnit::MilliwattHours))
+        );
+    }
+
+    #[test]
+    fn test_identity_is_stored() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+
+        let info = BatteryInfo {
+            model_name: Some("5B10W13930".to_string()),
+            serial_number: Some("1234".to_string()),
+            ..make_test_info(64, false)
+        };
         hist.record_snapshot(&info).unwrap();
 
+        let snap = &hist.get_snapshots_range(Duration::hours(1)).unwrap()[0];
+        assert_eq!(snap.model_name.as_deref(), Some("5B10W13930"));
+        assert_eq!(snap.serial_number.as_deref(), Some("1234"));
+        assert_eq!(snap.manufacturer, None);
+    }
+
+    #[test]
+    fn test_replacement_reason() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let old = BatteryInfo {
+            serial_number: Some("1234".to_string()),
+            ..make_test_info(80, false)
+        };
+        hist.record_snapshot(&old).unwrap();
+        let previous = hist.latest_snapshot("BAT0").unwrap().unwrap();
+
+        assert_eq!(replacement_reason(&previous, &old), None);
+        let swapped = BatteryInfo {
+            serial_number: Some("5678".to_string()),
+            ..old.clone()
+        };
+        assert!(replacement_reason(&previous, &swapped)
+            .unwrap()
+            .contains("serial number"));
+        let larger = BatteryInfo {
+            serial_number: None,
+            design_capacity_mah: Some(5200),
+            ..old.clone()
+        };
+        assert!(replacement_reason(&previous, &larger)
+            .unwrap()
+            .contains("design capacity"));
+        let reset = BatteryInfo {
+            cycle_count: Some(3),
+            ..old.clone()
+        };
+        assert!(replacement_reason(&previous, &reset)
+            .unwrap()
+            .contains("cycle count"));
+        let rounded = BatteryInfo {
+            design_capacity_mah: Some(4510),
+            cycle_count: Some(51),
+            ..old
+        };
+        assert_eq!(replacement_reason(&previous, &rounded), None);
+    }
+
+    #[test]
+    fn test_cycle_counter_persists_and_resets_with_the_pack() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let now = Utc::now().timestamp();
+        let reading = |level: u8| BatteryInfo {
+            cycle_count: None,
+            serial_number: Some("1234".to_string()),
+            max_energy_mwh: Some(50000),
+            ..make_test_info(level, false)
+        };
+
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        hist.record_snapshot_at(now - 7200, &reading(80)).unwrap();
+        hist.record_snapshot_at(now - 3600, &reading(55)).unwrap();
+        drop(hist);
+
+        // Counting carries on across runs
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        hist.record_snapshot_at(now - 1800, &reading(30)).unwrap();
+        let counter = hist
+            .with_battery(Some("BAT0"))
+            .cycle_counter()
+            .unwrap()
+            .unwrap();
+        assert!((counter.cycles - 0.5).abs() < 1e-9);
+        assert!((counter.discharged_mwh - 25000.0).abs() < 1e-6);
+        assert_eq!(counter.since.timestamp(), now - 7200);
+
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let new = BatteryInfo {
+            serial_number: Some("5678".to_string()),
+            ..reading(100)
+        };
+        hist.record_snapshot_at(now - 60, &new).unwrap();
+        let hist = hist.with_battery(Some("BAT0"));
+        let counter = hist.cycle_counter().unwrap().unwrap();
+        assert_eq!(counter.cycles, 0.0);
+        assert_eq!(counter.since.timestamp(), now - 60);
+
+        // The replaced pack's count is gone with it
+        let first = hist.installs().unwrap()[0].id;
+        let old = hist.with_pack(PackScope::Install(first));
+        assert!(old.cycle_counter().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_history_is_segmented_per_pack() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let now = Utc::now().timestamp();
+
+        let old = BatteryInfo {
+            serial_number: Some("1234".to_string()),
+            cycle_count: Some(600),
+            ..make_test_info(80, false)
+        };
+        hist.record_snapshot_at(now - 300, &old).unwrap();
+        hist.record_snapshot_at(
+            now - 200,
+            &BatteryInfo {
+                level: 40,
+                ..old.clone()
+            },
+        )
+        .unwrap();
+        let new = BatteryInfo {
+            serial_number: Some("5678".to_string()),
+            cycle_count: Some(2),
+            ..make_test_info(95, false)
+        };
+        hist.record_snapshot_at(now - 100, &new).unwrap();
+        hist.record_snapshot_at(now - 50, &new).unwrap();
+
+        let installs = hist.installs().unwrap();
+        assert_eq!(installs.len(), 2);
+        assert_eq!(installs[0].detail.as_deref(), Some("first seen"));
+        assert_eq!(installs[1].timestamp.timestamp(), now - 100);
+        assert_eq!(installs[1].serial_number.as_deref(), Some("5678"));
+
+        // The current pack excludes the old pack's readings and discharge
+        let current = hist.get_snapshots_range(Duration::hours(1)).unwrap();
+        assert_eq!(current.len(), 2);
+        assert_eq!(
+            hist.get_summary(Duration::hours(1))
+                .unwrap()
+                .estimated_cycles,
+            0.0
+        );
+
+        let hist = hist.with_pack(PackScope::Install(installs[0].id));
+        let earlier = hist.get_snapshots_range(Duration::hours(1)).unwrap();
+        assert_eq!(earlier.len(), 2);
+        assert_eq!(earlier[1].level, 40);
+
+        let hist = hist.with_pack(PackScope::All);
+        assert_eq!(
+            hist.get_snapshots_range(Duration::hours(1)).unwrap().len(),
+            4
+        );
+        assert!(hist
+            .with_pack(PackScope::Install(99))
+            .selected_install()
+            .is_err());
+    }
+
+    #[test]
+    fn test_open_upgrades_existing_database() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let ts = Utc::now().timestamp() - 60;
+        {
+            // Schema and data as written by the first release
+            let conn = Connection::open(tmp.path()).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE snapshots (
+                    timestamp INTEGER NOT NULL,
+                    level INTEGER NOT NULL,
+                    is_charging BOOLEAN NOT NULL,
+                    power_draw REAL,
+                    cycle_count INTEGER,
+                    max_capacity INTEGER,
+                    design_capacity INTEGER
+                );",
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO snapshots VALUES (?1, 64, 0, 9.5, 187, 4343, 4935)",
+                params![ts],
+            )
+            .unwrap();
+        }
+
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        assert_eq!(
+            migrations::schema_version(&hist.conn).unwrap(),
+            migrations::SCHEMA_VERSION
+        );
+        hist.record_snapshot(&make_test_info(60, false)).unwrap();
+
+        let snaps = hist.get_snapshots_range(Duration::hours(1)).unwrap();
+        assert_eq!(snaps.len(), 2);
+        assert_eq!(snaps[0].level, 64);
+        assert_eq!(snaps[0].battery, None);
+        assert_eq!(snaps[0].max_capacity, Some(4343));
+        assert_eq!(snaps[0].cycle_count, Some(187));
+    }
+
+    #[test]
+    fn test_full_reading_is_stored_and_summarised() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let now = Utc::now().timestamp();
+
+        let reading = |state, temperature| BatteryInfo {
+            state,
+            temperature_celsius: Some(temperature),
+            voltage_mv: Some(11800.0),
+            current_capacity_mah: Some(3000),
+            time_remaining_minutes: Some(95),
+            ..make_test_info(80, false)
+        };
+        hist.record_snapshot_at(now - 1800, &reading(ChargingState::Discharging, 30.0))
+            .unwrap();
+        hist.record_snapshot_at(now - 1200, &reading(ChargingState::NotCharging, 34.0))
+            .unwrap();
+        hist.record_snapshot_at(now - 600, &reading(ChargingState::Charging, 38.5))
+            .unwrap();
+
+        let snaps = hist.get_snapshots_range(Duration::hours(1)).unwrap();
+        assert_eq!(snaps[1].state, Some(ChargingState::NotCharging));
+        assert!(!snaps[1].is_charging);
+        assert_eq!(snaps[0].voltage_mv, Some(11800.0));
+        assert_eq!(snaps[0].current_capacity, Some(3000));
+        assert_eq!(snaps[0].time_remaining_minutes, Some(95));
+
+        let summary = hist.get_summary(Duration::hours(1)).unwrap();
+        assert_eq!(summary.total_discharging_minutes, 10);
+        assert_eq!(summary.total_not_charging_minutes, 10);
+        assert_eq!(summary.max_temperature_celsius, Some(38.5));
+        assert!((summary.avg_temperature_celsius.unwrap() - 34.1666).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_move_database() {
+        let tmp = tempfile::tempdir().unwrap();
+        let legacy = tmp.path().join(".batteryctl").join("history.db");
+        let target = tmp
+            .path()
+            .join("data")
+            .join("batteryctl")
+            .join("history.db");
+        {
+            let hist = HistoryManager::open(Some(&legacy)).unwrap();
+            hist.record_snapshot(&make_test_info(64, false)).unwrap();
+        }
+
+        move_database(&legacy, &target).unwrap();
+        assert!(!legacy.exists());
+        assert!(!tmp.path().join(".batteryctl").exists());
+        let hist = HistoryManager::open_at(&target).unwrap();
+        assert_eq!(hist.snapshot_count().unwrap(), 1);
+    }
+
+    /// Three days of readings every 30 minutes: discharging by day,
+    /// charging overnight.
+    fn record_three_days(hist: &HistoryManager, now: i64) {
+        let start = Granularity::Daily.bucket_start(now) - 3 * 86400;
+        for i in 0..(3 * 48) {
+            let hour = (i / 2) % 24;
+            let charging = hour >= 20;
+            let info = BatteryInfo {
+                state: if charging {
+                    ChargingState::Charging
+                } else {
+                    ChargingState::Discharging
+                },
+                level: if charging {
+                    40 + (hour - 20) * 10
+                } else {
+                    95 - hour * 2
+                } as u8,
+                temperature_celsius: Some(30.0 + (i % 5) as f64),
+                max_energy_mwh: Some(50000),
+                ..make_test_info(0, charging)
+            };
+            hist.record_snapshot_at(start + i * 1800, &info).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_rollup_summary_matches_raw_summary() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let now = Utc::now().timestamp();
+        record_three_days(&hist, now);
+
+        let duration = TimeRange::last(Duration::days(7));
+        let raw = summarize_snapshots(&hist.get_raw_snapshots(duration).unwrap(), &duration);
+        for granularity in [Granularity::Hourly, Granularity::Daily] {
+            let rollups = hist.get_rollups(granularity, duration).unwrap();
+            let summary = summarize_rollups(&rollups, &duration, granularity);
+            assert_eq!(summary.snapshots_count, raw.snapshots_count);
+            assert_eq!(summary.min_level, raw.min_level);
+            assert_eq!(summary.max_level, raw.max_level);
+            assert!((summary.avg_level - raw.avg_level).abs() < 1e-9);
+            assert_eq!(summary.charging_periods, raw.charging_periods);
+            assert_eq!(summary.total_charging_minutes, raw.total_charging_minutes);
+            assert_eq!(
+                summary.total_discharging_minutes,
+                raw.total_discharging_minutes
+            );
+            assert!((summary.estimated_cycles - raw.estimated_cycles).abs() < 1e-9);
+            assert_eq!(summary.max_temperature_celsius, raw.max_temperature_celsius);
+        }
+
+        // Long queries read rollups
+        let summary = hist.get_summary(duration).unwrap();
+        assert_eq!(summary.granularity, Granularity::Hourly);
+        assert_eq!(hist.get_snapshots_range(duration).unwrap().len(), 3 * 24);
+        let daily = hist.get_rollups(Granularity::Daily, duration).unwrap();
+        assert_eq!(daily.len(), 3);
+        assert!(daily[1].energy_out_mwh > 0.0 && daily[1].energy_in_mwh > 0.0);
+    }
+
+    #[test]
+    fn test_suspend_gaps_are_not_discharge_time() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let start = Utc::now().timestamp() - 86400;
+        // 20 minutes in use, six hours asleep, 20 more minutes in use
+        for (offset, level) in [(0, 90), (1200, 86), (22800, 80), (24000, 76)] {
+            hist.record_snapshot_at(start + offset, &make_test_info(level, false))
+                .unwrap();
+        }
+
+        let duration = TimeRange::last(Duration::days(2));
+        let raw = summarize_snapshots(&hist.get_raw_snapshots(duration).unwrap(), &duration);
+        assert_eq!(raw.total_discharging_minutes, 40);
+        assert_eq!(raw.total_sleep_minutes, 360);
+        let rollups = hist.get_rollups(Granularity::Hourly, duration).unwrap();
+        let summary = summarize_rollups(&rollups, &duration, Granularity::Hourly);
+        assert_eq!(summary.total_discharging_minutes, 40);
+        assert_eq!(summary.total_sleep_minutes, 360);
+    }
+
+    #[test]
+    fn test_sleep_events_are_recorded() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        hist.record_sleep_event(sleep::EVENT_SLEEP, &make_test_info(80, false))
+            .unwrap();
+        hist.record_sleep_event(sleep::EVENT_WAKE, &make_test_info(79, false))
+            .unwrap();
+
+        let events = hist.sleep_events(Duration::hours(1)).unwrap();
+        let kinds: Vec<&str> = events.iter().map(|e| e.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["sleep", "wake"]);
         assert_eq!(hist.snapshot_count().unwrap(), 2);
+        // Sleep events are not mistaken for installs
+        assert_eq!(hist.installs().unwrap().len(), 1);
+    }
 
-        let snapshots = hist.get_snapshots_range(Duration::hours(1)).unwrap();
-        assert_eq!(snapshots.len(), 2);
-        assert_eq!(snapshots[0].level, 80);
+    #[test]
+    fn test_retention_keeps_rollups_of_pruned_rows() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path())
+            .unwrap()
+            .with_retention(RetentionPolicy {
+                raw_days: 2,
+                hourly_days: 2,
+                daily_days: Some(30),
+            });
+        // Noon yesterday, so both recent readings share a daily bucket
+        let now = Granularity::Daily.bucket_start(Utc::now().timestamp()) - 86400 + 12 * 3600;
+        record_three_days(&hist, now - 10 * 86400);
+        hist.record_snapshot_at(now - 3600, &make_test_info(70, false))
+            .unwrap();
+
+        hist.refresh_stale_rollups().unwrap();
+        let counts = hist.apply_retention(now).unwrap();
+        assert_eq!(counts.raw, 3 * 48);
+        assert_eq!(counts.hourly, 3 * 24);
+        assert_eq!(counts.daily, 0);
+        assert_eq!(hist.snapshot_count().unwrap(), 1);
+
+        // Later refreshes leave the pruned buckets alone
+        hist.record_snapshot_at(now - 1800, &make_test_info(69, false))
+            .unwrap();
+        hist.refresh_stale_rollups().unwrap();
+        hist.apply_retention(now).unwrap();
+        let daily = hist
+            .get_rollups(Granularity::Daily, Duration::days(30))
+            .unwrap();
+        assert_eq!(daily.len(), 4);
+        assert_eq!(daily[0].samples, 48);
+        assert_eq!(
+            hist.get_summary(Duration::days(30))
+                .unwrap()
+                .snapshots_count,
+            3 * 48 + 2
+        );
+    }
+
+    #[test]
+    fn test_capacity_readings_reach_into_rollups() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path())
+            .unwrap()
+            .with_retention(RetentionPolicy {
+                raw_days: 2,
+                hourly_days: 10,
+                daily_days: None,
+            });
+        let now = Utc::now().timestamp();
+        // Four readings over a day, 40 and 6 days ago and today
+        for start in [now - 40 * 86400, now - 6 * 86400, now - 86400] {
+            for i in 0..4 {
+                hist.record_snapshot_at(start + i * 6 * 3600, &make_test_info(80, false))
+                    .unwrap();
+            }
+        }
+        hist.maintain().unwrap();
+        assert_eq!(hist.snapshot_count().unwrap(), 4);
+
+        let history = hist
+            .capacity_history(TimeRange::between(None, None).unwrap())
+            .unwrap();
+        assert_eq!(history.raw.len(), 4);
+        assert_eq!(history.hourly.len(), 4);
+        assert!(
+            (1..=2).contains(&history.daily.len()),
+            "{:?}",
+            history.daily
+        );
+        assert!(history.daily.iter().all(|r| r.bucket < now - 30 * 86400));
+        assert_eq!(
+            history.daily.iter().map(|r| r.health_samples).sum::<u32>(),
+            4
+        );
+        let health = 4200.0 / 4500.0 * 100.0;
+        for rollup in history.daily.iter().chain(&history.hourly) {
+            assert_eq!(rollup.health_median, Some(health));
+            assert_eq!(rollup.health_p25, rollup.health_p75);
+        }
+    }
+
+    #[test]
+    fn test_prune_older_than() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let now = Utc::now().timestamp();
+        record_three_days(&hist, now - 10 * 86400);
+        hist.record_snapshot_at(now - 3600, &make_test_info(70, false))
+            .unwrap();
+        hist.refresh_rollups(0).unwrap();
+
+        let dry = hist.prune(Duration::days(5), true).unwrap();
+        assert_eq!((dry.raw, dry.hourly, dry.daily), (3 * 48, 3 * 24, 3));
+        assert_eq!(hist.snapshot_count().unwrap(), 3 * 48 + 1);
+
+        let counts = hist.prune(Duration::days(5), false).unwrap();
+        assert_eq!(
+            (counts.raw, counts.hourly, counts.daily),
+            (dry.raw, dry.hourly, dry.daily)
+        );
+        assert_eq!(hist.snapshot_count().unwrap(), 1);
+        assert_eq!(
+            hist.get_rollups(Granularity::Daily, Duration::days(30))
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let report = hist.vacuum().unwrap();
+        assert!(report.size_after.unwrap() <= report.size_before.unwrap());
+    }
+
+    #[test]
+    fn test_stats() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let now = Utc::now().timestamp();
+        for offset in [7200, 6600, 6000, 600] {
+            hist.record_snapshot_at(now - offset, &make_test_info(70, false))
+                .unwrap();
+        }
+        let bat1 = BatteryInfo {
+            name: "BAT1".to_string(),
+            ..make_test_info(60, false)
+        };
+        hist.record_snapshot_at(now - 300, &bat1).unwrap();
+
+        let stats = hist.stats(Duration::hours(1)).unwrap();
+        assert_eq!(stats.path.as_deref(), Some(tmp.path()));
+        assert!(stats.file_size_bytes.unwrap() > 0);
+        assert_eq!(stats.schema_version, migrations::SCHEMA_VERSION);
+        assert_eq!(stats.snapshots, 5);
+        assert_eq!(stats.events, 2);
+        assert_eq!(stats.first.unwrap().timestamp(), now - 7200);
+        assert_eq!(stats.last.unwrap().timestamp(), now - 300);
+
+        let packs: Vec<_> = stats
+            .packs
+            .iter()
+            .map(|p| (p.battery.as_deref().unwrap(), p.snapshots))
+            .collect();
+        assert_eq!(packs, vec![("BAT0", 4), ("BAT1", 1)]);
+
+        assert_eq!(stats.gaps.len(), 1);
+        assert_eq!(stats.gaps[0].start.timestamp(), now - 6000);
+        assert_eq!(stats.gaps[0].minutes, 90);
+    }
+
+    #[test]
+    fn test_check_finds_bad_rows() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let now = Utc::now().timestamp();
+        hist.record_snapshot_at(now - 600, &make_test_info(70, false))
+            .unwrap();
+        hist.record_snapshot_at(now - 300, &make_test_info(69, false))
+            .unwrap();
+        assert!(hist.check().unwrap().ok);
+
+        // Clock went backwards, then the same reading was written twice
+        hist.record_snapshot_at(now - 900, &make_test_info(71, false))
+            .unwrap();
+        hist.record_snapshot_at(now - 900, &make_test_info(71, false))
+            .unwrap();
+        hist.conn
+            .execute(
+                "INSERT INTO snapshots (timestamp, level, is_charging, battery, temperature, voltage)
+                 VALUES (?1, 140, 0, 'BAT0', 250.0, 11400)",
+                params![now - 100],
+            )
+            .unwrap();
+
+        let report = hist.check().unwrap();
+        assert!(!report.ok);
+        assert_eq!(report.sqlite, "ok");
+        let kinds: Vec<_> = report.issues.iter().map(|i| i.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["out_of_order", "impossible_value", "duplicate"]);
+        assert_eq!(report.issues[0].timestamp.timestamp(), now - 900);
+        assert_eq!(
+            report.issues[1].detail,
+            "level outside 0-100%, temperature outside -40-100C"
+        );
+        assert_eq!(
+            report.issues[2].detail,
+            "2 rows with the same pack and timestamp"
+        );
+    }
+
+    #[test]
+    fn test_import_skips_duplicates() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let now = Utc::now().timestamp();
+        hist.record_snapshot_at(now - 600, &make_test_info(70, false))
+            .unwrap();
+        hist.record_snapshot_at(now - 300, &make_test_info(69, false))
+            .unwrap();
+
+        let exported = |offset: i64, level: u8| BatterySnapshot {
+            timestamp: DateTime::from_timestamp(now - offset, 0).unwrap(),
+            battery: Some("BAT0".to_string()),
+            level,
+            ..Default::default()
+        };
+        let file = vec![exported(7200, 90), exported(3600, 80), exported(600, 70)];
+        let counts = hist.import_snapshots(&file, None).unwrap();
+        assert_eq!((counts.snapshots, counts.duplicates), (2, 1));
+
+        // Older rows now belong to the current pack and sort first, and
+        // arriving after newer ones is no sign of a clock change
+        let rows = hist.get_snapshots_range(Duration::hours(3)).unwrap();
+        let levels: Vec<u8> = rows.iter().map(|s| s.level).collect();
+        assert_eq!(levels, vec![90, 80, 70, 69]);
+        assert_eq!(
+            hist.installs().unwrap()[0].timestamp.timestamp(),
+            now - 7200
+        );
+        assert!(hist.check().unwrap().ok);
+
+        let again = hist.import_snapshots(&file, None).unwrap();
+        assert_eq!((again.snapshots, again.duplicates), (0, 3));
+    }
+
+    #[test]
+    fn test_csv_export_round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("export.csv");
+        let snap = BatterySnapshot {
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            battery: Some("BAT1".to_string()),
+            level: 64,
+            state: Some(ChargingState::NotCharging),
+            power_draw: Some(7.5),
+            cycle_count: Some(187),
+            max_energy: Some(50160),
+            design_energy: Some(57000),
+            capacity_unit: Some(CapacityUnit::MilliwattHours),
+            temperature_celsius: Some(31.5),
+            serial_number: Some("1234".to_string()),
+            ..Default::default()
+        };
+        let snaps = std::slice::from_ref(&snap);
+        export::export_snapshots(snaps, export::ExportFormat::Csv, path.to_str().unwrap()).unwrap();
+
+        let read = read_export(&path).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].timestamp, snap.timestamp);
+        assert_eq!(read[0].battery.as_deref(), Some("BAT1"));
+        assert_eq!(read[0].host, None);
+        assert_eq!(read[0].state, Some(ChargingState::NotCharging));
+        assert_eq!(read[0].power_draw, Some(7.5));
+        assert_eq!(read[0].design_energy, Some(57000));
+        assert_eq!(read[0].capacity_unit, Some(CapacityUnit::MilliwattHours));
+        assert_eq!(read[0].temperature_celsius, Some(31.5));
+        assert_eq!(read[0].serial_number.as_deref(), Some("1234"));
+
+        let ndjson = tmp.path().join("export.ndjson");
+        export::export_snapshots(
+            snaps,
+            export::ExportFormat::Ndjson,
+            ndjson.to_str().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(read_export(&ndjson).unwrap()[0].cycle_count, Some(187));
+
+        let json = tmp.path().join("export.json");
+        std::fs::write(
+            &json,
+            serde_json::json!({ "snapshots": [snap] }).to_string(),
+        )
+        .unwrap();
+        assert_eq!(read_export(&json).unwrap()[0].max_energy, Some(50160));
+    }
+
+    #[test]
+    fn test_merge_tags_rows_with_host() {
+        let tmp = tempfile::tempdir().unwrap();
+        let now = Utc::now().timestamp();
+        let other_path = tmp.path().join("alice #1.db");
+        {
+            // Raw rows of the older days are already pruned there
+            let other = HistoryManager::open_at(&other_path)
+                .unwrap()
+                .with_retention(RetentionPolicy {
+                    raw_days: 2,
+                    hourly_days: 30,
+                    daily_days: None,
+                });
+            record_three_days(&other, now - 86400);
+            other.maintain().unwrap();
+        }
+
+        let before = std::fs::read(&other_path).unwrap();
+
+        let hist = HistoryManager::open_at(&tmp.path().join("team.db")).unwrap();
+        hist.record_snapshot_at(now - 60, &make_test_info(70, false))
+            .unwrap();
+        let counts = hist.merge(&other_path, "alice").unwrap();
+        // The other machine's file is only read
+        assert_eq!(std::fs::read(&other_path).unwrap(), before);
+        assert!(!tmp.path().join("alice #1.db-wal").exists());
+        assert!(counts.snapshots > 0 && counts.snapshots < 3 * 48);
+        assert_eq!(counts.events, 1);
+        assert!(counts.rollups > 0);
+        assert!(hist.merge(&tmp.path().join("team.db"), "me").is_err());
+
+        let alice = HistoryManager::open_at(&tmp.path().join("team.db"))
+            .unwrap()
+            .with_host(Some("alice"));
+        let summary = alice.get_summary(Duration::days(7)).unwrap();
+        assert_eq!(summary.snapshots_count, 3 * 48);
+        assert_eq!(alice.installs().unwrap().len(), 1);
+        assert_eq!(
+            alice.latest_reading().unwrap().unwrap().host.as_deref(),
+            Some("alice")
+        );
+        assert!(alice.record_snapshot(&make_test_info(50, false)).is_err());
+
+        // This machine's own view is unchanged
+        assert_eq!(
+            hist.get_summary(Duration::days(7)).unwrap().snapshots_count,
+            1
+        );
+        assert_eq!(hist.hosts().unwrap(), vec!["alice".to_string()]);
+
+        let again = hist.merge(&other_path, "alice").unwrap();
+        assert_eq!((again.snapshots, again.events, again.rollups), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_pack_scope_parse() {
+        assert_eq!(PackScope::parse("current").unwrap(), PackScope::Current);
+        assert_eq!(PackScope::parse("ALL").unwrap(), PackScope::All);
+        assert_eq!(PackScope::parse("#3").unwrap(), PackScope::Install(3));
+        assert!(PackScope::parse("newest").is_err());
     }
 
     #[test]
@@ -359,6 +3425,96 @@ mod tests {
         assert_eq!(parse_duration_str("4w").unwrap(), Duration::weeks(4));
         assert_eq!(parse_duration_str("1m").unwrap(), Duration::days(30));
         assert!(parse_duration_str("abc").is_err());
+
+        assert_eq!(parse_duration_str("1d12h").unwrap(), Duration::hours(36));
+        assert_eq!(parse_duration_str("90min").unwrap(), Duration::minutes(90));
+        assert_eq!(
+            parse_duration_str("2 hours 30 mins").unwrap(),
+            Duration::minutes(150)
+        );
+        assert_eq!(parse_duration_str("1y").unwrap(), Duration::days(365));
+        assert!(parse_duration_str("12").is_err());
+        assert!(parse_duration_str("3x").is_err());
+        assert!(parse_duration_str("99999999999d").is_err());
+    }
+
+    #[test]
+    fn test_parse_iso_duration() {
+        assert_eq!(parse_duration_str("P7D").unwrap(), Duration::days(7));
+        assert_eq!(parse_duration_str("PT36H").unwrap(), Duration::hours(36));
+        assert_eq!(
+            parse_duration_str("P1DT12H30M").unwrap(),
+            Duration::minutes(2190)
+        );
+        assert_eq!(parse_duration_str("P2W").unwrap(), Duration::weeks(2));
+        assert_eq!(parse_duration_str("P1M").unwrap(), Duration::days(30));
+        assert!(parse_duration_str("P").is_err());
+        assert!(parse_duration_str("P1DT").is_err());
+        assert!(parse_duration_str("PT1D").is_err());
+        assert!(parse_duration_str("P1D2Y").is_err());
+    }
+
+    #[test]
+    fn test_time_range_parse() {
+        let range = TimeRange::parse(
+            "24h",
+            Some("2026-09-01T00:00:00Z"),
+            Some("2026-09-08T00:00:00Z"),
+        )
+        .unwrap();
+        assert_eq!(range.start.to_rfc3339(), "2026-09-01T00:00:00+00:00");
+        assert_eq!(range.span(), Duration::days(7));
+        assert!(range.describe().contains(" to "));
+
+        // A duration before --until
+        let range = TimeRange::parse("2d", None, Some("2026-09-08T00:00:00Z")).unwrap();
+        assert_eq!(range.start.to_rfc3339(), "2026-09-06T00:00:00+00:00");
+
+        let range = TimeRange::parse("7d", None, None).unwrap();
+        assert_eq!(range.describe(), "Last 1 week");
+        assert_eq!(range.end, None);
+        assert!(TimeRange::parse("7d", Some("36h"), None)
+            .unwrap()
+            .describe()
+            .starts_with("Since "));
+
+        assert!(parse_time_str("2026-09-01").is_ok());
+        assert!(parse_time_str("2026-09-01 08:30").is_ok());
+        assert!(parse_time_str("yesterday").is_err());
+        assert!(TimeRange::parse("24h", Some("2026-09-08"), Some("2026-09-01")).is_err());
+
+        let everything = TimeRange::between(None, None).unwrap();
+        assert!(everything.is_open_start());
+        assert_eq!(everything.describe(), "All history");
+        assert!(!TimeRange::between(Some("90d"), None)
+            .unwrap()
+            .is_open_start());
+    }
+
+    #[test]
+    fn test_queries_stop_at_range_end() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let hist = HistoryManager::open_at(tmp.path()).unwrap();
+        let now = Utc::now().timestamp();
+        record_three_days(&hist, now);
+
+        let start =
+            DateTime::from_timestamp(Granularity::Daily.bucket_start(now) - 3 * 86400, 0).unwrap();
+        let first_day = TimeRange {
+            start,
+            end: Some(start + Duration::days(1)),
+            last: None,
+        };
+        assert_eq!(hist.get_raw_snapshots(first_day).unwrap().len(), 48);
+        let summary = hist.get_summary(first_day).unwrap();
+        assert_eq!(summary.snapshots_count, 48);
+        assert_eq!(summary.granularity, Granularity::Raw);
+        assert_eq!(
+            hist.get_rollups(Granularity::Hourly, first_day)
+                .unwrap()
+                .len(),
+            24
+        );
     }
 
     #[test]
@@ -367,5 +3523,20 @@ mod tests {
         assert_eq!(format_duration(&Duration::days(3)), "Last 3 days");
         assert_eq!(format_duration(&Duration::weeks(2)), "Last 2 weeks");
         assert_eq!(format_duration(&Duration::days(60)), "Last 2 months");
+        assert_eq!(format_duration(&Duration::hours(36)), "Last 1 day 12 hours");
+        assert_eq!(
+            format_duration(&Duration::minutes(90)),
+            "Last 1 hour 30 minutes"
+        );
+        assert_eq!(format_duration(&Duration::days(33)), "Last 1 month 3 days");
+        assert_eq!(format_duration(&Duration::days(7)), "Last 1 week");
+        assert_eq!(
+            format_duration(&Duration::seconds(90)),
+            "Last 1 minute 30 seconds"
+        );
+        assert_eq!(
+            format_duration(&(Duration::days(30) + Duration::hours(3))),
+            "Last 1 month 3 hours"
+        );
     }
 }
@@ -0,0 +1,291 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+use crate::battery::ChargingState;
+use crate::history::BatterySnapshot;
+
+/// Where UPower keeps its history files.
+pub const UPOWER_HISTORY_DIR: &str = "/var/lib/upower";
+
+/// A rate or time-remaining entry is attached to the charge entry closest to
+/// it, if they are at most this far apart.
+const UPOWER_MATCH_SECS: i64 = 15 * 60;
+
+/// History UPower recorded for one device.
+#[derive(Debug, Clone)]
+pub struct UpowerHistory {
+    /// UPower's device id: model, design energy and serial number joined
+    /// with dashes (e.g. `5B10W13930-57-1234`).
+    pub id: String,
+    /// One row per charge entry, without a pack name.
+    pub snapshots: Vec<BatterySnapshot>,
+}
+
+/// One line of a UPower `.dat` file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct UpowerEntry {
+    timestamp: i64,
+    value: f64,
+    state: ChargingState,
+}
+
+/// Read every `history-{charge,rate,time-full,time-empty}-<id>.dat` file in
+/// `dir`, one result per device that has a charge history.
+pub fn read_upower_dir(dir: &Path) -> Result<Vec<UpowerHistory>> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read UPower history in {}", dir.display()))?;
+
+    let mut devices: BTreeMap<String, BTreeMap<&str, Vec<UpowerEntry>>> = BTreeMap::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(stem) = name
+            .strip_prefix("history-")
+            .and_then(|n| n.strip_suffix(".dat"))
+        else {
+            continue;
+        };
+        let Some((kind, id)) = ["charge", "rate", "time-full", "time-empty"]
+            .into_iter()
+            .find_map(|kind| {
+                let id = stem.strip_prefix(kind)?.strip_prefix('-')?;
+                Some((kind, id))
+            })
+        else {
+            continue;
+        };
+
+        let text = std::fs::read_to_string(entry.path())
+            .with_context(|| format!("Failed to read {}", entry.path().display()))?;
+        devices
+            .entry(id.to_string())
+            .or_default()
+            .insert(kind, parse_upower_history(&text));
+    }
+
+    Ok(devices
+        .into_iter()
+        .filter_map(|(id, mut files)| {
+            let charge = files.remove("charge")?;
+            let snapshots = upower_snapshots(
+                &charge,
+                files.get("rate").map(Vec::as_slice).unwrap_or_default(),
+                files
+                    .get("time-full")
+                    .map(Vec::as_slice)
+                    .unwrap_or_default(),
+                files
+                    .get("time-empty")
+                    .map(Vec::as_slice)
+                    .unwrap_or_default(),
+            );
+            Some(UpowerHistory { id, snapshots })
+        })
+        .collect())
+}
+
+/// Parse a `.dat` file: `<unix time>\t<value>\t<state>` per line.
+/// Malformed lines are skipped.
+fn parse_upower_history(text: &str) -> Vec<UpowerEntry> {
+    let mut entries: Vec<UpowerEntry> = text
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let timestamp = fields.next()?.parse().ok()?;
+            let value = fields.next()?.parse().ok()?;
+            let state = match fields.next()? {
+                "charging" => ChargingState::Charging,
+                "discharging" | "empty" => ChargingState::Discharging,
+                "fully-charged" => ChargingState::Full,
+                "pending-charge" | "pending-discharge" => ChargingState::NotCharging,
+                _ => ChargingState::Unknown,
+            };
+            Some(UpowerEntry {
+                timestamp,
+                value,
+                state,
+            })
+        })
+        .collect();
+    entries.sort_by_key(|e| e.timestamp);
+    entries
+}
+
+/// Turn charge entries into snapshots, filling in power draw and time
+/// remaining from the other files where an entry is close enough.
+fn upower_snapshots(
+    charge: &[UpowerEntry],
+    rate: &[UpowerEntry],
+    time_full: &[UpowerEntry],
+    time_empty: &[UpowerEntry],
+) -> Vec<BatterySnapshot> {
+    let closest = |entries: &[UpowerEntry], timestamp: i64| {
+        entries
+            .iter()
+            .filter(|e| (e.timestamp - timestamp).abs() <= UPOWER_MATCH_SECS)
+            .min_by_key(|e| (e.timestamp - timestamp).abs())
+            .map(|e| e.value)
+    };
+
+    charge
+        .iter()
+        .map(|entry| {
+            let time_remaining = match entry.state {
+                ChargingState::Charging => closest(time_full, entry.timestamp),
+                ChargingState::Discharging => closest(time_empty, entry.timestamp),
+                _ => None,
+            };
+            BatterySnapshot {
+                timestamp: DateTime::from_timestamp(entry.timestamp, 0).unwrap_or_default(),
+                level: entry.value.round().clamp(0.0, 100.0) as u8,
+                is_charging: matches!(entry.state, ChargingState::Charging | ChargingState::Full),
+                state: Some(entry.state),
+                power_draw: closest(rate, entry.timestamp).filter(|w| *w > 0.0),
+                time_remaining_minutes: time_remaining
+                    .filter(|s| *s > 0.0)
+                    .map(|s| (s / 60.0).round() as i64),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Run `pmset -g log` (macOS only).
+pub fn read_pmset_log() -> Result<String> {
+    let output = Command::new("pmset")
+        .args(["-g", "log"])
+        .output()
+        .context("Failed to run pmset")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "pmset -g log failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Parse `pmset -g log` output. Entries that note the power source and
+/// charge (e.g. `... Using BATT (Charge:87%)`) become snapshots without a
+/// pack name; everything else is skipped.
+pub fn parse_pmset_log(text: &str) -> Vec<BatterySnapshot> {
+    let mut snapshots: Vec<BatterySnapshot> = Vec::new();
+    for line in text.lines() {
+        let Some(timestamp) = line
+            .get(..25)
+            .and_then(|s| DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S %z").ok())
+        else {
+            continue;
+        };
+        let Some(source) = line.find("Using ").map(|i| &line[i + 6..]) else {
+            continue;
+        };
+        let on_ac = source.starts_with("AC");
+        if !on_ac && !source.to_lowercase().starts_with("batt") {
+            continue;
+        }
+        let Some(level) = source.find("Charge:").and_then(|i| {
+            let digits: String = source[i + 7..]
+                .trim_start()
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            digits.parse::<u8>().ok()
+        }) else {
+            continue;
+        };
+
+        let timestamp = timestamp.with_timezone(&Utc);
+        // Summary lines repeat the entry logged in the same second
+        if snapshots.last().is_some_and(|s| s.timestamp == timestamp) {
+            continue;
+        }
+        // The log does not say whether the Mac was charging on AC
+        let state = match (on_ac, level) {
+            (false, _) => Some(ChargingState::Discharging),
+            (true, 100) => Some(ChargingState::Full),
+            (true, _) => None,
+        };
+        snapshots.push(BatterySnapshot {
+            timestamp,
+            level: level.min(100),
+            is_charging: on_ac,
+            state,
+            ..Default::default()
+        });
+    }
+    snapshots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_read_upower_dir() {
+        let devices = read_upower_dir(&fixture("upower")).unwrap();
+        let ids: Vec<&str> = devices.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["5B10W13930-57-1234", "hidpp_battery_0"]);
+
+        let laptop = &devices[0].snapshots;
+        assert_eq!(laptop.len(), 19);
+        assert_eq!(laptop[0].timestamp.timestamp(), 1789000000);
+        assert_eq!(laptop[0].level, 95);
+        assert_eq!(laptop[0].state, Some(ChargingState::Discharging));
+        assert_eq!(laptop[0].power_draw, Some(9.5));
+        assert_eq!(laptop[0].time_remaining_minutes, Some(285));
+
+        let charging = &laptop[12];
+        assert_eq!((charging.level, charging.is_charging), (35, true));
+        assert_eq!(charging.power_draw, Some(35.0));
+        assert_eq!(charging.time_remaining_minutes, Some(110));
+        assert_eq!(laptop[18].state, Some(ChargingState::Full));
+
+        // The malformed line is skipped
+        assert_eq!(devices[1].snapshots.len(), 2);
+        assert_eq!(devices[1].snapshots[1].power_draw, None);
+    }
+
+    #[test]
+    fn test_parse_upower_states() {
+        let entries = parse_upower_history(
+            "1700000100\t80.000\tpending-charge\n1700000000\t81.000\tempty\nnot a line\n",
+        );
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].state, ChargingState::Discharging);
+        assert_eq!(entries[1].state, ChargingState::NotCharging);
+    }
+
+    #[test]
+    fn test_parse_pmset_log() {
+        let text = std::fs::read_to_string(fixture("pmset.log")).unwrap();
+        let snapshots = parse_pmset_log(&text);
+        let levels: Vec<(u8, bool)> = snapshots.iter().map(|s| (s.level, s.is_charging)).collect();
+        assert_eq!(
+            levels,
+            vec![
+                (87, false),
+                (54, false),
+                (53, true),
+                (96, true),
+                (100, true),
+                (98, false)
+            ]
+        );
+        assert_eq!(
+            snapshots[0].timestamp,
+            DateTime::parse_from_rfc3339("2026-09-10T06:02:11Z").unwrap()
+        );
+        assert_eq!(snapshots[4].state, Some(ChargingState::Full));
+        assert_eq!(snapshots[2].state, None);
+    }
+}
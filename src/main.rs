@@ -1,15 +1,31 @@
 #![allow(dead_code)]
 
 mod alert;
+mod backend;
 mod battery;
+mod charge;
+mod config;
+mod cycles;
 mod display;
+mod export;
+mod forecast;
 mod health;
 mod history;
+mod importers;
+mod migrations;
 mod optimize;
 mod power;
+mod rating;
+mod resistance;
+mod rollup;
+mod sessions;
+mod sleep;
+mod stress;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result};
+use backend::BatteryBackend;
+use clap::{ArgGroup, Parser, Subcommand};
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
@@ -29,13 +45,63 @@ struct Cli {
     /// Output as JSON instead of formatted text
     #[arg(long, global = true)]
     json: bool,
+
+    /// Battery pack to report on (e.g. BAT0, BAT1); defaults to all packs combined
+    #[arg(long, global = true, value_name = "NAME")]
+    battery: Option<String>,
+
+    /// Read batteries from this power_supply directory instead of /sys/class/power_supply
+    #[arg(long, global = true, value_name = "DIR", env = "BATTERYCTL_SYSFS_ROOT")]
+    sysfs_root: Option<std::path::PathBuf>,
+
+    /// History database to use instead of the one in the data directory
+    #[arg(long, global = true, value_name = "PATH", env = "BATTERYCTL_DB")]
+    db: Option<std::path::PathBuf>,
+
+    /// Config file to use instead of batteryctl/config.json in the config directory
+    #[arg(long, global = true, value_name = "PATH", env = "BATTERYCTL_CONFIG")]
+    config: Option<std::path::PathBuf>,
+}
+
+/// Where the history database lives, how long its data is kept, and which
+/// machine's rows to read.
+#[derive(Clone, Copy)]
+struct HistoryOptions<'a> {
+    db: Option<&'a Path>,
+    retention: config::RetentionPolicy,
+    host: Option<&'a str>,
+}
+
+impl<'a> HistoryOptions<'a> {
+    fn open(&self) -> Result<history::HistoryManager> {
+        Ok(history::HistoryManager::open(self.db)?
+            .with_retention(self.retention)
+            .with_host(self.host))
+    }
+
+    /// Open the database only if there is one, for commands that merely
+    /// consult it.
+    fn open_existing(&self) -> Option<history::HistoryManager> {
+        let path = match self.db {
+            Some(path) => path.to_path_buf(),
+            None => history::get_db_path().ok()?,
+        };
+        if !path.exists() {
+            return None;
+        }
+        self.open().ok()
+    }
+
+    fn for_host(self, host: Option<&'a str>) -> Self {
+        Self { host, ..self }
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Current battery status with detailed metrics
     #[command(
-        long_about = "Display current battery status including charge level, power state, and time remaining.\n\nExamples:\n  batteryctl status              # Quick status overview\n  batteryctl status -d           # Detailed view with health, cycles, temperature\n  batteryctl status --watch      # Live monitoring with 5s refresh\n  batteryctl status -w -i 2      # Live monitoring with 2s refresh interval\n  batteryctl status --json       # Machine-readable JSON output"
+        long_about = "Display current battery status including charge level, power state, and time remaining.\n\nExamples:\n  batteryctl status              # Quick status overview\n  batteryctl status -d           # Detailed view with health, cycles, temperature\n  batteryctl status --watch      # Live monitoring with 5s refresh\n  batteryctl status -w -i 2      # Live monitoring with 2s refresh interval\n  batteryctl status --battery BAT1  # Only the second pack on multi-battery laptops\n  batteryctl status --json       # Machine-readable JSON output"
     )]
     Status {
         /// Show detailed metrics (health, cycles, temperature)
@@ -53,8 +119,9 @@ enum Commands {
 
     /// Battery health report with degradation trends
     #[command(
-        long_about = "Generate a comprehensive battery health report showing capacity degradation,\ncycle count, estimated remaining lifespan, and manufacturing details.\n\nExamples:\n  batteryctl health                    # Basic health report\n  batteryctl health --history          # Include capacity trend chart over time\n  batteryctl health --compare-new      # Compare current battery vs new baseline\n  batteryctl health --history --compare-new  # Full report with trends and comparison\n  batteryctl health --json             # Export health data as JSON"
+        long_about = "Generate a comprehensive battery health report showing capacity degradation,\ncycle count, estimated remaining lifespan, and manufacturing details.\n\nExamples:\n  batteryctl health                    # Basic health report\n  batteryctl health --history          # Include capacity trend chart over time\n  batteryctl health --compare-new      # Compare current battery vs new baseline\n  batteryctl health --history --compare-new  # Full report with trends and comparison\n  batteryctl health --history --since 2025-01-01  # Trend from a given date\n  batteryctl health --stress           # What has aged the pack over the last 30 days\n  batteryctl health --json             # Export health data as JSON"
     )]
+    #[command(group(ArgGroup::new("analysis").args(["history", "stress"]).multiple(true)))]
     Health {
         /// Show capacity history trend
         #[arg(long)]
@@ -63,6 +130,29 @@ enum Commands {
         /// Compare current battery with a new one
         #[arg(long)]
         compare_new: bool,
+
+        /// Score how hard the pack has been used: time at high and low charge, heat,
+        /// discharge depth and charging power. Built from raw snapshots, so it reaches
+        /// back as far as retention.raw_days (30 days by default)
+        #[arg(long)]
+        stress: bool,
+
+        /// Physical pack for the trend: current, all, or a pack number
+        #[arg(long, value_name = "PACK", default_value = "current")]
+        pack: String,
+
+        /// Start the trend here instead of at the first reading, and the stress analysis
+        /// instead of 30 days ago (date, date and time, or e.g. 90d)
+        #[arg(long, value_name = "TIME", requires = "analysis")]
+        since: Option<String>,
+
+        /// End the trend and stress analysis here instead of now
+        #[arg(long, value_name = "TIME", requires = "analysis")]
+        until: Option<String>,
+
+        /// Report on a machine merged into the history database, from its latest reading
+        #[arg(long, value_name = "NAME")]
+        host: Option<String>,
     },
 
     /// Identify top power-consuming applications
@@ -81,16 +171,80 @@ enum Commands {
 
     /// Battery usage history over configurable time periods
     #[command(
-        long_about = "View battery usage history with charge level charts and summary statistics.\nData is collected automatically when running other batteryctl commands.\n\nDuration format: <number><unit> where unit is h (hours), d (days), w (weeks), m (months).\n\nExamples:\n  batteryctl history                 # Last 24 hours (default)\n  batteryctl history -d 7d           # Last 7 days\n  batteryctl history -d 4w           # Last 4 weeks\n  batteryctl history -d 1m           # Last month\n  batteryctl history -d 24h -o data.csv   # Export to CSV file\n  batteryctl history -d 7d -o data.json   # Export to JSON file"
+        long_about = "View battery usage history with charge level charts and summary statistics.\nData is collected automatically when running other batteryctl commands.\n\nDuration format: one or more <number><unit> where unit is min (minutes), h (hours),\nd (days), w (weeks), m (months of 30 days) or y (years), e.g. 1d12h; or ISO-8601 (P1DT12H).\nTimes for --since/--until: a date (local midnight), a local date and time, RFC 3339,\nor a duration ago (36h).\n\nExamples:\n  batteryctl history                 # Last 24 hours (default)\n  batteryctl history -d 7d           # Last 7 days\n  batteryctl history -d 1d12h        # Last 36 hours\n  batteryctl history -d 1m           # Last month\n  batteryctl history --since 2026-09-01 --until 2026-09-08   # One week in September\n  batteryctl history --until 2026-09-08 -d 2d                 # Two days before that\n  batteryctl history -d 24h -o data.csv   # Export to CSV file\n  batteryctl history -d 7d -o data.json   # Export to JSON file\n  batteryctl history -d 7d -o - --format influx | influx write -b battery   # Pipe line protocol\n  batteryctl history -d 30d -o dump.sql   # SQL for 'sqlite3 new.db < dump.sql'\n\nMaintenance:\n  batteryctl history stats           # Rows, date range, file size, gaps, packs\n  batteryctl history check           # Look for bad or duplicate rows\n  batteryctl history prune --older-than 90d\n  batteryctl history vacuum          # Reclaim space after pruning\n\nCollecting several machines:\n  batteryctl history merge alice-laptop.db   # Rows tagged with host 'alice-laptop'\n  batteryctl history --host alice-laptop -d 7d\n  batteryctl history import battery.csv      # Re-import an export of this machine\n\nBringing in data collected by the OS:\n  batteryctl history import-upower           # /var/lib/upower/history-*.dat\n  batteryctl history import-pmset            # pmset -g log on macOS"
     )]
     History {
-        /// Time period (e.g., 24h, 7d, 4w, 1m)
-        #[arg(short, long, default_value = "24h")]
+        #[command(subcommand)]
+        action: Option<HistoryAction>,
+
+        /// Time period (e.g., 24h, 7d, 1d12h, 90min, P2W)
+        #[arg(short, long, default_value = "24h", conflicts_with = "since")]
         duration: String,
 
-        /// Export to CSV file
-        #[arg(short, long)]
+        /// Start of the period (e.g. 2026-09-01, "2026-09-01 08:00", 36h)
+        #[arg(long, value_name = "TIME")]
+        since: Option<String>,
+
+        /// End of the period; with --duration, the period ends here
+        #[arg(long, value_name = "TIME")]
+        until: Option<String>,
+
+        /// Export the snapshots to this file, or to stdout with '-'
+        #[arg(short, long, value_name = "FILE")]
         output: Option<String>,
+
+        /// Export format: json, ndjson, csv, influx or sqlite (default: from the file extension)
+        #[arg(long, value_name = "FORMAT", requires = "output")]
+        format: Option<String>,
+
+        /// Physical pack to show: current, all, or a pack number
+        #[arg(long, value_name = "PACK", default_value = "current")]
+        pack: String,
+
+        /// Show history merged from another machine instead of this one
+        #[arg(long, value_name = "NAME")]
+        host: Option<String>,
+    },
+
+    /// Charge and discharge sessions with energy, power and temperature
+    #[command(
+        long_about = "Split recorded history into charge and discharge sessions. Each session runs\nfrom plugging in or unplugging until the next change, or until recording stops\nfor more than an hour, and shows its levels, duration, energy moved, average\nand peak power and temperature. Sessions are built from raw snapshots, so they\nreach back as far as retention.raw_days (30 days by default).\n\nExamples:\n  batteryctl sessions                # Last 24 hours (default)\n  batteryctl sessions -d 7d          # Last 7 days\n  batteryctl sessions --battery BAT1 # One pack on multi-battery laptops\n  batteryctl sessions -d 7d --json   # Machine-readable output"
+    )]
+    Sessions {
+        /// Time period (e.g., 24h, 7d, 4w)
+        #[arg(short, long, default_value = "24h")]
+        duration: String,
+
+        /// Physical pack to show: current, all, or a pack number
+        #[arg(long, value_name = "PACK", default_value = "current")]
+        pack: String,
+
+        /// Show sessions merged from another machine instead of this one
+        #[arg(long, value_name = "NAME")]
+        host: Option<String>,
+    },
+
+    /// Battery drain while suspended, per sleep
+    #[command(
+        long_about = "Report how much charge was lost during each sleep on battery, in percent per hour,\nand flag sleeps that drained abnormally fast (more than 1%/h over two hours or more),\nwhich usually means the machine is not reaching a deep sleep state (e.g. broken s2idle).\n\nSleeps are found from gaps of more than an hour between snapshots, which may also be\ntime the machine was off, or exactly from the 'hook pre-sleep' / 'hook post-sleep' events.\n\nExamples:\n  batteryctl sleep                   # Last 7 days (default)\n  batteryctl sleep -d 4w             # Last 4 weeks\n  batteryctl sleep --json            # Machine-readable output"
+    )]
+    Sleep {
+        /// Time period (e.g., 24h, 7d, 4w)
+        #[arg(short, long, default_value = "7d")]
+        duration: String,
+
+        /// Show sleeps merged from another machine instead of this one
+        #[arg(long, value_name = "NAME")]
+        host: Option<String>,
+    },
+
+    /// Record a reading around system sleep (for systemd-sleep and similar)
+    #[command(
+        long_about = "Record a snapshot and a sleep or wake event. Run 'hook pre-sleep' just before\nthe machine suspends and 'hook post-sleep' right after it resumes, so 'sleep' can\nmeasure the drain exactly instead of guessing from gaps.\n\nsystemd runs every executable in /usr/lib/systemd/system-sleep/ with 'pre' or 'post';\nas root, point --db at your own database:\n\n  #!/bin/sh\n  DB=/home/alice/.local/share/batteryctl/history.db\n  case \"$1\" in\n    pre)  batteryctl --db \"$DB\" hook pre-sleep ;;\n    post) batteryctl --db \"$DB\" hook post-sleep ;;\n  esac"
+    )]
+    Hook {
+        #[command(subcommand)]
+        event: HookEvent,
     },
 
     /// Smart suggestions to optimize battery life
@@ -121,6 +275,49 @@ enum Commands {
         daemon: bool,
     },
 
+    /// Read or set charge start/stop thresholds
+    #[command(
+        long_about = "Show or change the charge thresholds exposed by the firmware\n(charge_control_start_threshold / charge_control_end_threshold in sysfs).\nWriting requires root. Without --battery, every pack is changed.\n\nExamples:\n  batteryctl limit                        # Show the current thresholds\n  sudo batteryctl limit --stop 80         # Stop charging at 80%\n  sudo batteryctl limit --start 75 --stop 80  # Resume below 75%, stop at 80%\n  batteryctl limit --stop 60 --dry-run    # Show what would be written\n  sudo batteryctl limit --off             # Charge to 100% again\n  batteryctl limit --battery BAT1 --json  # One pack, machine-readable"
+    )]
+    Limit {
+        /// Stop charging at this level
+        #[arg(long, value_name = "PERCENT")]
+        stop: Option<u8>,
+
+        /// Resume charging below this level
+        #[arg(long, value_name = "PERCENT")]
+        start: Option<u8>,
+
+        /// Remove the limit and charge to 100%
+        #[arg(long, conflicts_with_all = ["stop", "start"])]
+        off: bool,
+
+        /// Show what would be written without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Control charge behaviour: inhibit charging or force discharge
+    #[command(
+        long_about = "List or switch the charge behaviour exposed by newer kernels\n(charge_behaviour in sysfs): auto, inhibit-charge or force-discharge.\nWith --for or --until-level, batteryctl stays in the foreground and switches\nback to the previous mode when the condition is met or on Ctrl+C.\nWriting requires root.\n\nExamples:\n  batteryctl charge                                  # List supported modes\n  sudo batteryctl charge inhibit-charge              # Hold the current level on AC\n  sudo batteryctl charge force-discharge --until-level 20  # Calibration drain\n  sudo batteryctl charge inhibit-charge --for 2h     # Pause charging for two hours\n  sudo batteryctl charge auto                        # Back to normal charging\n  batteryctl charge force-discharge --dry-run        # Show what would be written"
+    )]
+    Charge {
+        /// Mode to switch to (auto, inhibit-charge, force-discharge)
+        mode: Option<String>,
+
+        /// Switch back to the previous mode after this long (e.g. 2h)
+        #[arg(long = "for", value_name = "DURATION", requires = "mode")]
+        duration: Option<String>,
+
+        /// Switch back to the previous mode once the battery reaches this level
+        #[arg(long, value_name = "PERCENT", requires = "mode")]
+        until_level: Option<u8>,
+
+        /// Show what would be written without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
     /// Record a battery snapshot to the history database
     #[command(
         long_about = "Manually record a single battery snapshot to the local history database.\nThis is useful for cron jobs or periodic data collection scripts.\nNote: Snapshots are also recorded automatically by 'status' and 'status --watch'.\n\nExamples:\n  batteryctl record                          # Record current state\n  watch -n 300 batteryctl record             # Record every 5 minutes (shell)\n  crontab: */10 * * * * batteryctl record    # Cron job every 10 minutes"
@@ -128,40 +325,220 @@ enum Commands {
     Record,
 }
 
+/// Moments the sleep hooks are run at.
+#[derive(Subcommand)]
+enum HookEvent {
+    /// The machine is about to suspend
+    PreSleep,
+
+    /// The machine has just resumed
+    PostSleep,
+}
+
+/// Maintenance operations on the history database.
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// Delete snapshots and rollups older than a given age
+    Prune {
+        /// Age beyond which data is deleted (e.g. 90d, 12w)
+        #[arg(long, value_name = "DURATION")]
+        older_than: String,
+
+        /// Count what would be deleted without deleting it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Rebuild the database file to reclaim unused space
+    Vacuum,
+
+    /// Row counts, date range, file size, recording gaps and per-pack breakdown
+    Stats {
+        /// Report gaps between snapshots at least this long
+        #[arg(long, value_name = "DURATION", default_value = "6h")]
+        min_gap: String,
+    },
+
+    /// Look for out-of-order timestamps, impossible values and duplicate rows
+    Check,
+
+    /// Add snapshots from a CSV, NDJSON or JSON file written by 'history --output'
+    Import {
+        /// File to import (.csv, .ndjson or .jsonl, or JSON otherwise)
+        file: std::path::PathBuf,
+
+        /// Tag the rows as coming from this machine name instead of this one
+        #[arg(long, value_name = "NAME")]
+        host: Option<String>,
+    },
+
+    /// Import the history UPower has already collected (Linux)
+    ImportUpower {
+        /// UPower's history directory
+        #[arg(value_name = "DIR", default_value = importers::UPOWER_HISTORY_DIR)]
+        dir: std::path::PathBuf,
+
+        /// Import this UPower device even if it matches no battery by serial number
+        #[arg(long, value_name = "ID")]
+        device: Option<String>,
+    },
+
+    /// Import charge levels from the macOS power management log
+    ImportPmset {
+        /// Saved output of 'pmset -g log'; runs pmset when omitted
+        file: Option<std::path::PathBuf>,
+    },
+
+    /// Copy another machine's history database into this one
+    Merge {
+        /// History database to copy from
+        #[arg(value_name = "DB")]
+        source: std::path::PathBuf,
+
+        /// Name to tag its rows with (defaults to the file name without extension)
+        #[arg(long, value_name = "NAME")]
+        host: Option<String>,
+    },
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let battery = cli.battery.as_deref();
+    let backend = backend::detect(cli.sysfs_root.as_deref());
+    let backend = backend.as_ref();
+    let config = config::load(cli.config.as_deref())?;
+    rating::set_user_rules(config.cycle_ratings);
+    let db = &HistoryOptions {
+        db: cli.db.as_deref(),
+        retention: config.retention,
+        host: None,
+    };
 
     match cli.command {
         Commands::Status {
             detailed,
             watch,
             interval,
-        } => cmd_status(detailed, watch, interval, cli.json),
+        } => cmd_status(backend, battery, db, detailed, watch, interval, cli.json),
 
         Commands::Health {
             history,
             compare_new,
-        } => cmd_health(history, compare_new, cli.json),
+            stress,
+            pack,
+            since,
+            until,
+            host,
+        } => {
+            let range = history::TimeRange::between(since.as_deref(), until.as_deref())?;
+            cmd_health(
+                backend,
+                battery,
+                &db.for_host(host.as_deref()),
+                history.then_some(range),
+                stress.then_some(range),
+                compare_new,
+                &pack,
+                cli.json,
+            )
+        }
+
+        Commands::PowerHogs { detailed, filter } => {
+            cmd_power_hogs(backend, detailed, filter, cli.json)
+        }
+
+        Commands::History {
+            action: Some(action),
+            ..
+        } => cmd_history_maintenance(backend, battery, db, action, cli.json),
 
-        Commands::PowerHogs { detailed, filter } => cmd_power_hogs(detailed, filter, cli.json),
+        Commands::History {
+            action: None,
+            duration,
+            since,
+            until,
+            output,
+            format,
+            pack,
+            host,
+        } => cmd_history(
+            backend,
+            battery,
+            &db.for_host(host.as_deref()),
+            history::TimeRange::parse(&duration, since.as_deref(), until.as_deref())?,
+            export_target(output.as_deref(), format.as_deref())?,
+            &pack,
+            cli.json,
+        ),
 
-        Commands::History { duration, output } => cmd_history(&duration, output.as_deref(), cli.json),
+        Commands::Sessions {
+            duration,
+            pack,
+            host,
+        } => cmd_sessions(
+            backend,
+            battery,
+            &db.for_host(host.as_deref()),
+            &duration,
+            &pack,
+            cli.json,
+        ),
 
-        Commands::Optimize { aggressive } => cmd_optimize(aggressive, cli.json),
+        Commands::Sleep { duration, host } => cmd_sleep(
+            backend,
+            battery,
+            &db.for_host(host.as_deref()),
+            &duration,
+            cli.json,
+        ),
+
+        Commands::Hook { event } => cmd_hook(backend, db, event),
+
+        Commands::Optimize { aggressive } => cmd_optimize(backend, battery, aggressive, cli.json),
 
         Commands::Alert {
             level,
             on_full,
             daemon,
-        } => cmd_alert(level, on_full, daemon),
+        } => cmd_alert(backend, battery, level, on_full, daemon),
+
+        Commands::Limit {
+            stop,
+            start,
+            off,
+            dry_run,
+        } => cmd_limit(backend, battery, stop, start, off, dry_run, cli.json),
+
+        Commands::Charge {
+            mode,
+            duration,
+            until_level,
+            dry_run,
+        } => cmd_charge(
+            backend,
+            battery,
+            mode.as_deref(),
+            duration.as_deref(),
+            until_level,
+            dry_run,
+            cli.json,
+        ),
 
-        Commands::Record => cmd_record(),
+        Commands::Record => cmd_record(backend, battery, db),
     }
 }
 
 // ── Command implementations ────────────────────────────────────────────
 
-fn cmd_status(detailed: bool, watch: bool, interval: u64, json: bool) -> Result<()> {
+fn cmd_status(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    db: &HistoryOptions,
+    detailed: bool,
+    watch: bool,
+    interval: u64,
+    json: bool,
+) -> Result<()> {
     if watch {
         let running = Arc::new(AtomicBool::new(true));
         let r = running.clone();
@@ -173,24 +550,20 @@ fn cmd_status(detailed: bool, watch: bool, interval: u64, json: bool) -> Result<
             // Clear screen
             print!("\x1B[2J\x1B[1;1H");
 
-            let info = battery::get_battery_info()?;
+            let all = battery::get_battery_info(backend)?;
 
             // Record snapshot while we're at it
-            if let Ok(hist) = history::HistoryManager::open() {
-                let _ = hist.record_snapshot(&info);
+            if let Ok(hist) = db.open() {
+                let _ = hist.record_snapshot(&all);
             }
 
+            let info = select_or_all(all, battery)?;
+
             if json {
                 println!("{}", serde_json::to_string_pretty(&info)?);
             } else {
                 display::print_status(&info, detailed);
-                println!(
-                    "\n{}",
-                    format!(
-                        "Refreshing every {}s. Press Ctrl+C to stop.",
-                        interval
-                    )
-                );
+                println!("\nRefreshing every {}s. Press Ctrl+C to stop.", interval);
             }
 
             // Interruptible sleep
@@ -203,13 +576,15 @@ fn cmd_status(detailed: bool, watch: bool, interval: u64, json: bool) -> Result<
             }
         }
     } else {
-        let info = battery::get_battery_info()?;
+        let all = battery::get_battery_info(backend)?;
 
         // Record snapshot
-        if let Ok(hist) = history::HistoryManager::open() {
-            let _ = hist.record_snapshot(&info);
+        if let Ok(hist) = db.open() {
+            let _ = hist.record_snapshot(&all);
         }
 
+        let info = select_or_all(all, battery)?;
+
         if json {
             println!("{}", serde_json::to_string_pretty(&info)?);
         } else {
@@ -220,16 +595,39 @@ fn cmd_status(detailed: bool, watch: bool, interval: u64, json: bool) -> Result<
     Ok(())
 }
 
-fn cmd_health(show_history: bool, compare_new: bool, json: bool) -> Result<()> {
-    let info = battery::get_battery_info()?;
+#[allow(clippy::too_many_arguments)]
+fn cmd_health(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    db: &HistoryOptions,
+    trend: Option<history::TimeRange>,
+    stress: Option<history::TimeRange>,
+    compare_new: bool,
+    pack: &str,
+    json: bool,
+) -> Result<()> {
+    let pack = history::PackScope::parse(pack)?;
+    let info = match db.host {
+        // No live reading from another machine; use the last one it recorded
+        Some(name) => db
+            .open()?
+            .with_battery(battery)
+            .with_pack(pack)
+            .latest_reading()?
+            .ok_or_else(|| anyhow::anyhow!("No history from host '{}' in this database", name))?
+            .to_battery_info(),
+        None => battery::get_selected_battery(backend, battery)?,
+    };
 
-    let hist_manager = if show_history {
-        history::HistoryManager::open().ok()
+    // The cycle counter is worth a look even without the trend
+    let hist_manager = if trend.is_some() || stress.is_some() {
+        db.open().ok()
     } else {
-        None
-    };
+        db.open_existing()
+    }
+    .map(|h| h.with_battery(Some(&info.name)).with_pack(pack));
 
-    let report = health::generate_health_report(&info, hist_manager.as_ref())?;
+    let report = health::generate_health_report(&info, hist_manager.as_ref(), trend, stress)?;
 
     if json {
         println!("{}", serde_json::to_string_pretty(&report)?);
@@ -238,16 +636,32 @@ fn cmd_health(show_history: bool, compare_new: bool, json: bool) -> Result<()> {
 
     display::print_health_report(&report);
 
+    if let Some(range) = stress {
+        let range = health::stress_range(range);
+        // Like sessions, the analysis only has raw readings to go on
+        let now = chrono::Utc::now().timestamp();
+        let clamped = hist_manager
+            .as_ref()
+            .is_some_and(|h| range.start.timestamp() < h.raw_kept_since(now));
+        let raw_days = clamped.then_some(db.retention.raw_days);
+        display::print_stress(report.stress.as_ref(), &range, raw_days);
+    }
+
     if compare_new {
-        let comparison = health::compare_with_new(&info);
+        let comparison = health::compare_with_new(&report);
         display::print_health_comparison(&comparison);
     }
 
     Ok(())
 }
 
-fn cmd_power_hogs(detailed: bool, filter: Option<String>, json: bool) -> Result<()> {
-    let sys_power = power::get_system_power_draw();
+fn cmd_power_hogs(
+    backend: &dyn BatteryBackend,
+    detailed: bool,
+    filter: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let sys_power = backend.power_draw();
 
     let report = if let Some(ref f) = filter {
         power::get_power_report_filtered(f, sys_power)?
@@ -264,23 +678,48 @@ fn cmd_power_hogs(detailed: bool, filter: Option<String>, json: bool) -> Result<
     Ok(())
 }
 
-fn cmd_history(duration_str: &str, output: Option<&str>, json: bool) -> Result<()> {
-    let duration = history::parse_duration_str(duration_str)?;
-    let hist = history::HistoryManager::open()?;
+/// Where and how `history -o` writes; `--format` wins over the extension.
+fn export_target<'a>(
+    output: Option<&'a str>,
+    format: Option<&str>,
+) -> Result<Option<(&'a str, export::ExportFormat)>> {
+    let Some(path) = output else {
+        return Ok(None);
+    };
+    let format = match format {
+        Some(format) => export::ExportFormat::parse(format)?,
+        None => export::ExportFormat::for_path(path),
+    };
+    Ok(Some((path, format)))
+}
 
-    let snapshots = hist.get_snapshots_range(duration)?;
-    let summary = hist.get_summary(duration)?;
+fn cmd_history(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    db: &HistoryOptions,
+    range: history::TimeRange,
+    export: Option<(&str, export::ExportFormat)>,
+    pack: &str,
+    json: bool,
+) -> Result<()> {
+    // Pack names on another machine need not match this one's
+    let scope = match db.host {
+        Some(_) => battery.map(|b| b.to_string()),
+        None => history_scope(backend, battery),
+    };
+    let hist = db
+        .open()?
+        .with_battery(scope.as_deref())
+        .with_pack(history::PackScope::parse(pack)?);
+
+    let snapshots = hist.get_snapshots_range(range)?;
+    let summary = hist.get_summary(range)?;
+    let installs = hist.installs()?;
+    let selected = hist.selected_install()?.map(|install| install.id);
 
     // Export if requested
-    if let Some(path) = output {
-        if path.ends_with(".csv") {
-            display::export_snapshots_csv(&snapshots, path)?;
-        } else {
-            // Default to JSON
-            let json_str = serde_json::to_string_pretty(&snapshots)?;
-            std::fs::write(path, json_str)?;
-            println!("Exported {} snapshots to {}", snapshots.len(), path);
-        }
+    if let Some((path, format)) = export {
+        export::export_snapshots(&snapshots, format, path)?;
         return Ok(());
     }
 
@@ -288,18 +727,288 @@ fn cmd_history(duration_str: &str, output: Option<&str>, json: bool) -> Result<(
         let output = serde_json::json!({
             "summary": summary,
             "snapshots": snapshots,
+            "packs": installs,
+            "pack": selected,
         });
         println!("{}", serde_json::to_string_pretty(&output)?);
     } else {
         display::print_history(&snapshots, &summary);
+
+        // Only worth listing once a slot has held more than one pack
+        let slots: std::collections::HashSet<&str> =
+            installs.iter().map(|i| i.battery.as_str()).collect();
+        if installs.len() > slots.len() {
+            display::print_pack_installs(&installs, selected);
+        }
+
+        let hosts = hist.hosts()?;
+        if db.host.is_none() && !hosts.is_empty() {
+            println!();
+            println!(
+                "  Also holds history from: {}. Use --host <NAME> to view it.",
+                hosts.join(", ")
+            );
+        }
     }
 
     Ok(())
 }
 
-fn cmd_optimize(aggressive: bool, json: bool) -> Result<()> {
-    let info = battery::get_battery_info()?;
-    let sys_power = power::get_system_power_draw();
+fn cmd_history_maintenance(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    db: &HistoryOptions,
+    action: HistoryAction,
+    json: bool,
+) -> Result<()> {
+    let hist = db.open()?;
+
+    match action {
+        HistoryAction::Prune {
+            older_than,
+            dry_run,
+        } => {
+            let counts = hist.prune(history::parse_duration_str(&older_than)?, dry_run)?;
+            if json {
+                let output = serde_json::json!({ "dry_run": dry_run, "deleted": counts });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                display::print_prune_counts(&counts, &older_than, dry_run);
+            }
+        }
+        HistoryAction::Vacuum => {
+            let report = hist.vacuum()?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                display::print_vacuum_report(&report);
+            }
+        }
+        HistoryAction::Stats { min_gap } => {
+            let stats = hist.stats(history::parse_duration_str(&min_gap)?)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                display::print_history_stats(&stats);
+            }
+        }
+        HistoryAction::Check => {
+            let report = hist.check()?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                display::print_integrity_report(&report);
+            }
+            if !report.ok {
+                anyhow::bail!(
+                    "History database has {} problem(s)",
+                    report.issues.len().max(1)
+                );
+            }
+        }
+        HistoryAction::Import { file, host } => {
+            let snapshots = history::read_export(&file)?;
+            let counts = hist.import_snapshots(&snapshots, host.as_deref())?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&counts)?);
+            } else {
+                display::print_import_counts(&counts, &file.display().to_string());
+            }
+        }
+        HistoryAction::ImportUpower { dir, device } => {
+            let packs = local_packs(backend);
+            let mut imported = Vec::new();
+            let mut skipped = Vec::new();
+            for history in importers::read_upower_dir(&dir)? {
+                if device.as_ref().is_some_and(|d| *d != history.id) {
+                    continue;
+                }
+                // Match by serial number, so peripherals such as mice are left out
+                let slot = packs
+                    .iter()
+                    .find(|p| {
+                        p.serial_number
+                            .as_deref()
+                            .is_some_and(|serial| history.id.ends_with(&format!("-{}", serial)))
+                    })
+                    .map(|p| p.name.clone())
+                    .or_else(|| device.as_ref().and_then(|_| import_slot(&packs, battery)));
+                let Some(slot) = slot else {
+                    skipped.push(history.id);
+                    continue;
+                };
+                let snapshots: Vec<_> = history
+                    .snapshots
+                    .into_iter()
+                    .map(|s| history::BatterySnapshot {
+                        battery: Some(slot.clone()),
+                        ..s
+                    })
+                    .collect();
+                let counts = hist.import_snapshots(&snapshots, None)?;
+                imported.push((history.id, slot, counts));
+            }
+
+            if device.is_some() && imported.is_empty() {
+                anyhow::bail!(
+                    "No UPower history for that device in {}, or no battery to file it under (use --battery)",
+                    dir.display()
+                );
+            }
+            if json {
+                let devices: Vec<_> = imported
+                    .iter()
+                    .map(|(id, slot, counts)| {
+                        serde_json::json!({ "device": id, "battery": slot, "imported": counts })
+                    })
+                    .collect();
+                let output = serde_json::json!({ "devices": devices, "skipped": skipped });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                for (id, slot, counts) in &imported {
+                    display::print_import_counts(
+                        counts,
+                        &format!("UPower device {} as {}", id, slot),
+                    );
+                }
+                if imported.is_empty() {
+                    println!("No UPower battery history found in {}.", dir.display());
+                }
+                for id in &skipped {
+                    println!(
+                        "Skipped UPower device {}: no battery with a matching serial number. \
+                         Use --device {} to import it anyway.",
+                        id, id
+                    );
+                }
+            }
+        }
+        HistoryAction::ImportPmset { file } => {
+            let text = match &file {
+                Some(path) => std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?,
+                None => importers::read_pmset_log()?,
+            };
+            let packs = local_packs(backend);
+            let Some(slot) = import_slot(&packs, battery) else {
+                anyhow::bail!("Several batteries found; choose one with --battery");
+            };
+            let snapshots: Vec<_> = importers::parse_pmset_log(&text)
+                .into_iter()
+                .map(|s| history::BatterySnapshot {
+                    battery: Some(slot.clone()),
+                    ..s
+                })
+                .collect();
+            let counts = hist.import_snapshots(&snapshots, None)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&counts)?);
+            } else {
+                display::print_import_counts(&counts, "pmset log");
+            }
+        }
+        HistoryAction::Merge { source, host } => {
+            let host = match host {
+                Some(host) => host,
+                None => source
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .ok_or_else(|| anyhow::anyhow!("Pass --host to name the merged machine"))?,
+            };
+            let counts = hist.merge(&source, &host)?;
+            if json {
+                let output = serde_json::json!({ "host": host, "merged": counts });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                display::print_import_counts(
+                    &counts,
+                    &format!("{} (host '{}')", source.display(), host),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_sessions(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    db: &HistoryOptions,
+    duration_str: &str,
+    pack: &str,
+    json: bool,
+) -> Result<()> {
+    let duration = history::parse_duration_str(duration_str)?;
+    let scope = match db.host {
+        Some(_) => battery.map(|b| b.to_string()),
+        None => history_scope(backend, battery),
+    };
+    let hist = db
+        .open()?
+        .with_battery(scope.as_deref())
+        .with_pack(history::PackScope::parse(pack)?);
+
+    let snapshots = hist.get_raw_snapshots(duration)?;
+    let sessions = sessions::detect_sessions(&snapshots);
+
+    if json {
+        let output = serde_json::json!({ "sessions": sessions });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        display::print_sessions(&sessions, &history::format_duration(&duration));
+    }
+    Ok(())
+}
+
+fn cmd_sleep(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    db: &HistoryOptions,
+    duration_str: &str,
+    json: bool,
+) -> Result<()> {
+    let duration = history::parse_duration_str(duration_str)?;
+    let scope = match db.host {
+        Some(_) => battery.map(|b| b.to_string()),
+        None => history_scope(backend, battery),
+    };
+    let hist = db
+        .open()?
+        .with_battery(scope.as_deref())
+        .with_pack(history::PackScope::All);
+
+    let snapshots = hist.get_raw_snapshots(duration)?;
+    let events = hist.sleep_events(duration)?;
+    let report = sleep::sleep_report(sleep::detect_sleep(&snapshots, &events));
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        display::print_sleep_report(&report, &history::format_duration(&duration));
+    }
+    Ok(())
+}
+
+fn cmd_hook(backend: &dyn BatteryBackend, db: &HistoryOptions, event: HookEvent) -> Result<()> {
+    let kind = match event {
+        HookEvent::PreSleep => sleep::EVENT_SLEEP,
+        HookEvent::PostSleep => sleep::EVENT_WAKE,
+    };
+    let info = battery::get_battery_info(backend)?;
+    db.open()?.record_sleep_event(kind, &info)?;
+    println!("Recorded {} at {}% ({})", kind, info.level, info.state);
+    Ok(())
+}
+
+fn cmd_optimize(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    aggressive: bool,
+    json: bool,
+) -> Result<()> {
+    let info = battery::get_selected_battery(backend, battery)?;
+    let sys_power = backend.power_draw();
     let power_report = power::get_power_report(sys_power)?;
     let report = optimize::generate_suggestions(&info, Some(&power_report), aggressive);
 
@@ -312,7 +1021,13 @@ fn cmd_optimize(aggressive: bool, json: bool) -> Result<()> {
     Ok(())
 }
 
-fn cmd_alert(level: Option<u8>, on_full: bool, _daemon: bool) -> Result<()> {
+fn cmd_alert(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    level: Option<u8>,
+    on_full: bool,
+    _daemon: bool,
+) -> Result<()> {
     if level.is_none() && !on_full {
         anyhow::bail!(
             "Please specify at least one alert condition:\n  \
@@ -322,6 +1037,7 @@ fn cmd_alert(level: Option<u8>, on_full: bool, _daemon: bool) -> Result<()> {
     }
 
     let config = alert::AlertConfig {
+        battery: battery.map(|b| b.to_string()),
         level_threshold: level,
         on_full,
         check_interval: std::time::Duration::from_secs(60),
@@ -333,16 +1049,214 @@ fn cmd_alert(level: Option<u8>, on_full: bool, _daemon: bool) -> Result<()> {
         r.store(false, Ordering::Relaxed);
     })?;
 
-    alert::run_alert_loop(&config, running)
+    alert::run_alert_loop(backend, &config, running)
+}
+
+fn cmd_limit(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    stop: Option<u8>,
+    start: Option<u8>,
+    off: bool,
+    dry_run: bool,
+    json: bool,
+) -> Result<()> {
+    let changing = off || stop.is_some() || start.is_some();
+    let mut statuses = Vec::new();
+    let mut updates = Vec::new();
+
+    // Check every pack before writing any, so a pack that cannot take the
+    // new thresholds does not leave the others half changed
+    for name in pack_names(backend, battery)? {
+        let current = backend.charge_limit(&name)?;
+        if changing {
+            let current = current
+                .ok_or_else(|| anyhow::anyhow!("Charge limits are not supported on {}", name))?;
+            let update = if off {
+                charge::ChargeLimit {
+                    start: current.start.map(|_| 0),
+                    end: Some(100),
+                }
+            } else {
+                charge::ChargeLimit { start, end: stop }
+            };
+            current.merged(&update).validate()?;
+            updates.push(update);
+        }
+        statuses.push(charge::LimitStatus {
+            battery: name,
+            supported: current.is_some(),
+            limit: current,
+            actions: Vec::new(),
+        });
+    }
+
+    for (status, update) in statuses.iter_mut().zip(&updates) {
+        status.actions = backend.set_charge_limit(&status.battery, update, dry_run)?;
+        if !dry_run {
+            status.limit = backend.charge_limit(&status.battery)?;
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&statuses)?);
+    } else {
+        display::print_limit_status(&statuses);
+    }
+    Ok(())
 }
 
-fn cmd_record() -> Result<()> {
-    let info = battery::get_battery_info()?;
-    let hist = history::HistoryManager::open()?;
-    hist.record_snapshot(&info)?;
+fn cmd_charge(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    mode: Option<&str>,
+    duration: Option<&str>,
+    until_level: Option<u8>,
+    dry_run: bool,
+    json: bool,
+) -> Result<()> {
+    let revert = charge::RevertConfig {
+        battery: battery.map(|b| b.to_string()),
+        after: duration
+            .map(history::parse_duration_str)
+            .transpose()?
+            .map(|d| d.to_std())
+            .transpose()?,
+        until_level,
+        mode: mode.unwrap_or_default().to_string(),
+        check_interval: std::time::Duration::from_secs(30),
+    };
+
+    let mut statuses = Vec::new();
+    for name in pack_names(backend, battery)? {
+        let behaviour = backend.charge_behaviour(&name)?;
+        if mode.is_some() && behaviour.is_none() {
+            anyhow::bail!("Charge behaviour control is not supported on {}", name);
+        }
+        statuses.push(charge::BehaviourStatus {
+            battery: name,
+            behaviour,
+            actions: Vec::new(),
+        });
+    }
+
+    let mut previous = Vec::new();
+    if let Some(mode) = mode {
+        // Plan every switch before making any, so a pack that rejects the
+        // mode does not leave the others switched with nothing to revert them
+        for status in &mut statuses {
+            status.actions = backend.set_charge_behaviour(&status.battery, mode, true)?;
+        }
+        if !dry_run {
+            for status in &mut statuses {
+                let active = status.behaviour.as_ref().map(|b| b.active.clone());
+                let switched = backend.set_charge_behaviour(&status.battery, mode, false);
+                let actions = match switched {
+                    Ok(actions) => actions,
+                    Err(e) => {
+                        if let Err(restore) = charge::restore_behaviours(backend, &previous) {
+                            eprintln!("Error: {:#}", restore);
+                        }
+                        return Err(e);
+                    }
+                };
+                status.actions = actions;
+                if let Some(active) = active.filter(|a| a != mode) {
+                    previous.push((status.battery.clone(), active));
+                }
+                // The switch is made; a failed re-read must not skip the revert
+                if let Ok(behaviour) = backend.charge_behaviour(&status.battery) {
+                    status.behaviour = behaviour;
+                }
+            }
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&statuses)?);
+    } else {
+        display::print_charge_behaviour(&statuses);
+    }
+
+    if revert.is_set() && !dry_run && !previous.is_empty() {
+        let running = Arc::new(AtomicBool::new(true));
+        let r = running.clone();
+        ctrlc::set_handler(move || {
+            r.store(false, Ordering::Relaxed);
+        })?;
+
+        eprintln!("Temporary charge behaviour active. Press Ctrl+C to revert now.");
+        charge::run_revert_loop(backend, &revert, &previous, running)?;
+    }
+
+    Ok(())
+}
+
+fn cmd_record(
+    backend: &dyn BatteryBackend,
+    battery: Option<&str>,
+    db: &HistoryOptions,
+) -> Result<()> {
+    let all = battery::get_battery_info(backend)?;
+    let hist = db.open()?;
+    hist.record_snapshot(&all)?;
+    let info = select_or_all(all, battery)?;
     println!(
         "Recorded snapshot: {}% ({})",
         info.level, info.state
     );
     Ok(())
 }
+
+// ── Helpers ────────────────────────────────────────────────────────────
+
+/// Narrow a combined reading down to the pack chosen with `--battery`.
+fn select_or_all(
+    info: battery::BatteryInfo,
+    battery: Option<&str>,
+) -> Result<battery::BatteryInfo> {
+    match battery {
+        Some(name) => battery::select_battery(info, name),
+        None => Ok(info),
+    }
+}
+
+/// Packs a command should act on: the one chosen with `--battery`, or all.
+fn pack_names(backend: &dyn BatteryBackend, battery: Option<&str>) -> Result<Vec<String>> {
+    let info = battery::get_battery_info(backend)?;
+    let info = select_or_all(info, battery)?;
+    Ok(if info.packs.is_empty() {
+        vec![info.name]
+    } else {
+        info.packs.into_iter().map(|p| p.name).collect()
+    })
+}
+
+/// Every pack on this machine, or none if batteries can't be read.
+fn local_packs(backend: &dyn BatteryBackend) -> Vec<battery::BatteryInfo> {
+    match battery::get_battery_info(backend) {
+        Ok(info) if info.packs.is_empty() => vec![info],
+        Ok(info) => info.packs,
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Pack imported history is filed under: the one chosen with `--battery`,
+/// or the only one this machine has.
+fn import_slot(packs: &[battery::BatteryInfo], battery: Option<&str>) -> Option<String> {
+    match (battery, packs) {
+        (Some(name), _) => Some(name.to_string()),
+        (None, [only]) => Some(only.name.clone()),
+        _ => None,
+    }
+}
+
+/// Pack whose history to show: the one chosen with `--battery`, otherwise
+/// the current default view (the combined view on multi-pack machines).
+fn history_scope(backend: &dyn BatteryBackend, battery: Option<&str>) -> Option<String> {
+    battery.map(|b| b.to_string()).or_else(|| {
+        battery::get_battery_info(backend)
+            .ok()
+            .map(|info| info.name)
+    })
+}
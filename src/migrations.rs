@@ -0,0 +1,451 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+
+/// A schema change, applied once and in order.
+struct Migration {
+    description: &'static str,
+    apply: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+/// Every schema change since the first release, oldest first. The database's
+/// `PRAGMA user_version` is the number of entries already applied.
+///
+/// Append only: never edit or reorder an entry that has shipped. Steps that
+/// add columns use `add_column_if_missing`, because databases from before
+/// versioning may already have some of them.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "create snapshots table",
+        apply: create_snapshots,
+    },
+    Migration {
+        description: "track battery packs",
+        apply: |conn| add_column_if_missing(conn, "snapshots", "battery", "TEXT"),
+    },
+    Migration {
+        description: "store energy alongside charge",
+        apply: add_energy_columns,
+    },
+    Migration {
+        description: "store pack identity",
+        apply: add_identity_columns,
+    },
+    Migration {
+        description: "create events table",
+        apply: create_events,
+    },
+    Migration {
+        description: "store state, temperature, voltage and remaining charge",
+        apply: add_reading_columns,
+    },
+    Migration {
+        description: "create hourly and daily rollups",
+        apply: create_rollups,
+    },
+    Migration {
+        description: "tag rows with the host they were recorded on",
+        apply: add_host_columns,
+    },
+    Migration {
+        description: "count suspend gaps separately in rollups",
+        apply: |conn| {
+            add_column_if_missing(
+                conn,
+                "rollups_hourly",
+                "sleep_minutes",
+                "INTEGER NOT NULL DEFAULT 0",
+            )?;
+            add_column_if_missing(
+                conn,
+                "rollups_daily",
+                "sleep_minutes",
+                "INTEGER NOT NULL DEFAULT 0",
+            )
+        },
+    },
+    Migration {
+        description: "count discharge throughput per pack",
+        apply: create_cycle_counters,
+    },
+    Migration {
+        description: "track internal resistance per pack",
+        apply: create_resistance_samples,
+    },
+    Migration {
+        description: "mark imported snapshots",
+        apply: |conn| {
+            add_column_if_missing(conn, "snapshots", "imported", "INTEGER NOT NULL DEFAULT 0")
+        },
+    },
+    Migration {
+        description: "store health quartiles in rollups",
+        apply: add_health_quartile_columns,
+    },
+];
+
+/// Schema version this build writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// The `user_version` recorded in the database.
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Bring the database up to `SCHEMA_VERSION`, one migration per transaction.
+///
+/// Each step re-reads the version under the write lock, so two processes
+/// opening an old database at once apply every migration exactly once.
+pub fn migrate(conn: &Connection) -> Result<()> {
+    let mut current = schema_version(conn).context("Failed to read schema version")?;
+    while current < SCHEMA_VERSION {
+        let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+        current = schema_version(&tx).context("Failed to read schema version")?;
+        let Some(migration) = MIGRATIONS.get(current as usize) else {
+            break;
+        };
+        let version = current + 1;
+        (migration.apply)(&tx).with_context(|| {
+            format!(
+                "Failed to migrate history database to version {} ({})",
+                version, migration.description
+            )
+        })?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+        current = version;
+    }
+
+    if current > SCHEMA_VERSION {
+        anyhow::bail!(
+            "History database has schema version {}, but this batteryctl only knows up to {}. \
+             Upgrade batteryctl to use it.",
+            current,
+            SCHEMA_VERSION
+        );
+    }
+    Ok(())
+}
+
+fn create_snapshots(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS snapshots (
+            timestamp INTEGER NOT NULL,
+            level INTEGER NOT NULL,
+            is_charging BOOLEAN NOT NULL,
+            power_draw REAL,
+            cycle_count INTEGER,
+            max_capacity INTEGER,
+            design_capacity INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS idx_snapshots_timestamp ON snapshots(timestamp);",
+    )
+}
+
+fn add_energy_columns(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "snapshots", "max_energy", "INTEGER")?;
+    add_column_if_missing(conn, "snapshots", "design_energy", "INTEGER")?;
+    add_column_if_missing(conn, "snapshots", "capacity_unit", "TEXT")
+}
+
+fn add_identity_columns(conn: &Connection) -> rusqlite::Result<()> {
+    for column in ["model_name", "manufacturer", "serial_number", "technology"] {
+        add_column_if_missing(conn, "snapshots", column, "TEXT")?;
+    }
+    Ok(())
+}
+
+fn add_reading_columns(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "snapshots", "state", "TEXT")?;
+    add_column_if_missing(conn, "snapshots", "temperature", "REAL")?;
+    add_column_if_missing(conn, "snapshots", "voltage", "REAL")?;
+    add_column_if_missing(conn, "snapshots", "current_capacity", "INTEGER")?;
+    add_column_if_missing(conn, "snapshots", "current_energy", "INTEGER")?;
+    add_column_if_missing(conn, "snapshots", "time_remaining", "INTEGER")
+}
+
+fn create_rollups(conn: &Connection) -> rusqlite::Result<()> {
+    for table in ["rollups_hourly", "rollups_daily"] {
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                bucket INTEGER NOT NULL,
+                battery TEXT NOT NULL,
+                samples INTEGER NOT NULL,
+                min_level INTEGER NOT NULL,
+                max_level INTEGER NOT NULL,
+                avg_level REAL NOT NULL,
+                level_discharged REAL NOT NULL,
+                charging_periods INTEGER NOT NULL,
+                charging_minutes INTEGER NOT NULL,
+                discharging_minutes INTEGER NOT NULL,
+                not_charging_minutes INTEGER NOT NULL,
+                energy_in_mwh REAL NOT NULL,
+                energy_out_mwh REAL NOT NULL,
+                avg_power REAL,
+                avg_discharge_power REAL,
+                discharge_power_samples INTEGER NOT NULL,
+                avg_temperature REAL,
+                max_temperature REAL,
+                temperature_samples INTEGER NOT NULL,
+                state TEXT,
+                is_charging BOOLEAN NOT NULL,
+                cycle_count INTEGER,
+                max_capacity INTEGER,
+                design_capacity INTEGER,
+                max_energy INTEGER,
+                design_energy INTEGER,
+                capacity_unit TEXT,
+                PRIMARY KEY (battery, bucket)
+            );
+            CREATE INDEX IF NOT EXISTS idx_{}_bucket ON {}(bucket);",
+            table, table, table
+        ))?;
+    }
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );",
+    )
+}
+
+/// `host` is NULL for rows recorded on this machine. Rollups key on it, so
+/// their tables are rebuilt with it in the primary key (`''` for local rows).
+fn add_host_columns(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "snapshots", "host", "TEXT")?;
+    add_column_if_missing(conn, "events", "host", "TEXT")?;
+    for table in ["rollups_hourly", "rollups_daily"] {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {table} RENAME TO {table}_old;
+            CREATE TABLE {table} (
+                host TEXT NOT NULL DEFAULT '',
+                bucket INTEGER NOT NULL,
+                battery TEXT NOT NULL,
+                samples INTEGER NOT NULL,
+                min_level INTEGER NOT NULL,
+                max_level INTEGER NOT NULL,
+                avg_level REAL NOT NULL,
+                level_discharged REAL NOT NULL,
+                charging_periods INTEGER NOT NULL,
+                charging_minutes INTEGER NOT NULL,
+                discharging_minutes INTEGER NOT NULL,
+                not_charging_minutes INTEGER NOT NULL,
+                energy_in_mwh REAL NOT NULL,
+                energy_out_mwh REAL NOT NULL,
+                avg_power REAL,
+                avg_discharge_power REAL,
+                discharge_power_samples INTEGER NOT NULL,
+                avg_temperature REAL,
+                max_temperature REAL,
+                temperature_samples INTEGER NOT NULL,
+                state TEXT,
+                is_charging BOOLEAN NOT NULL,
+                cycle_count INTEGER,
+                max_capacity INTEGER,
+                design_capacity INTEGER,
+                max_energy INTEGER,
+                design_energy INTEGER,
+                capacity_unit TEXT,
+                PRIMARY KEY (host, battery, bucket)
+            );
+            INSERT INTO {table} SELECT '', * FROM {table}_old;
+            DROP TABLE {table}_old;
+            CREATE INDEX idx_{table}_bucket ON {table}(bucket);",
+            table = table
+        ))?;
+    }
+    Ok(())
+}
+
+/// One row per pack and host (`''` for this machine), replaced when the
+/// pack is.
+fn add_health_quartile_columns(conn: &Connection) -> rusqlite::Result<()> {
+    for table in ["rollups_hourly", "rollups_daily"] {
+        for column in ["health_p25", "health_median", "health_p75"] {
+            add_column_if_missing(conn, table, column, "REAL")?;
+        }
+        add_column_if_missing(conn, table, "health_samples", "INTEGER NOT NULL DEFAULT 0")?;
+    }
+    // Rebuild the rollups that still have raw rows, so they get quartiles
+    conn.execute("DELETE FROM meta WHERE key = 'rollups_through'", [])?;
+    Ok(())
+}
+
+fn create_cycle_counters(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS cycle_counters (
+            host TEXT NOT NULL DEFAULT '',
+            battery TEXT NOT NULL,
+            since INTEGER NOT NULL,
+            updated INTEGER NOT NULL,
+            discharged_mwh REAL NOT NULL,
+            cycles REAL NOT NULL,
+            PRIMARY KEY (host, battery)
+        );",
+    )
+}
+
+fn create_resistance_samples(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS resistance_samples (
+            host TEXT NOT NULL DEFAULT '',
+            battery TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            resistance_mohm REAL NOT NULL,
+            current_step_ma REAL NOT NULL,
+            temperature REAL,
+            PRIMARY KEY (host, battery, timestamp)
+        );",
+    )
+}
+
+fn create_events(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS events (
+            id INTEGER PRIMARY KEY,
+            timestamp INTEGER NOT NULL,
+            battery TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            serial_number TEXT,
+            model_name TEXT,
+            detail TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_events_kind ON events(kind, timestamp);",
+    )
+}
+
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    decl: &str,
+) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|name| name.ok())
+        .any(|name| name == column);
+    if !exists {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, decl
+        ))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(&format!("PRAGMA table_info({})", table))
+            .unwrap();
+        stmt.query_map([], |row| row.get::<_, String>(1))
+            .unwrap()
+            .map(|c| c.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_migrates_fresh_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert!(columns(&conn, "snapshots").contains(&"serial_number".to_string()));
+
+        // Running again is a no-op
+        migrate(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_upgrades_first_release_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_snapshots(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO snapshots (timestamp, level, is_charging, power_draw, cycle_count, max_capacity, design_capacity)
+             VALUES (1700000000, 64, 0, 9.5, 187, 4343, 4935)",
+            [],
+        )
+        .unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+
+        migrate(&conn).unwrap();
+
+        let (level, max, battery): (i32, i32, Option<String>) = conn
+            .query_row(
+                "SELECT level, max_capacity, battery FROM snapshots",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((level, max, battery), (64, 4343, None));
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_upgrades_unversioned_database_with_newer_columns() {
+        // Databases from before versioning gained columns on open, so some
+        // migrations find their changes already applied.
+        let conn = Connection::open_in_memory().unwrap();
+        create_snapshots(&conn).unwrap();
+        add_column_if_missing(&conn, "snapshots", "battery", "TEXT").unwrap();
+        add_energy_columns(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO snapshots (timestamp, level, is_charging, battery, max_energy, capacity_unit)
+             VALUES (1700000000, 64, 0, 'BAT0', 50160, 'mWh')",
+            [],
+        )
+        .unwrap();
+
+        migrate(&conn).unwrap();
+
+        let energy: i32 = conn
+            .query_row(
+                "SELECT max_energy FROM snapshots WHERE battery = 'BAT0'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(energy, 50160);
+        assert!(columns(&conn, "events").contains(&"detail".to_string()));
+    }
+
+    #[test]
+    fn test_rollups_gain_host_key() {
+        let conn = Connection::open_in_memory().unwrap();
+        for migration in &MIGRATIONS[..7] {
+            (migration.apply)(&conn).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 7).unwrap();
+        conn.execute(
+            "INSERT INTO rollups_daily (bucket, battery, samples, min_level, max_level, avg_level,
+                 level_discharged, charging_periods, charging_minutes, discharging_minutes,
+                 not_charging_minutes, energy_in_mwh, energy_out_mwh, discharge_power_samples,
+                 temperature_samples, is_charging)
+             VALUES (1700006400, 'BAT0', 48, 40, 95, 70.0, 55.0, 1, 240, 1200, 0, 0, 0, 0, 0, 0)",
+            [],
+        )
+        .unwrap();
+
+        migrate(&conn).unwrap();
+
+        let (host, samples): (String, i64) = conn
+            .query_row("SELECT host, samples FROM rollups_daily", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((host.as_str(), samples), ("", 48));
+        assert!(columns(&conn, "snapshots").contains(&"host".to_string()));
+    }
+
+    #[test]
+    fn test_refuses_newer_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        let err = migrate(&conn).unwrap_err().to_string();
+        assert!(err.contains("Upgrade batteryctl"), "{}", err);
+    }
+}
@@ -77,9 +77,8 @@ pub fn generate_suggestions(
                 suggestions.push(Suggestion {
                     priority: Priority::High,
                     title: format!("{} is using {:.0}% CPU", app.name, app.cpu_percent),
-                    description: format!(
-                        "Close unused instances or switch to a lighter alternative"
-                    ),
+                    description: "Close unused instances or switch to a lighter alternative"
+                        .to_string(),
                     estimated_savings: Some(savings),
                 });
                 total_savings_mins += 15;
@@ -110,7 +109,12 @@ pub fn generate_suggestions(
     }
 
     // ── Medium Impact: Battery level advice ─────────────────────────────
-    if battery.level > 80 && matches!(battery.state, crate::battery::ChargingState::Charging) {
+    // Skipped when a firmware charge limit already stops charging early.
+    let limit_active = battery.charge_limit.is_some_and(|l| l.is_active());
+    if battery.level > 80
+        && matches!(battery.state, crate::battery::ChargingState::Charging)
+        && !limit_active
+    {
         suggestions.push(Suggestion {
             priority: Priority::Medium,
             title: "Unplug charger to preserve battery health".to_string(),
@@ -199,6 +203,7 @@ mod tests {
     #[test]
     fn test_generate_suggestions() {
         let info = BatteryInfo {
+            name: "BAT0".to_string(),
             level: 50,
             state: ChargingState::Discharging,
             time_remaining_minutes: Some(120),
@@ -212,6 +217,7 @@ mod tests {
             condition: BatteryCondition::Normal,
             manufacture_date: None,
             is_present: true,
+            ..Default::default()
         };
 
         let report = generate_suggestions(&info, None, false);
@@ -221,6 +227,7 @@ mod tests {
     #[test]
     fn test_aggressive_mode_adds_suggestions() {
         let info = BatteryInfo {
+            name: "BAT0".to_string(),
             level: 50,
             state: ChargingState::Discharging,
             time_remaining_minutes: None,
@@ -234,10 +241,33 @@ mod tests {
             condition: BatteryCondition::Unknown,
             manufacture_date: None,
             is_present: true,
+            ..Default::default()
         };
 
         let normal = generate_suggestions(&info, None, false);
         let aggressive = generate_suggestions(&info, None, true);
         assert!(aggressive.suggestions.len() > normal.suggestions.len());
     }
+
+    #[test]
+    fn test_unplug_advice_suppressed_by_charge_limit() {
+        let mut info = BatteryInfo {
+            level: 85,
+            state: ChargingState::Charging,
+            ..Default::default()
+        };
+        let unplug = |report: &OptimizationReport| {
+            report
+                .suggestions
+                .iter()
+                .any(|s| s.title.starts_with("Unplug charger"))
+        };
+        assert!(unplug(&generate_suggestions(&info, None, false)));
+
+        info.charge_limit = Some(crate::charge::ChargeLimit {
+            start: Some(85),
+            end: Some(90),
+        });
+        assert!(!unplug(&generate_suggestions(&info, None, false)));
+    }
 }
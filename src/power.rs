@@ -146,40 +146,8 @@ pub fn get_power_report_filtered(
     Ok(report)
 }
 
-/// Try to get the actual system power draw from platform-specific sources.
-pub fn get_system_power_draw() -> Option<f64> {
-    if cfg!(target_os = "linux") {
-        get_linux_power_draw()
-    } else if cfg!(target_os = "macos") {
-        get_macos_power_draw()
-    } else {
-        None
-    }
-}
-
-fn get_linux_power_draw() -> Option<f64> {
-    // Try reading from power_supply
-    let base = std::path::Path::new("/sys/class/power_supply");
-    if !base.exists() {
-        return None;
-    }
-    for entry in std::fs::read_dir(base).ok()?.flatten() {
-        let path = entry.path();
-        let type_file = path.join("type");
-        if let Ok(ptype) = std::fs::read_to_string(&type_file) {
-            if ptype.trim().eq_ignore_ascii_case("battery") {
-                if let Ok(power) = std::fs::read_to_string(path.join("power_now")) {
-                    if let Ok(val) = power.trim().parse::<f64>() {
-                        return Some(val / 1_000_000.0); // µW -> W
-                    }
-                }
-            }
-        }
-    }
-    None
-}
-
-fn get_macos_power_draw() -> Option<f64> {
+/// Read the adapter/battery wattage reported by `system_profiler`.
+pub fn get_macos_power_draw() -> Option<f64> {
     // Parse pmset -g batt for wattage info (e.g., "(id=1234567) ... ; ... present: true")
     let output = std::process::Command::new("system_profiler")
         .args(["SPPowerDataType"])
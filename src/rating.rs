@@ -0,0 +1,264 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+use crate::battery::BatteryCondition;
+
+/// Built-in rules, most specific first. `cycle_ratings` in the config file
+/// takes the same form and is consulted before these.
+const BUILTIN_RULES: &str = include_str!("ratings.json");
+
+/// What batteryctl assumed for every pack before ratings were looked up.
+const DEFAULT_CYCLES: u32 = 1000;
+const DEFAULT_END_OF_LIFE: f64 = 80.0;
+
+/// Below end of life by this many points, a pack is due for replacement
+/// rather than just worn.
+const REPLACE_MARGIN: f64 = 20.0;
+
+/// Share of the rated cycles after which service is recommended.
+const SERVICE_SHARE: f64 = 0.8;
+
+/// Cycles a pack is rated for, and the share of its design capacity it
+/// should still hold after them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CycleRating {
+    pub rated_cycles: u32,
+    pub end_of_life_percent: f64,
+    /// Rule the rating comes from, e.g. `Li-ion`, or `default`.
+    pub source: String,
+}
+
+impl Default for CycleRating {
+    fn default() -> Self {
+        Self {
+            rated_cycles: DEFAULT_CYCLES,
+            end_of_life_percent: DEFAULT_END_OF_LIFE,
+            source: "default".to_string(),
+        }
+    }
+}
+
+impl CycleRating {
+    pub fn remaining_cycles(&self, cycles: u32) -> u32 {
+        self.rated_cycles.saturating_sub(cycles)
+    }
+
+    /// Condition for a health percentage: service below end of life,
+    /// replace well below it.
+    pub fn condition_for_health(&self, health: f64) -> BatteryCondition {
+        if health >= self.end_of_life_percent {
+            BatteryCondition::Normal
+        } else if health >= self.end_of_life_percent - REPLACE_MARGIN {
+            BatteryCondition::ServiceRecommended
+        } else {
+            BatteryCondition::Replace
+        }
+    }
+
+    /// Condition for a cycle count: service towards the end of the rated
+    /// cycles, replace past them.
+    pub fn condition_for_cycles(&self, cycles: u32) -> BatteryCondition {
+        let cycles = cycles as f64;
+        let rated = self.rated_cycles as f64;
+        if cycles < rated * SERVICE_SHARE {
+            BatteryCondition::Normal
+        } else if cycles < rated {
+            BatteryCondition::ServiceRecommended
+        } else {
+            BatteryCondition::Replace
+        }
+    }
+}
+
+/// A row of the rating table. Every field that is set must match the pack:
+/// `technology` by chemistry, `manufacturer` and `model` as
+/// case-insensitive prefixes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RatingRule {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub technology: Option<String>,
+    #[serde(default)]
+    pub manufacturer: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub rated_cycles: u32,
+    #[serde(default = "default_end_of_life")]
+    pub end_of_life_percent: f64,
+}
+
+fn default_end_of_life() -> f64 {
+    DEFAULT_END_OF_LIFE
+}
+
+impl RatingRule {
+    pub fn validate(&self) -> Result<()> {
+        if self.rated_cycles == 0 {
+            anyhow::bail!("cycle_ratings: rated_cycles must be greater than 0");
+        }
+        if !(self.end_of_life_percent > 0.0 && self.end_of_life_percent <= 100.0) {
+            anyhow::bail!(
+                "cycle_ratings: end_of_life_percent must be between 0 and 100 (got {})",
+                self.end_of_life_percent
+            );
+        }
+        Ok(())
+    }
+
+    fn matches(&self, pack: &PackIdentity) -> bool {
+        let prefix = |rule: &Option<String>, value: Option<&str>| match (rule, value) {
+            (None, _) => true,
+            (Some(rule), Some(value)) => value
+                .to_lowercase()
+                .starts_with(&rule.trim().to_lowercase()),
+            (Some(_), None) => false,
+        };
+        let chemistry = match (&self.technology, pack.technology) {
+            (None, _) => true,
+            (Some(rule), Some(value)) => chemistry(rule) == chemistry(value),
+            (Some(_), None) => false,
+        };
+        chemistry
+            && prefix(&self.manufacturer, pack.manufacturer)
+            && prefix(&self.model, pack.model)
+    }
+
+    fn rating(&self) -> CycleRating {
+        let source = self
+            .name
+            .clone()
+            .or_else(|| self.model.clone())
+            .or_else(|| self.manufacturer.clone())
+            .or_else(|| self.technology.clone())
+            .unwrap_or_else(|| "config".to_string());
+        CycleRating {
+            rated_cycles: self.rated_cycles,
+            end_of_life_percent: self.end_of_life_percent,
+            source,
+        }
+    }
+}
+
+/// Canonical name for the ways chemistries are spelled (sysfs says `LiFe`
+/// for LiFePO4, people say LFP).
+fn chemistry(name: &str) -> String {
+    let name = name.trim().to_lowercase().replace(['-', ' '], "");
+    match name.as_str() {
+        "life" | "lifepo4" | "lfp" => "lfp".to_string(),
+        "lion" | "liion" => "liion".to_string(),
+        "lipo" | "lipoly" | "lipolymer" => "lipoly".to_string(),
+        _ => name,
+    }
+}
+
+/// What the firmware says about a pack, for looking up its rating.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PackIdentity<'a> {
+    pub technology: Option<&'a str>,
+    pub manufacturer: Option<&'a str>,
+    pub model: Option<&'a str>,
+}
+
+static USER_RULES: OnceLock<Vec<RatingRule>> = OnceLock::new();
+static BUILTIN: OnceLock<Vec<RatingRule>> = OnceLock::new();
+
+/// Install the rules from the config file, once at startup.
+pub fn set_user_rules(rules: Vec<RatingRule>) {
+    let _ = USER_RULES.set(rules);
+}
+
+fn builtin_rules() -> &'static [RatingRule] {
+    BUILTIN.get_or_init(|| {
+        serde_json::from_str(BUILTIN_RULES).expect("built-in cycle ratings are valid JSON")
+    })
+}
+
+/// Rating of a pack: the first matching rule from the config file, else
+/// the first built-in one, else the 1000 cycles assumed for any pack.
+pub fn rating_for(pack: PackIdentity) -> CycleRating {
+    lookup(USER_RULES.get().map_or(&[], |rules| rules), pack)
+}
+
+fn lookup(user_rules: &[RatingRule], pack: PackIdentity) -> CycleRating {
+    user_rules
+        .iter()
+        .chain(builtin_rules())
+        .find(|rule| rule.matches(&pack))
+        .map(RatingRule::rating)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack<'a>(
+        technology: Option<&'a str>,
+        manufacturer: Option<&'a str>,
+        model: Option<&'a str>,
+    ) -> PackIdentity<'a> {
+        PackIdentity {
+            technology,
+            manufacturer,
+            model,
+        }
+    }
+
+    #[test]
+    fn test_builtin_ratings() {
+        for rule in builtin_rules() {
+            rule.validate().unwrap();
+        }
+
+        let thinkpad = lookup(&[], pack(Some("Li-poly"), Some("SMP"), Some("5B10W13930")));
+        assert_eq!(thinkpad.rated_cycles, 500);
+        assert!(thinkpad.source.starts_with("Lenovo"));
+        assert_eq!(
+            lookup(&[], pack(Some("Li-ion"), None, Some("DELL 8P6X604"))).rated_cycles,
+            300
+        );
+        assert_eq!(
+            lookup(&[], pack(Some("LiFe"), None, None)).rated_cycles,
+            2000
+        );
+        assert_eq!(
+            lookup(&[], pack(Some("li-ion"), Some("ACME"), None)).source,
+            "Li-ion"
+        );
+        assert_eq!(lookup(&[], pack(None, None, None)), CycleRating::default());
+    }
+
+    #[test]
+    fn test_config_rules_come_first() {
+        let rules: Vec<RatingRule> = serde_json::from_str(
+            r#"[{"name": "Framework", "manufacturer": "NVT", "rated_cycles": 1000},
+                {"technology": "LFP", "rated_cycles": 3000, "end_of_life_percent": 70}]"#,
+        )
+        .unwrap();
+
+        let framework = lookup(&rules, pack(Some("Li-ion"), Some("NVT"), Some("FRANGWA")));
+        assert_eq!(
+            (framework.rated_cycles, framework.end_of_life_percent),
+            (1000, 80.0)
+        );
+        assert_eq!(framework.source, "Framework");
+        let lfp = lookup(&rules, pack(Some("LiFe"), None, None));
+        assert_eq!((lfp.rated_cycles, lfp.end_of_life_percent), (3000, 70.0));
+        assert_eq!(
+            lfp.condition_for_health(65.0),
+            BatteryCondition::ServiceRecommended
+        );
+        assert_eq!(
+            lfp.condition_for_cycles(2500),
+            BatteryCondition::ServiceRecommended
+        );
+
+        let bad = RatingRule {
+            end_of_life_percent: 120.0,
+            ..rules[0].clone()
+        };
+        assert!(bad.validate().is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::battery::ChargingState;
+use crate::history::BatterySnapshot;
+
+/// Readings further apart than this differ in charge and temperature by
+/// more than the voltage step shows. A `*/10` cron lands a little over ten
+/// minutes apart, so a minute of slack keeps those pairs.
+const MAX_PAIR_SECONDS: i64 = 11 * 60;
+
+/// Open-circuit voltage falls as the pack discharges, which would read as
+/// resistance, so the level may only move this much between the readings.
+const MAX_LEVEL_STEP: u8 = 1;
+
+/// Smaller steps in current are lost in the voltage readings' resolution.
+const MIN_CURRENT_STEP_MA: f64 = 300.0;
+
+/// Anything outside this range, in mΩ, is a glitch rather than a pack.
+const MIN_PLAUSIBLE_MOHM: f64 = 10.0;
+const MAX_PLAUSIBLE_MOHM: f64 = 2000.0;
+
+/// The current estimate is taken from the samples of this many days up
+/// to the latest one, and so is the baseline from the first ones.
+const WINDOW_DAYS: i64 = 30;
+
+/// Fewest samples an estimate is made from.
+const MIN_SAMPLES: usize = 3;
+
+/// One estimate of a pack's DC internal resistance, from the voltage sag
+/// between two readings on battery at different loads.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResistanceSample {
+    pub battery: String,
+    /// The later of the two readings.
+    pub timestamp: DateTime<Utc>,
+    pub resistance_mohm: f64,
+    /// Difference in current between the readings, in mA.
+    pub current_step_ma: f64,
+    /// Resistance rises in the cold, so samples are best compared at
+    /// similar temperatures.
+    pub temperature_celsius: Option<f64>,
+}
+
+/// Current drawn from the pack in mA, from its power draw and voltage.
+fn discharge_current_ma(snap: &BatterySnapshot) -> Option<f64> {
+    let volts = snap.voltage_mv? / 1000.0;
+    let watts = snap.power_draw?;
+    (volts > 0.0).then(|| watts / volts * 1000.0)
+}
+
+/// Resistance from two readings of a pack taken minutes apart on battery,
+/// if the load changed enough between them: the voltage drop over the rise
+/// in current.
+pub fn sample_between(
+    before: &BatterySnapshot,
+    after: &BatterySnapshot,
+) -> Option<ResistanceSample> {
+    let discharging = |s: &BatterySnapshot| s.effective_state() == ChargingState::Discharging;
+    if !discharging(before) || !discharging(after) {
+        return None;
+    }
+    let seconds = after
+        .timestamp
+        .signed_duration_since(before.timestamp)
+        .num_seconds();
+    if seconds <= 0 || seconds > MAX_PAIR_SECONDS {
+        return None;
+    }
+    if before.level.abs_diff(after.level) > MAX_LEVEL_STEP {
+        return None;
+    }
+
+    let current_step = discharge_current_ma(after)? - discharge_current_ma(before)?;
+    if current_step.abs() < MIN_CURRENT_STEP_MA {
+        return None;
+    }
+    // mV per mA is ohms
+    let resistance_mohm = (before.voltage_mv? - after.voltage_mv?) / current_step * 1000.0;
+    if !(MIN_PLAUSIBLE_MOHM..=MAX_PLAUSIBLE_MOHM).contains(&resistance_mohm) {
+        return None;
+    }
+
+    Some(ResistanceSample {
+        battery: after.battery.clone().unwrap_or_default(),
+        timestamp: after.timestamp,
+        resistance_mohm,
+        current_step_ma: current_step.abs(),
+        temperature_celsius: after.temperature_celsius,
+    })
+}
+
+/// Median resistance over one stretch of time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResistancePoint {
+    /// Start of the stretch.
+    pub date: DateTime<Utc>,
+    pub resistance_mohm: f64,
+    pub samples: usize,
+}
+
+/// A pack's internal resistance now and how far it has risen.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InternalResistance {
+    /// Median of the samples from the 30 days up to the latest one.
+    pub resistance_mohm: f64,
+    pub samples: usize,
+    /// Median of the pack's first 30 days of samples, once there is more
+    /// history than the two windows cover.
+    pub baseline: Option<ResistancePoint>,
+    pub change_percent: Option<f64>,
+    /// One point per 30 days since the first sample, oldest first.
+    pub trend: Vec<ResistancePoint>,
+}
+
+/// Summarise a pack's samples, oldest first. `None` with too few recent
+/// samples to say anything.
+pub fn summarize(samples: &[ResistanceSample]) -> Option<InternalResistance> {
+    let (first, last) = (samples.first()?, samples.last()?);
+    let window = Duration::days(WINDOW_DAYS);
+    let point = |date: DateTime<Utc>, samples: Vec<f64>| {
+        (samples.len() >= MIN_SAMPLES).then(|| ResistancePoint {
+            date,
+            resistance_mohm: median(&samples),
+            samples: samples.len(),
+        })
+    };
+    let within = |from: DateTime<Utc>, to: DateTime<Utc>| -> Vec<f64> {
+        samples
+            .iter()
+            .filter(|s| s.timestamp >= from && s.timestamp <= to)
+            .map(|s| s.resistance_mohm)
+            .collect()
+    };
+
+    let recent = point(
+        last.timestamp - window,
+        within(last.timestamp - window, last.timestamp),
+    )?;
+    let baseline = (last.timestamp - first.timestamp > window * 2)
+        .then(|| {
+            point(
+                first.timestamp,
+                within(first.timestamp, first.timestamp + window),
+            )
+        })
+        .flatten();
+
+    let mut trend = Vec::new();
+    let mut from = first.timestamp;
+    while from <= last.timestamp {
+        let values = within(from, from + window - Duration::seconds(1));
+        if !values.is_empty() {
+            trend.push(ResistancePoint {
+                date: from,
+                resistance_mohm: median(&values),
+                samples: values.len(),
+            });
+        }
+        from += window;
+    }
+
+    Some(InternalResistance {
+        resistance_mohm: recent.resistance_mohm,
+        samples: recent.samples,
+        change_percent: baseline
+            .as_ref()
+            .map(|b| (recent.resistance_mohm / b.resistance_mohm - 1.0) * 100.0),
+        baseline,
+        trend,
+    })
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(second: i64, level: u8, watts: f64, millivolts: f64) -> BatterySnapshot {
+        BatterySnapshot {
+            timestamp: DateTime::from_timestamp(1_700_000_000 + second, 0).unwrap(),
+            battery: Some("BAT0".to_string()),
+            level,
+            state: Some(ChargingState::Discharging),
+            power_draw: Some(watts),
+            voltage_mv: Some(millivolts),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sample_between() {
+        // 0.5 A at 12.0 V, then 2.0 A with the voltage sagging to 11.85 V: 100 mΩ
+        let idle = reading(0, 80, 6.0, 12000.0);
+        let busy = reading(120, 80, 23.7, 11850.0);
+        let sample = sample_between(&idle, &busy).unwrap();
+        assert!(
+            (sample.resistance_mohm - 100.0).abs() < 1.0,
+            "{}",
+            sample.resistance_mohm
+        );
+        assert!((sample.current_step_ma - 1500.0).abs() < 1.0);
+        // The load can fall as well as rise
+        let idle_again = reading(240, 80, 6.0, 12000.0);
+        assert!((sample_between(&busy, &idle_again).unwrap().resistance_mohm - 100.0).abs() < 1.0);
+
+        // A */10 cron a second late still pairs up
+        let late = BatterySnapshot {
+            timestamp: idle.timestamp + Duration::seconds(601),
+            ..busy.clone()
+        };
+        assert!(sample_between(&idle, &late).is_some());
+
+        // Too little change in load, too far apart, or charging
+        assert!(sample_between(&idle, &reading(120, 80, 7.0, 11990.0)).is_none());
+        assert!(sample_between(&idle, &reading(3600, 79, 23.7, 11850.0)).is_none());
+        assert!(sample_between(&idle, &reading(120, 77, 23.7, 11850.0)).is_none());
+        let charging = BatterySnapshot {
+            state: Some(ChargingState::Charging),
+            ..busy.clone()
+        };
+        assert!(sample_between(&idle, &charging).is_none());
+        // A voltage that rose under more load is noise
+        assert!(sample_between(&idle, &reading(120, 80, 23.7, 12100.0)).is_none());
+    }
+
+    #[test]
+    fn test_summarize() {
+        let sample = |day: i64, mohm: f64| ResistanceSample {
+            battery: "BAT0".to_string(),
+            timestamp: DateTime::from_timestamp(1_700_000_000 + day * 86400, 0).unwrap(),
+            resistance_mohm: mohm,
+            current_step_ma: 1000.0,
+            temperature_celsius: None,
+        };
+        assert_eq!(summarize(&[sample(0, 100.0), sample(1, 110.0)]), None);
+
+        // 100 mΩ rising to 125 mΩ over four months, with a stray reading
+        let samples: Vec<ResistanceSample> = (0..120)
+            .map(|day| {
+                sample(
+                    day,
+                    if day == 60 {
+                        900.0
+                    } else {
+                        100.0 + day as f64 * 25.0 / 120.0
+                    },
+                )
+            })
+            .collect();
+        let summary = summarize(&samples).unwrap();
+        assert!(
+            (summary.resistance_mohm - 121.0).abs() < 1.0,
+            "{}",
+            summary.resistance_mohm
+        );
+        assert_eq!(summary.samples, 31);
+        let baseline = summary.baseline.unwrap();
+        assert!((baseline.resistance_mohm - 103.0).abs() < 1.0);
+        assert!((summary.change_percent.unwrap() - 17.5).abs() < 1.5);
+        assert_eq!(summary.trend.len(), 4);
+        assert!(summary
+            .trend
+            .windows(2)
+            .all(|w| w[0].resistance_mohm < w[1].resistance_mohm));
+
+        // A month of samples has no baseline yet
+        assert_eq!(summarize(&samples[..30]).unwrap().baseline, None);
+    }
+}
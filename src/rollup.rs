@@ -0,0 +1,387 @@
+use crate::battery::{CapacityUnit, ChargingState};
+use crate::config::RetentionPolicy;
+use crate::health;
+use crate::history::BatterySnapshot;
+use crate::sleep;
+use chrono::{DateTime, Duration};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Resolution of the rows a history query reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    /// Every recorded snapshot.
+    #[default]
+    Raw,
+    /// One aggregate row per pack per hour.
+    Hourly,
+    /// One aggregate row per pack per day (UTC).
+    Daily,
+}
+
+impl Granularity {
+    /// The coarsest granularity that still gives a useful chart for a period
+    /// `span` long that starts `age` ago, moving to a coarser one when
+    /// `retention` has already dropped the finer rows for part of the period.
+    pub fn for_range(span: Duration, age: Duration, retention: &RetentionPolicy) -> Self {
+        let kept = |days: u32| age <= Duration::days(days as i64);
+        if span <= Duration::days(2) && kept(retention.raw_days) {
+            Granularity::Raw
+        } else if span <= Duration::days(90) && kept(retention.hourly_days) {
+            Granularity::Hourly
+        } else {
+            Granularity::Daily
+        }
+    }
+
+    /// Bucket length in seconds (1 for raw rows).
+    pub fn bucket_secs(&self) -> i64 {
+        match self {
+            Granularity::Raw => 1,
+            Granularity::Hourly => 3600,
+            Granularity::Daily => 86400,
+        }
+    }
+
+    /// Start of the bucket containing `timestamp`.
+    pub fn bucket_start(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.bucket_secs())
+    }
+
+    /// Table holding this granularity's rollups.
+    pub fn table(&self) -> Option<&'static str> {
+        match self {
+            Granularity::Raw => None,
+            Granularity::Hourly => Some("rollups_hourly"),
+            Granularity::Daily => Some("rollups_daily"),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Raw => "raw",
+            Granularity::Hourly => "hourly",
+            Granularity::Daily => "daily",
+        }
+    }
+}
+
+/// Aggregate of one pack's snapshots over one bucket.
+///
+/// Interval statistics (minutes per state, level and energy changes) cover
+/// the span from each snapshot's predecessor to the snapshot, so they sum
+/// across buckets to the same totals as the raw rows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rollup {
+    pub bucket: i64,
+    /// Pack name; empty for rows recorded before packs were tracked.
+    pub battery: String,
+    pub samples: u32,
+    pub min_level: u8,
+    pub max_level: u8,
+    pub avg_level: f64,
+    /// Sum of level changes (in percent) between consecutive readings on battery.
+    pub level_discharged: f64,
+    pub charging_periods: u32,
+    pub charging_minutes: i64,
+    pub discharging_minutes: i64,
+    pub not_charging_minutes: i64,
+    /// Time in suspend gaps, which counts towards none of the states above.
+    pub sleep_minutes: i64,
+    pub energy_in_mwh: f64,
+    pub energy_out_mwh: f64,
+    pub avg_power: Option<f64>,
+    pub avg_discharge_power: Option<f64>,
+    pub discharge_power_samples: u32,
+    pub avg_temperature: Option<f64>,
+    pub max_temperature: Option<f64>,
+    pub temperature_samples: u32,
+    /// Last reading in the bucket, for capacity trends.
+    pub state: Option<ChargingState>,
+    pub is_charging: bool,
+    pub cycle_count: Option<u32>,
+    pub max_capacity: Option<u32>,
+    pub design_capacity: Option<u32>,
+    pub max_energy: Option<u32>,
+    pub design_energy: Option<u32>,
+    pub capacity_unit: Option<CapacityUnit>,
+    /// Quartiles of the bucket's health readings (see
+    /// `BatterySnapshot::health_percent`) and how many there were. Rollups
+    /// written before these were kept have none.
+    pub health_p25: Option<f64>,
+    pub health_median: Option<f64>,
+    pub health_p75: Option<f64>,
+    pub health_samples: u32,
+}
+
+impl Rollup {
+    /// Represent the bucket as a snapshot, for charts and exports.
+    pub fn to_snapshot(&self) -> BatterySnapshot {
+        BatterySnapshot {
+            timestamp: DateTime::from_timestamp(self.bucket, 0).unwrap_or_default(),
+            battery: (!self.battery.is_empty()).then(|| self.battery.clone()),
+            level: self.avg_level.round().clamp(0.0, 100.0) as u8,
+            is_charging: self.is_charging,
+            state: self.state,
+            power_draw: self.avg_power,
+            cycle_count: self.cycle_count,
+            max_capacity: self.max_capacity,
+            design_capacity: self.design_capacity,
+            max_energy: self.max_energy,
+            design_energy: self.design_energy,
+            capacity_unit: self.capacity_unit,
+            temperature_celsius: self.avg_temperature,
+            ..Default::default()
+        }
+    }
+}
+
+/// Running sums for one bucket.
+#[derive(Default)]
+struct Accumulator {
+    rollup: Rollup,
+    level_sum: f64,
+    power_sum: f64,
+    power_samples: u32,
+    discharge_power_sum: f64,
+    temperature_sum: f64,
+    health: Vec<f64>,
+}
+
+impl Accumulator {
+    fn add(&mut self, snap: &BatterySnapshot, previous: Option<&BatterySnapshot>) {
+        let r = &mut self.rollup;
+        if r.samples == 0 {
+            r.min_level = snap.level;
+            r.max_level = snap.level;
+        }
+        r.samples += 1;
+        r.min_level = r.min_level.min(snap.level);
+        r.max_level = r.max_level.max(snap.level);
+        self.level_sum += snap.level as f64;
+
+        if let Some(power) = snap.power_draw {
+            self.power_sum += power;
+            self.power_samples += 1;
+            if !snap.is_charging {
+                self.discharge_power_sum += power;
+                r.discharge_power_samples += 1;
+            }
+        }
+        if let Some(temp) = snap.temperature_celsius {
+            self.temperature_sum += temp;
+            r.temperature_samples += 1;
+            r.max_temperature = Some(r.max_temperature.map_or(temp, |m| m.max(temp)));
+        }
+        if let Some(health) = snap.health_percent() {
+            self.health.push(health);
+        }
+
+        let was_charging = previous.is_some_and(|p| p.is_charging);
+        if snap.is_charging && !was_charging {
+            r.charging_periods += 1;
+        }
+
+        if let Some(prev) = previous {
+            let dt = snap
+                .timestamp
+                .signed_duration_since(prev.timestamp)
+                .num_minutes();
+            if sleep::is_sleep_gap(prev, snap) {
+                r.sleep_minutes += dt;
+            } else {
+                match prev.effective_state() {
+                    ChargingState::Charging => r.charging_minutes += dt,
+                    ChargingState::Full | ChargingState::NotCharging => {
+                        r.not_charging_minutes += dt
+                    }
+                    _ => r.discharging_minutes += dt,
+                }
+            }
+            if !snap.is_charging && !prev.is_charging {
+                r.level_discharged += (snap.level as f64 - prev.level as f64).abs();
+            }
+            if let (Some(before), Some(after)) = (stored_energy(prev), stored_energy(snap)) {
+                let delta = after - before;
+                if delta > 0.0 {
+                    r.energy_in_mwh += delta;
+                } else {
+                    r.energy_out_mwh -= delta;
+                }
+            }
+        }
+
+        r.state = snap.state;
+        r.is_charging = snap.is_charging;
+        r.cycle_count = snap.cycle_count.or(r.cycle_count);
+        r.max_capacity = snap.max_capacity.or(r.max_capacity);
+        r.design_capacity = snap.design_capacity.or(r.design_capacity);
+        r.max_energy = snap.max_energy.or(r.max_energy);
+        r.design_energy = snap.design_energy.or(r.design_energy);
+        r.capacity_unit = snap.capacity_unit.or(r.capacity_unit);
+    }
+
+    fn finish(mut self) -> Rollup {
+        let r = &mut self.rollup;
+        r.avg_level = self.level_sum / r.samples as f64;
+        r.avg_power = (self.power_samples > 0).then(|| self.power_sum / self.power_samples as f64);
+        r.avg_discharge_power = (r.discharge_power_samples > 0)
+            .then(|| self.discharge_power_sum / r.discharge_power_samples as f64);
+        r.avg_temperature = (r.temperature_samples > 0)
+            .then(|| self.temperature_sum / r.temperature_samples as f64);
+        if !self.health.is_empty() {
+            self.health.sort_by(f64::total_cmp);
+            r.health_p25 = Some(health::percentile(&self.health, 25.0));
+            r.health_median = Some(health::percentile(&self.health, 50.0));
+            r.health_p75 = Some(health::percentile(&self.health, 75.0));
+            r.health_samples = self.health.len() as u32;
+        }
+        self.rollup
+    }
+}
+
+/// Energy held by the battery at a snapshot, in mWh: the measured value, or
+/// the level applied to the full-charge energy.
+pub fn stored_energy(snap: &BatterySnapshot) -> Option<f64> {
+    snap.current_energy.map(|e| e as f64).or_else(|| {
+        let full = snap.max_energy?;
+        Some(full as f64 * snap.level as f64 / 100.0)
+    })
+}
+
+/// Aggregate `snapshots` into buckets starting at `from_bucket` or later.
+///
+/// Snapshots before `from_bucket` only serve as predecessors for interval
+/// statistics. Rows are grouped by pack, so the input may mix packs.
+pub fn compute_rollups(
+    snapshots: &[BatterySnapshot],
+    granularity: Granularity,
+    from_bucket: i64,
+) -> Vec<Rollup> {
+    let mut by_battery: BTreeMap<&str, Vec<&BatterySnapshot>> = BTreeMap::new();
+    for snap in snapshots {
+        by_battery
+            .entry(snap.battery.as_deref().unwrap_or(""))
+            .or_default()
+            .push(snap);
+    }
+
+    let mut rollups = Vec::new();
+    for (battery, mut rows) in by_battery {
+        rows.sort_by_key(|s| s.timestamp);
+
+        let mut buckets: BTreeMap<i64, Accumulator> = BTreeMap::new();
+        let mut previous: Option<&BatterySnapshot> = None;
+        for snap in rows {
+            let bucket = granularity.bucket_start(snap.timestamp.timestamp());
+            if bucket >= from_bucket {
+                let acc = buckets.entry(bucket).or_insert_with(|| Accumulator {
+                    rollup: Rollup {
+                        bucket,
+                        battery: battery.to_string(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+                acc.add(snap, previous);
+            }
+            previous = Some(snap);
+        }
+        rollups.extend(buckets.into_values().map(Accumulator::finish));
+    }
+    rollups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snap(ts: i64, level: u8, state: ChargingState, power: f64) -> BatterySnapshot {
+        BatterySnapshot {
+            timestamp: DateTime::from_timestamp(ts, 0).unwrap(),
+            battery: Some("BAT0".to_string()),
+            level,
+            is_charging: matches!(state, ChargingState::Charging | ChargingState::Full),
+            state: Some(state),
+            power_draw: Some(power),
+            max_energy: Some(50000),
+            temperature_celsius: Some(30.0 + level as f64 / 10.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_granularity_for_range() {
+        let policy = RetentionPolicy::default();
+        let pick = |d| Granularity::for_range(d, d, &policy);
+        assert_eq!(pick(Duration::hours(24)), Granularity::Raw);
+        assert_eq!(pick(Duration::days(30)), Granularity::Hourly);
+        assert_eq!(pick(Duration::days(180)), Granularity::Daily);
+
+        // A short period too old for the raw rows reads the hourly ones
+        assert_eq!(
+            Granularity::for_range(Duration::days(1), Duration::days(60), &policy),
+            Granularity::Hourly
+        );
+
+        let short = RetentionPolicy {
+            hourly_days: 14,
+            ..policy
+        };
+        assert_eq!(
+            Granularity::for_range(Duration::days(30), Duration::days(30), &short),
+            Granularity::Daily
+        );
+        assert_eq!(Granularity::Hourly.bucket_start(7250), 7200);
+    }
+
+    #[test]
+    fn test_compute_hourly_rollups() {
+        let base = 1_700_000_000 - 1_700_000_000 % 3600;
+        let rows = vec![
+            snap(base + 600, 80, ChargingState::Discharging, 10.0),
+            snap(base + 1800, 70, ChargingState::Discharging, 12.0),
+            snap(base + 3600 + 600, 60, ChargingState::Discharging, 8.0),
+            snap(base + 3600 + 1800, 65, ChargingState::Charging, -20.0),
+        ];
+        let rollups = compute_rollups(&rows, Granularity::Hourly, base);
+        assert_eq!(rollups.len(), 2);
+
+        let first = &rollups[0];
+        assert_eq!(first.bucket, base);
+        assert_eq!(first.samples, 2);
+        assert_eq!((first.min_level, first.max_level), (70, 80));
+        assert_eq!(first.avg_level, 75.0);
+        assert_eq!(first.discharging_minutes, 20);
+        assert_eq!(first.level_discharged, 10.0);
+        assert_eq!(first.energy_out_mwh, 5000.0);
+        assert_eq!(first.avg_discharge_power, Some(11.0));
+
+        // The second bucket picks up the interval from the first bucket's last row
+        let second = &rollups[1];
+        assert_eq!(second.discharging_minutes, 60);
+        assert_eq!(second.level_discharged, 10.0);
+        assert_eq!(second.energy_in_mwh, 2500.0);
+        assert_eq!(second.energy_out_mwh, 5000.0);
+        assert_eq!(second.charging_periods, 1);
+        assert_eq!(second.max_temperature, Some(36.5));
+
+        // Rows before from_bucket only act as predecessors
+        let later = compute_rollups(&rows, Granularity::Hourly, base + 3600);
+        assert_eq!(later.len(), 1);
+        assert_eq!(later[0], rollups[1]);
+    }
+
+    #[test]
+    fn test_rollups_are_grouped_by_pack() {
+        let mut other = snap(100, 50, ChargingState::Discharging, 5.0);
+        other.battery = None;
+        let rows = vec![snap(200, 80, ChargingState::Discharging, 10.0), other];
+        let rollups = compute_rollups(&rows, Granularity::Daily, 0);
+        assert_eq!(rollups.len(), 2);
+        assert_eq!(rollups[0].battery, "");
+        assert_eq!(rollups[1].battery, "BAT0");
+        assert_eq!(rollups[0].to_snapshot().battery, None);
+    }
+}
@@ -0,0 +1,214 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::battery::ChargingState;
+use crate::history::BatterySnapshot;
+use crate::rollup::stored_energy;
+use crate::sleep::is_sleep_gap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionKind {
+    Charge,
+    Discharge,
+}
+
+impl SessionKind {
+    /// Session kind a reading belongs to; `None` on AC without charging.
+    fn of(snap: &BatterySnapshot) -> Option<Self> {
+        match snap.effective_state() {
+            ChargingState::Charging => Some(SessionKind::Charge),
+            ChargingState::Full | ChargingState::NotCharging => None,
+            _ => Some(SessionKind::Discharge),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionKind::Charge => "charge",
+            SessionKind::Discharge => "discharge",
+        }
+    }
+}
+
+/// An uninterrupted stretch of charging or of running on battery.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub kind: SessionKind,
+    pub battery: Option<String>,
+    pub start: DateTime<Utc>,
+    /// The first reading after the session (plugged in, unplugged, full),
+    /// or its last reading when recording stopped.
+    pub end: DateTime<Utc>,
+    pub start_level: u8,
+    pub end_level: u8,
+    pub duration_minutes: i64,
+    /// Energy charged or drawn, from the stored energy at either end, or
+    /// from the power readings when that is unknown.
+    pub energy_mwh: Option<f64>,
+    pub avg_power_watts: Option<f64>,
+    pub peak_power_watts: Option<f64>,
+    pub avg_temperature_celsius: Option<f64>,
+    pub max_temperature_celsius: Option<f64>,
+    pub samples: usize,
+}
+
+/// Split one pack's snapshots, oldest first, into charge and discharge
+/// sessions. Time on AC without charging belongs to neither, and a suspend
+/// gap ends a session.
+pub fn detect_sessions(snapshots: &[BatterySnapshot]) -> Vec<Session> {
+    let mut sessions = Vec::new();
+    let mut start = 0;
+    while start < snapshots.len() {
+        let Some(kind) = SessionKind::of(&snapshots[start]) else {
+            start += 1;
+            continue;
+        };
+
+        let mut last = start;
+        while let Some(next) = snapshots.get(last + 1) {
+            if is_sleep_gap(&snapshots[last], next) || SessionKind::of(next) != Some(kind) {
+                break;
+            }
+            last += 1;
+        }
+
+        // The interval up to the next reading still belongs to the session
+        let end = match snapshots.get(last + 1) {
+            Some(next) if !is_sleep_gap(&snapshots[last], next) => last + 1,
+            _ => last,
+        };
+        if end > start {
+            sessions.push(build_session(
+                kind,
+                &snapshots[start..=end],
+                &snapshots[start..=last],
+            ));
+        }
+        start = last + 1;
+    }
+    sessions
+}
+
+fn gap_minutes(a: &BatterySnapshot, b: &BatterySnapshot) -> i64 {
+    b.timestamp.signed_duration_since(a.timestamp).num_minutes()
+}
+
+/// `span` runs from the first reading to the end of the session; `rows`
+/// are the readings taken during it.
+fn build_session(kind: SessionKind, span: &[BatterySnapshot], rows: &[BatterySnapshot]) -> Session {
+    let first = &span[0];
+    let last = &span[span.len() - 1];
+
+    let powers: Vec<f64> = rows.iter().filter_map(|s| s.power_draw).collect();
+    let temperatures: Vec<f64> = rows.iter().filter_map(|s| s.temperature_celsius).collect();
+    let average = |values: &[f64]| {
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    };
+    let peak = |values: &[f64]| values.iter().copied().reduce(f64::max);
+
+    let energy_mwh = match (stored_energy(first), stored_energy(last)) {
+        (Some(before), Some(after)) => Some((after - before).abs()),
+        _ => {
+            // Watts over each interval, in mWh
+            let integrated: Option<f64> = span
+                .windows(2)
+                .map(|w| {
+                    let hours = gap_minutes(&w[0], &w[1]) as f64 / 60.0;
+                    w[0].power_draw.map(|p| p * hours * 1000.0)
+                })
+                .sum();
+            integrated
+        }
+    };
+
+    Session {
+        kind,
+        battery: first.battery.clone(),
+        start: first.timestamp,
+        end: last.timestamp,
+        start_level: first.level,
+        end_level: last.level,
+        duration_minutes: (last.timestamp - first.timestamp).num_minutes(),
+        energy_mwh,
+        avg_power_watts: average(&powers),
+        peak_power_watts: peak(&powers),
+        avg_temperature_celsius: average(&temperatures),
+        max_temperature_celsius: peak(&temperatures),
+        samples: rows.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(minute: i64, level: u8, state: ChargingState, power: f64) -> BatterySnapshot {
+        BatterySnapshot {
+            timestamp: DateTime::from_timestamp(1_700_000_000 + minute * 60, 0).unwrap(),
+            battery: Some("BAT0".to_string()),
+            level,
+            is_charging: matches!(state, ChargingState::Charging | ChargingState::Full),
+            state: Some(state),
+            power_draw: Some(power),
+            max_energy: Some(50000),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sessions_split_on_state_changes() {
+        use ChargingState::*;
+        let rows = vec![
+            reading(0, 90, Discharging, 8.0),
+            reading(30, 80, Discharging, 12.0),
+            reading(60, 70, Discharging, 10.0),
+            reading(90, 71, Charging, 40.0),
+            reading(120, 90, Charging, 30.0),
+            reading(150, 100, Full, 0.0),
+            reading(180, 100, Full, 0.0),
+            reading(210, 98, Discharging, 9.0),
+            reading(240, 95, Discharging, 9.0),
+        ];
+        let sessions = detect_sessions(&rows);
+        assert_eq!(sessions.len(), 3);
+
+        let discharge = &sessions[0];
+        assert_eq!(discharge.kind, SessionKind::Discharge);
+        // Ends when the charger is plugged in
+        assert_eq!((discharge.start_level, discharge.end_level), (90, 71));
+        assert_eq!(discharge.duration_minutes, 90);
+        assert_eq!(discharge.samples, 3);
+        assert_eq!(discharge.energy_mwh, Some(9500.0));
+        assert_eq!(discharge.avg_power_watts, Some(10.0));
+        assert_eq!(discharge.peak_power_watts, Some(12.0));
+
+        let charge = &sessions[1];
+        assert_eq!(charge.kind, SessionKind::Charge);
+        assert_eq!((charge.start_level, charge.end_level), (71, 100));
+        assert_eq!(charge.duration_minutes, 60);
+
+        // Still running when recording stopped
+        assert_eq!(sessions[2].duration_minutes, 30);
+        assert_eq!(sessions[2].end_level, 95);
+    }
+
+    #[test]
+    fn test_sessions_split_on_gaps() {
+        use ChargingState::*;
+        let mut rows = vec![
+            reading(0, 90, Discharging, 8.0),
+            reading(30, 85, Discharging, 8.0),
+            reading(600, 60, Discharging, 8.0),
+            reading(630, 55, Discharging, 8.0),
+        ];
+        rows[0].max_energy = None;
+        rows[1].max_energy = None;
+        let sessions = detect_sessions(&rows);
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].end_level, 85);
+        // No stored energy, so 8 W for half an hour
+        assert_eq!(sessions[0].energy_mwh, Some(4000.0));
+        assert!(detect_sessions(&rows[..1]).is_empty());
+    }
+}
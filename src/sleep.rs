@@ -0,0 +1,219 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::history::{BatteryEvent, BatterySnapshot};
+
+/// Event kind recorded by `batteryctl hook pre-sleep`.
+pub const EVENT_SLEEP: &str = "sleep";
+
+/// Event kind recorded by `batteryctl hook post-sleep`.
+pub const EVENT_WAKE: &str = "wake";
+
+/// Readings more than this far apart span a suspend (or the machine being
+/// off) rather than time in use.
+pub const SLEEP_GAP_MINUTES: i64 = 60;
+
+/// Drain while asleep above this, in percent per hour, usually means the
+/// machine never reached a deep sleep state (e.g. s2idle stuck awake).
+pub const ABNORMAL_DRAIN_PER_HOUR: f64 = 1.0;
+
+/// Shorter sleeps are never flagged: one percent lost over half an hour
+/// already reads as 2%/h.
+pub const ABNORMAL_MIN_MINUTES: i64 = 120;
+
+/// Whether the interval between two readings is a suspend gap, which does
+/// not count as time charging or on battery.
+pub fn is_sleep_gap(before: &BatterySnapshot, after: &BatterySnapshot) -> bool {
+    after
+        .timestamp
+        .signed_duration_since(before.timestamp)
+        .num_minutes()
+        > SLEEP_GAP_MINUTES
+}
+
+/// How a sleep was detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SleepSource {
+    /// Bracketed by the `pre-sleep` and `post-sleep` hooks.
+    Hook,
+    /// A gap in the readings; the machine may also have been off.
+    Gap,
+}
+
+/// One stretch asleep on battery.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SleepPeriod {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub minutes: i64,
+    pub level_before: u8,
+    pub level_after: u8,
+    /// Percent lost while asleep.
+    pub drain_percent: f64,
+    pub drain_per_hour: f64,
+    pub source: SleepSource,
+    /// Drained faster than `ABNORMAL_DRAIN_PER_HOUR` over a long enough sleep.
+    pub abnormal: bool,
+}
+
+/// Sleep periods and the drain over all of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SleepReport {
+    pub periods: Vec<SleepPeriod>,
+    pub total_minutes: i64,
+    pub total_drain_percent: f64,
+    pub avg_drain_per_hour: Option<f64>,
+    pub abnormal: usize,
+}
+
+/// Find the sleeps on battery in one pack's snapshots, oldest first.
+///
+/// A sleep is either the span between a `sleep` and a `wake` event, or a
+/// gap of more than `SLEEP_GAP_MINUTES` between readings. Sleeps with
+/// the charger connected on either side are skipped, as their drain says
+/// nothing.
+pub fn detect_sleep(snapshots: &[BatterySnapshot], events: &[BatteryEvent]) -> Vec<SleepPeriod> {
+    let at = |kind: &str, from: DateTime<Utc>, to: DateTime<Utc>| {
+        events
+            .iter()
+            .filter(|e| e.kind == kind && e.timestamp >= from && e.timestamp <= to)
+            .map(|e| e.timestamp)
+            .next()
+    };
+
+    snapshots
+        .windows(2)
+        .filter_map(|pair| {
+            let (before, after) = (&pair[0], &pair[1]);
+            let hooked = match (
+                at(EVENT_SLEEP, before.timestamp, after.timestamp),
+                at(EVENT_WAKE, before.timestamp, after.timestamp),
+            ) {
+                (Some(sleep), Some(wake)) => sleep <= wake,
+                _ => false,
+            };
+            if !hooked && !is_sleep_gap(before, after) {
+                return None;
+            }
+            if before.is_charging || after.is_charging {
+                return None;
+            }
+
+            let minutes = after
+                .timestamp
+                .signed_duration_since(before.timestamp)
+                .num_minutes();
+            if minutes <= 0 {
+                return None;
+            }
+            let drain_percent = before.level as f64 - after.level as f64;
+            let drain_per_hour = drain_percent / (minutes as f64 / 60.0);
+            Some(SleepPeriod {
+                start: before.timestamp,
+                end: after.timestamp,
+                minutes,
+                level_before: before.level,
+                level_after: after.level,
+                drain_percent,
+                drain_per_hour,
+                source: if hooked {
+                    SleepSource::Hook
+                } else {
+                    SleepSource::Gap
+                },
+                abnormal: minutes >= ABNORMAL_MIN_MINUTES
+                    && drain_per_hour > ABNORMAL_DRAIN_PER_HOUR,
+            })
+        })
+        .collect()
+}
+
+pub fn sleep_report(periods: Vec<SleepPeriod>) -> SleepReport {
+    let total_minutes: i64 = periods.iter().map(|p| p.minutes).sum();
+    let total_drain_percent: f64 = periods.iter().map(|p| p.drain_percent).sum();
+    SleepReport {
+        total_minutes,
+        total_drain_percent,
+        avg_drain_per_hour: (total_minutes > 0)
+            .then(|| total_drain_percent / (total_minutes as f64 / 60.0)),
+        abnormal: periods.iter().filter(|p| p.abnormal).count(),
+        periods,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::ChargingState;
+
+    fn reading(minute: i64, level: u8, charging: bool) -> BatterySnapshot {
+        BatterySnapshot {
+            timestamp: DateTime::from_timestamp(1_700_000_000 + minute * 60, 0).unwrap(),
+            battery: Some("BAT0".to_string()),
+            level,
+            is_charging: charging,
+            state: Some(if charging {
+                ChargingState::Charging
+            } else {
+                ChargingState::Discharging
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn event(minute: i64, kind: &str) -> BatteryEvent {
+        BatteryEvent {
+            id: 0,
+            timestamp: DateTime::from_timestamp(1_700_000_000 + minute * 60, 0).unwrap(),
+            battery: "BAT0".to_string(),
+            kind: kind.to_string(),
+            serial_number: None,
+            model_name: None,
+            detail: None,
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_overnight_gap_is_abnormal_drain() {
+        let rows = vec![
+            reading(0, 80, false),
+            reading(10, 79, false),
+            // Eight hours asleep, 16% lost
+            reading(490, 63, false),
+            reading(500, 62, false),
+            // Asleep on the charger
+            reading(900, 90, true),
+        ];
+        let periods = detect_sleep(&rows, &[]);
+        assert_eq!(periods.len(), 1);
+        let night = &periods[0];
+        assert_eq!(night.source, SleepSource::Gap);
+        assert_eq!(night.minutes, 480);
+        assert_eq!(night.drain_percent, 16.0);
+        assert_eq!(night.drain_per_hour, 2.0);
+        assert!(night.abnormal);
+
+        let report = sleep_report(periods);
+        assert_eq!(report.abnormal, 1);
+        assert_eq!(report.avg_drain_per_hour, Some(2.0));
+    }
+
+    #[test]
+    fn test_hooks_mark_short_sleeps() {
+        let rows = vec![
+            reading(0, 50, false),
+            reading(20, 50, false),
+            reading(25, 49, false),
+        ];
+        let events = vec![event(0, EVENT_SLEEP), event(20, EVENT_WAKE)];
+        let periods = detect_sleep(&rows, &events);
+        assert_eq!(periods.len(), 1);
+        assert_eq!(periods[0].source, SleepSource::Hook);
+        assert_eq!(periods[0].drain_percent, 0.0);
+        assert!(!periods[0].abnormal);
+
+        assert!(detect_sleep(&rows, &[]).is_empty());
+    }
+}
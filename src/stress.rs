@@ -0,0 +1,359 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::history::BatterySnapshot;
+use crate::sessions::{self, SessionKind};
+use crate::sleep::is_sleep_gap;
+
+/// Charge levels, in percent, above and below which a pack ages faster.
+pub const HIGH_CHARGE: u8 = 90;
+pub const LOW_CHARGE: u8 = 10;
+
+/// Pack temperatures, in °C, above which it ages faster, and much faster.
+pub const WARM_CELSIUS: f64 = 35.0;
+pub const HOT_CELSIUS: f64 = 40.0;
+
+/// An interval longer than this says nothing about the charge in between;
+/// shorter gaps count, since a sleeping laptop still sits at its charge.
+const MAX_INTERVAL_HOURS: f64 = 24.0;
+
+/// Discharges shallower than this, in points, are unplugging to move the
+/// laptop rather than cycles.
+const MIN_DISCHARGE_DEPTH: u8 = 5;
+
+/// How much each factor counts towards the index.
+const HIGH_CHARGE_WEIGHT: f64 = 0.30;
+const HEAT_WEIGHT: f64 = 0.25;
+const LOW_CHARGE_WEIGHT: f64 = 0.15;
+const DEPTH_WEIGHT: f64 = 0.15;
+const CHARGE_RATE_WEIGHT: f64 = 0.15;
+
+/// One habit's share of the stress on a pack.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StressFactor {
+    /// `high_charge`, `heat`, `low_charge`, `depth` or `charge_rate`.
+    pub factor: String,
+    pub label: String,
+    /// The measured value, e.g. "38% of the time".
+    pub measured: String,
+    /// 0 (no stress) to 100.
+    pub score: f64,
+    pub weight: f64,
+    /// Points this factor adds to the index.
+    pub contribution: f64,
+    pub advice: String,
+}
+
+/// How hard a pack has been used over a period, from its raw readings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StressReport {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Time the readings cover, in hours.
+    pub hours: f64,
+    /// Shares of the covered time, in percent.
+    pub high_charge_percent: f64,
+    pub low_charge_percent: f64,
+    /// Shares of the time awake with a temperature reading, in percent.
+    pub warm_percent: Option<f64>,
+    pub hot_percent: Option<f64>,
+    /// Mean depth of the discharges of at least 5 points, in points.
+    pub avg_depth_of_discharge: Option<f64>,
+    pub discharges: usize,
+    pub avg_charging_power_watts: Option<f64>,
+    /// Charging power over the full-charge energy, in multiples of C.
+    pub avg_charge_rate: Option<f64>,
+    /// Weighted mean of the factor scores, 0 to 100.
+    pub index: f64,
+    pub level: String,
+    pub factors: Vec<StressFactor>,
+    /// The factor adding the most to the index, if any adds to it.
+    pub costliest: Option<String>,
+}
+
+/// Time in each charge and temperature band over the intervals between
+/// readings, oldest first.
+#[derive(Default)]
+struct Exposure {
+    hours: f64,
+    high_charge: f64,
+    low_charge: f64,
+    temperature_hours: f64,
+    warm: f64,
+    hot: f64,
+}
+
+fn exposure(snapshots: &[BatterySnapshot]) -> Exposure {
+    let mut exposure = Exposure::default();
+    for pair in snapshots.windows(2) {
+        let (before, after) = (&pair[0], &pair[1]);
+        let hours = after
+            .timestamp
+            .signed_duration_since(before.timestamp)
+            .num_seconds() as f64
+            / 3600.0;
+        if hours <= 0.0 || hours > MAX_INTERVAL_HOURS {
+            continue;
+        }
+        exposure.hours += hours;
+        if before.level > HIGH_CHARGE {
+            exposure.high_charge += hours;
+        }
+        if before.level < LOW_CHARGE {
+            exposure.low_charge += hours;
+        }
+        // Asleep, the pack cools down to the room
+        if let Some(celsius) = before
+            .temperature_celsius
+            .filter(|_| !is_sleep_gap(before, after))
+        {
+            exposure.temperature_hours += hours;
+            if celsius > WARM_CELSIUS {
+                exposure.warm += hours;
+            }
+            if celsius > HOT_CELSIUS {
+                exposure.hot += hours;
+            }
+        }
+    }
+    exposure
+}
+
+/// `value` on a 0–100 scale running from `zero_at` to `max_at`.
+fn score(value: f64, zero_at: f64, max_at: f64) -> f64 {
+    ((value - zero_at) / (max_at - zero_at)).clamp(0.0, 1.0) * 100.0
+}
+
+/// Analyse one pack's raw readings, oldest first. `None` if they cover no
+/// time.
+///
+/// Each factor scores 0 to 100 (see the README for the scales), and the
+/// index is their weighted mean; factors without data are left out.
+pub fn analyze(snapshots: &[BatterySnapshot]) -> Option<StressReport> {
+    let (first, last) = (snapshots.first()?, snapshots.last()?);
+    let exposure = exposure(snapshots);
+    if exposure.hours <= 0.0 {
+        return None;
+    }
+    let share = |hours: f64, of: f64| hours / of * 100.0;
+    let high_charge_percent = share(exposure.high_charge, exposure.hours);
+    let low_charge_percent = share(exposure.low_charge, exposure.hours);
+    let (warm_percent, hot_percent) = match exposure.temperature_hours {
+        t if t > 0.0 => (Some(share(exposure.warm, t)), Some(share(exposure.hot, t))),
+        _ => (None, None),
+    };
+
+    let sessions = sessions::detect_sessions(snapshots);
+    let depths: Vec<f64> = sessions
+        .iter()
+        .filter(|s| s.kind == SessionKind::Discharge)
+        .map(|s| s.start_level.saturating_sub(s.end_level))
+        .filter(|&depth| depth >= MIN_DISCHARGE_DEPTH)
+        .map(f64::from)
+        .collect();
+    let avg_depth_of_discharge =
+        (!depths.is_empty()).then(|| depths.iter().sum::<f64>() / depths.len() as f64);
+
+    // Weighted by how long each charge lasted
+    let (watt_minutes, minutes) = sessions
+        .iter()
+        .filter(|s| s.kind == SessionKind::Charge && s.duration_minutes > 0)
+        .filter_map(|s| {
+            Some((
+                s.avg_power_watts? * s.duration_minutes as f64,
+                s.duration_minutes as f64,
+            ))
+        })
+        .fold((0.0, 0.0), |(w, m), (sw, sm)| (w + sw, m + sm));
+    let avg_charging_power_watts = (minutes > 0.0).then(|| watt_minutes / minutes);
+    let full_wh = last
+        .max_energy
+        .filter(|&e| e > 0)
+        .map(|e| e as f64 / 1000.0);
+    let avg_charge_rate = avg_charging_power_watts
+        .zip(full_wh)
+        .map(|(watts, wh)| watts / wh);
+
+    let mut factors = vec![
+        StressFactor {
+            factor: "high_charge".to_string(),
+            label: format!("Above {}%", HIGH_CHARGE),
+            measured: format!("{:.0}% of the time", high_charge_percent),
+            score: score(high_charge_percent, 0.0, 50.0),
+            weight: HIGH_CHARGE_WEIGHT,
+            contribution: 0.0,
+            advice:
+                "Set a charge limit (batteryctl limit --stop 80) on a laptop that stays plugged in."
+                    .to_string(),
+        },
+        StressFactor {
+            factor: "low_charge".to_string(),
+            label: format!("Below {}%", LOW_CHARGE),
+            measured: format!("{:.0}% of the time", low_charge_percent),
+            score: score(low_charge_percent, 0.0, 10.0),
+            weight: LOW_CHARGE_WEIGHT,
+            contribution: 0.0,
+            advice: "Plug in before the level drops below 20%.".to_string(),
+        },
+    ];
+    if let (Some(warm), Some(hot)) = (warm_percent, hot_percent) {
+        factors.push(StressFactor {
+            factor: "heat".to_string(),
+            label: format!("Above {:.0}/{:.0} °C", WARM_CELSIUS, HOT_CELSIUS),
+            measured: format!("{:.0}% / {:.0}% of the time", warm, hot),
+            // Time above 40 °C counts twice, being above 35 °C as well
+            score: score(warm + hot, 0.0, 40.0),
+            weight: HEAT_WEIGHT,
+            contribution: 0.0,
+            advice: "Keep the vents clear and the laptop off soft surfaces, and avoid heavy loads while it charges."
+                .to_string(),
+        });
+    }
+    if let Some(depth) = avg_depth_of_discharge {
+        factors.push(StressFactor {
+            factor: "depth".to_string(),
+            label: "Discharge depth".to_string(),
+            measured: format!("{:.0} points on average", depth),
+            score: score(depth, 20.0, 80.0),
+            weight: DEPTH_WEIGHT,
+            contribution: 0.0,
+            advice: "Recharge sooner: shallow cycles (say 80% to 40%) wear the pack less than full ones."
+                .to_string(),
+        });
+    }
+    if let Some(rate) = avg_charge_rate {
+        factors.push(StressFactor {
+            factor: "charge_rate".to_string(),
+            label: "Charging power".to_string(),
+            measured: format!("{:.2}C ({:.0} W)", rate, avg_charging_power_watts.unwrap_or_default()),
+            score: score(rate, 0.5, 1.0),
+            weight: CHARGE_RATE_WEIGHT,
+            contribution: 0.0,
+            advice: "Use a lower-wattage charger, or a slow or adaptive charging mode, when there is no hurry."
+                .to_string(),
+        });
+    }
+
+    let total_weight: f64 = factors.iter().map(|f| f.weight).sum();
+    for factor in &mut factors {
+        factor.contribution = factor.score * factor.weight / total_weight;
+    }
+    let index = factors.iter().map(|f| f.contribution).sum::<f64>();
+    let costliest = factors
+        .iter()
+        .filter(|f| f.contribution > 0.0)
+        .max_by(|a, b| a.contribution.total_cmp(&b.contribution))
+        .map(|f| f.factor.clone());
+
+    Some(StressReport {
+        start: first.timestamp,
+        end: last.timestamp,
+        hours: exposure.hours,
+        high_charge_percent,
+        low_charge_percent,
+        warm_percent,
+        hot_percent,
+        avg_depth_of_discharge,
+        discharges: depths.len(),
+        avg_charging_power_watts,
+        avg_charge_rate,
+        index,
+        level: stress_level(index).to_string(),
+        factors,
+        costliest,
+    })
+}
+
+fn stress_level(index: f64) -> &'static str {
+    match index {
+        i if i < 25.0 => "low",
+        i if i < 50.0 => "moderate",
+        i if i < 75.0 => "high",
+        _ => "severe",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::ChargingState;
+
+    fn reading(
+        minute: i64,
+        level: u8,
+        state: ChargingState,
+        watts: f64,
+        celsius: f64,
+    ) -> BatterySnapshot {
+        BatterySnapshot {
+            timestamp: DateTime::from_timestamp(1_700_000_000 + minute * 60, 0).unwrap(),
+            battery: Some("BAT0".to_string()),
+            level,
+            is_charging: matches!(state, ChargingState::Charging | ChargingState::Full),
+            state: Some(state),
+            power_draw: Some(watts),
+            max_energy: Some(50000),
+            temperature_celsius: Some(celsius),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plugged_in_all_day() {
+        use ChargingState::*;
+        // Every day: on the charger at 100% and warm for 20 hours, then a
+        // short run on battery and a fast recharge
+        let mut rows = Vec::new();
+        for day in 0..7 {
+            let start = day * 1440;
+            for hour in 0..20 {
+                rows.push(reading(start + hour * 60, 100, Full, 0.0, 38.0));
+            }
+            rows.push(reading(start + 1200, 100, Discharging, 12.0, 33.0));
+            rows.push(reading(start + 1260, 80, Discharging, 12.0, 31.0));
+            rows.push(reading(start + 1320, 60, Charging, 40.0, 42.0));
+            rows.push(reading(start + 1380, 90, Charging, 35.0, 41.0));
+        }
+        rows.push(reading(7 * 1440, 100, Full, 0.0, 36.0));
+
+        let report = analyze(&rows).unwrap();
+        assert!((report.hours - 168.0).abs() < 1e-9);
+        assert!(
+            report.high_charge_percent > 80.0,
+            "{}",
+            report.high_charge_percent
+        );
+        assert_eq!(report.low_charge_percent, 0.0);
+        assert_eq!(report.discharges, 7);
+        assert_eq!(report.avg_depth_of_discharge, Some(40.0));
+        assert_eq!(report.avg_charging_power_watts, Some(37.5));
+        assert!((report.avg_charge_rate.unwrap() - 0.75).abs() < 1e-9);
+
+        assert_eq!(report.costliest.as_deref(), Some("high_charge"));
+        let total: f64 = report.factors.iter().map(|f| f.contribution).sum();
+        assert!((total - report.index).abs() < 1e-9);
+        // 30 for the charge level, 25 for the heat, 5 for the depth and 7.5
+        // for the charging power
+        assert!((report.index - 67.5).abs() < 1e-6, "{}", report.index);
+        assert_eq!(report.level, "high");
+    }
+
+    #[test]
+    fn test_gentle_use_scores_low() {
+        use ChargingState::*;
+        let rows: Vec<BatterySnapshot> = (0..48)
+            .map(|i| {
+                let level = 70 - (i % 12) as u8;
+                reading(i * 30, level, Discharging, 8.0, 30.0)
+            })
+            .collect();
+        let report = analyze(&rows).unwrap();
+        assert_eq!(report.index, 0.0);
+        assert_eq!(report.level, "low");
+        assert_eq!(report.costliest, None);
+        // No charge sessions, so no charging power factor
+        assert!(report.factors.iter().all(|f| f.factor != "charge_rate"));
+
+        assert!(analyze(&rows[..1]).is_none());
+    }
+}
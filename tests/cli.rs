@@ -0,0 +1,759 @@
+//! Runs every subcommand against the canned sysfs trees in `tests/fixtures/sysfs`.
+//!
+//! Each test gets its own home directory so the history database never
+//! touches the real one.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+const TREES: [&str; 4] = ["wh", "mah", "broken", "dual"];
+
+fn fixture(tree: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/sysfs")
+        .join(tree)
+}
+
+struct Sandbox {
+    home: tempfile::TempDir,
+    tree: &'static str,
+    root: PathBuf,
+}
+
+impl Sandbox {
+    fn new(tree: &'static str) -> Self {
+        Self {
+            home: tempfile::tempdir().unwrap(),
+            tree,
+            root: fixture(tree),
+        }
+    }
+
+    /// Like `new`, but on a writable copy of the tree.
+    fn writable(tree: &'static str) -> Self {
+        let home = tempfile::tempdir().unwrap();
+        let root = home.path().join("power_supply");
+        for supply in std::fs::read_dir(fixture(tree)).unwrap().flatten() {
+            let dest = root.join(supply.file_name());
+            std::fs::create_dir_all(&dest).unwrap();
+            for file in std::fs::read_dir(supply.path()).unwrap().flatten() {
+                std::fs::copy(file.path(), dest.join(file.file_name())).unwrap();
+            }
+        }
+        Self { home, tree, root }
+    }
+
+    fn read(&self, rel: &str) -> String {
+        std::fs::read_to_string(self.root.join(rel))
+            .unwrap()
+            .trim()
+            .to_string()
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_batteryctl"));
+        cmd.args(args)
+            .env("HOME", self.home.path())
+            .env("BATTERYCTL_SYSFS_ROOT", &self.root)
+            .env("NO_COLOR", "1")
+            .env_remove("XDG_DATA_HOME")
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("BATTERYCTL_DB")
+            .env_remove("BATTERYCTL_CONFIG");
+        cmd
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    fn run_ok(&self, args: &[&str]) -> String {
+        let out = self.run(args);
+        assert!(
+            out.status.success(),
+            "`batteryctl {}` failed on '{}' tree:\n{}",
+            args.join(" "),
+            self.tree,
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8_lossy(&out.stdout).to_string()
+    }
+
+    fn run_json(&self, args: &[&str]) -> serde_json::Value {
+        let mut with_json = args.to_vec();
+        with_json.push("--json");
+        serde_json::from_str(&self.run_ok(&with_json)).unwrap()
+    }
+}
+
+#[test]
+fn status_on_every_tree() {
+    for tree in TREES {
+        let sb = Sandbox::new(tree);
+        let out = sb.run_ok(&["status"]);
+        assert!(out.contains("BATTERY STATUS"), "{}", out);
+        sb.run_ok(&["status", "--detailed"]);
+        let json = sb.run_json(&["status"]);
+        assert!(json["level"].is_u64());
+    }
+}
+
+#[test]
+fn status_reads_fixture_values() {
+    let wh = Sandbox::new("wh").run_json(&["status"]);
+    assert_eq!(wh["name"], "BAT0");
+    assert_eq!(wh["level"], 64);
+    assert_eq!(wh["state"], "Discharging");
+    assert_eq!(wh["cycle_count"], 187);
+    assert_eq!(wh["capacity_unit"], "mWh");
+    assert_eq!(wh["max_energy_mwh"], 50160);
+    // Derived through voltage_min_design (11.55 V)
+    assert_eq!(wh["max_capacity_mah"], 4343);
+
+    assert_eq!(wh["manufacturer"], "SMP");
+    assert_eq!(wh["serial_number"], "1234");
+    assert_eq!(wh["technology"], "Li-poly");
+
+    let mah = Sandbox::new("mah").run_json(&["status"]);
+    assert_eq!(mah["level"], 76);
+    assert_eq!(mah["max_capacity_mah"], 4630);
+    assert_eq!(mah["capacity_unit"], "mAh");
+    assert_eq!(mah["max_energy_mwh"], 35188);
+    assert_eq!(mah["temperature_celsius"], 31.8);
+
+    let broken = Sandbox::new("broken").run_json(&["status"]);
+    assert_eq!(broken["state"], "Unknown");
+    assert_eq!(broken["condition"], "Unknown");
+    assert_eq!(broken["level"], 0);
+}
+
+#[test]
+fn status_combines_and_selects_packs() {
+    let sb = Sandbox::new("dual");
+    let combined = sb.run_json(&["status"]);
+    assert_eq!(combined["name"], "combined");
+    assert_eq!(combined["packs"].as_array().unwrap().len(), 2);
+    assert_eq!(combined["state"], "Discharging");
+
+    let bat1 = sb.run_json(&["status", "--battery", "BAT1"]);
+    assert_eq!(bat1["name"], "BAT1");
+    assert_eq!(bat1["level"], 100);
+
+    let missing = sb.run(&["status", "--battery", "BAT7"]);
+    assert!(!missing.status.success());
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("BAT0, BAT1"));
+}
+
+#[test]
+fn health_on_every_tree() {
+    for tree in TREES {
+        let sb = Sandbox::new(tree);
+        sb.run_ok(&["record"]);
+        let out = sb.run_ok(&["health", "--history", "--compare-new"]);
+        assert!(out.contains("Battery Health Report"), "{}", out);
+        let json = sb.run_json(&["health"]);
+        assert!(json["condition"].is_string());
+    }
+}
+
+#[test]
+fn cycle_rating_follows_pack_and_config() {
+    let sb = Sandbox::new("wh");
+    let json = sb.run_json(&["health"]);
+    assert_eq!(json["cycle_rating"]["rated_cycles"], 500);
+    assert_eq!(json["estimated_remaining_cycles"], 313);
+
+    let config = sb.home.path().join("config.json");
+    std::fs::write(
+        &config,
+        r#"{"cycle_ratings": [{"name": "Spare", "model": "5b10w", "rated_cycles": 800, "end_of_life_percent": 70}]}"#,
+    )
+    .unwrap();
+    let json = sb.run_json(&["health", "--config", config.to_str().unwrap()]);
+    assert_eq!(json["cycle_rating"]["source"], "Spare");
+    assert_eq!(json["estimated_remaining_cycles"], 613);
+}
+
+#[test]
+fn history_and_record_on_every_tree() {
+    for tree in TREES {
+        let sb = Sandbox::new(tree);
+        sb.run_ok(&["record"]);
+        sb.run_ok(&["status"]);
+
+        let json = sb.run_json(&["history", "-d", "1h"]);
+        assert!(json["summary"]["snapshots_count"].as_u64().unwrap() >= 2);
+
+        let csv = sb.home.path().join("export.csv");
+        sb.run_ok(&["history", "-d", "1h", "-o", csv.to_str().unwrap()]);
+        let exported = std::fs::read_to_string(&csv).unwrap();
+        assert!(exported.starts_with("timestamp,"));
+        assert!(exported.lines().next().unwrap().contains("temperature_c"));
+
+        sb.run_ok(&["history", "-d", "1h"]);
+    }
+}
+
+#[test]
+fn identity_is_shown_and_recorded() {
+    let sb = Sandbox::new("wh");
+    let out = sb.run_ok(&["status", "--detailed"]);
+    assert!(out.contains("SMP 5B10W13930"), "{}", out);
+    assert!(sb.run_ok(&["health"]).contains("Li-poly"));
+    assert_eq!(sb.run_json(&["health"])["serial_number"], "1234");
+
+    let dual = Sandbox::new("dual");
+    dual.run_ok(&["record"]);
+    let bat1 = dual.run_json(&["history", "-d", "1h", "--battery", "BAT1"]);
+    assert_eq!(bat1["snapshots"][0]["serial_number"], "1187");
+    let combined = dual.run_json(&["status"]);
+    assert!(combined["serial_number"].is_null());
+    assert_eq!(combined["technology"], "Li-ion");
+}
+
+#[test]
+fn replaced_pack_starts_a_new_history() {
+    let sb = Sandbox::writable("wh");
+    sb.run_ok(&["record"]);
+    std::thread::sleep(Duration::from_millis(1100));
+    std::fs::write(sb.root.join("BAT0/serial_number"), "9999\n").unwrap();
+    std::fs::write(sb.root.join("BAT0/cycle_count"), "1\n").unwrap();
+    sb.run_ok(&["record"]);
+
+    let current = sb.run_json(&["history", "-d", "1h"]);
+    assert_eq!(current["snapshots"].as_array().unwrap().len(), 1);
+    assert_eq!(current["packs"].as_array().unwrap().len(), 2);
+    assert_eq!(current["packs"][1]["serial_number"], "9999");
+
+    let first = current["packs"][0]["id"].to_string();
+    let earlier = sb.run_json(&["history", "-d", "1h", "--pack", &first]);
+    assert_eq!(earlier["snapshots"][0]["serial_number"], "1234");
+    let all = sb.run_json(&["history", "-d", "1h", "--pack", "all"]);
+    assert_eq!(all["snapshots"].as_array().unwrap().len(), 2);
+
+    assert!(sb
+        .run_ok(&["history", "-d", "1h"])
+        .contains("Battery Packs:"));
+    assert!(sb.run_json(&["health", "--history"])["first_seen"].is_string());
+}
+
+#[test]
+fn history_is_kept_per_pack() {
+    let sb = Sandbox::new("dual");
+    sb.run_ok(&["record"]);
+
+    let combined = sb.run_json(&["history", "-d", "1h"]);
+    assert_eq!(combined["snapshots"].as_array().unwrap().len(), 1);
+    assert_eq!(combined["snapshots"][0]["battery"], "combined");
+
+    let bat0 = sb.run_json(&["history", "-d", "1h", "--battery", "BAT0"]);
+    assert_eq!(bat0["snapshots"][0]["level"], 50);
+    let bat1 = sb.run_json(&["history", "-d", "1h", "--battery", "BAT1"]);
+    assert_eq!(bat1["snapshots"][0]["state"], "NotCharging");
+}
+
+#[test]
+fn optimize_and_power_hogs_on_every_tree() {
+    for tree in TREES {
+        let sb = Sandbox::new(tree);
+        let json = sb.run_json(&["optimize"]);
+        assert!(!json["suggestions"].as_array().unwrap().is_empty());
+        sb.run_ok(&["optimize", "--aggressive"]);
+        sb.run_json(&["power-hogs"]);
+    }
+}
+
+#[test]
+fn alert_requires_a_condition() {
+    let out = Sandbox::new("wh").run(&["alert"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--level"));
+}
+
+#[test]
+fn alert_monitor_reports_low_battery() {
+    let sb = Sandbox::new("wh");
+    let mut child = sb
+        .command(&["alert", "--level", "80"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(1500));
+    child.kill().unwrap();
+    let out = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Battery LOW: 64%"), "{}", stderr);
+}
+
+#[test]
+fn missing_root_reports_no_battery() {
+    let sb = Sandbox::new("does-not-exist");
+    let out = sb.run(&["status"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("No battery found"));
+}
+
+#[test]
+fn limit_shows_thresholds_and_unsupported_hardware() {
+    let json = Sandbox::new("wh").run_json(&["limit"]);
+    assert_eq!(json[0]["battery"], "BAT0");
+    assert_eq!(json[0]["limit"]["end"], 80);
+
+    let unsupported = Sandbox::new("mah").run_json(&["limit"]);
+    assert_eq!(unsupported[0]["supported"], false);
+
+    let out = Sandbox::new("mah").run(&["limit", "--stop", "80"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not supported on BAT0"));
+}
+
+#[test]
+fn limit_writes_thresholds() {
+    let sb = Sandbox::writable("dual");
+
+    sb.run_ok(&["limit", "--stop", "60", "--start", "50", "--dry-run"]);
+    assert_eq!(sb.read("BAT0/charge_control_end_threshold"), "80");
+
+    sb.run_ok(&[
+        "limit",
+        "--battery",
+        "BAT1",
+        "--stop",
+        "60",
+        "--start",
+        "50",
+    ]);
+    assert_eq!(sb.read("BAT1/charge_control_start_threshold"), "50");
+    assert_eq!(sb.read("BAT1/charge_control_end_threshold"), "60");
+    assert_eq!(sb.read("BAT0/charge_control_end_threshold"), "80");
+
+    sb.run_ok(&["limit", "--off"]);
+    assert_eq!(sb.read("BAT0/charge_control_start_threshold"), "0");
+    assert_eq!(sb.read("BAT0/charge_control_end_threshold"), "100");
+    assert_eq!(sb.read("BAT1/charge_control_end_threshold"), "100");
+
+    // Stop below the current start is rejected before anything is written
+    sb.run_ok(&["limit", "--start", "70", "--stop", "80"]);
+    let out = sb.run(&["limit", "--stop", "60"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("must be below"));
+    assert_eq!(sb.read("BAT0/charge_control_end_threshold"), "80");
+}
+
+#[test]
+fn limit_checks_every_pack_before_writing() {
+    let sb = Sandbox::writable("dual");
+    sb.run_ok(&[
+        "limit",
+        "--battery",
+        "BAT0",
+        "--start",
+        "40",
+        "--stop",
+        "50",
+    ]);
+
+    // BAT1 still starts at 75%, so a 60% stop fails there and BAT0 is kept
+    let out = sb.run(&["limit", "--stop", "60"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("must be below"));
+    assert_eq!(sb.read("BAT0/charge_control_end_threshold"), "50");
+
+    std::fs::remove_file(sb.root.join("BAT1/charge_control_end_threshold")).unwrap();
+    std::fs::remove_file(sb.root.join("BAT1/charge_control_start_threshold")).unwrap();
+    let out = sb.run(&["limit", "--stop", "70"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not supported on BAT1"));
+    assert_eq!(sb.read("BAT0/charge_control_end_threshold"), "50");
+}
+
+#[test]
+fn active_limit_is_reported() {
+    let sb = Sandbox::new("wh");
+    assert!(sb
+        .run_ok(&["status", "--detailed"])
+        .contains("Charge Limit:"));
+    let health = sb.run_json(&["health"]);
+    assert_eq!(health["charge_limit"]["start"], 75);
+}
+
+#[test]
+fn charge_lists_and_switches_modes() {
+    let json = Sandbox::new("wh").run_json(&["charge"]);
+    assert_eq!(json[0]["behaviour"]["active"], "auto");
+    assert_eq!(json[0]["behaviour"]["available"][2], "force-discharge");
+
+    let unsupported = Sandbox::new("mah").run_json(&["charge"]);
+    assert!(unsupported[0]["behaviour"].is_null());
+
+    let sb = Sandbox::writable("wh");
+    sb.run_ok(&["charge", "inhibit-charge", "--dry-run"]);
+    assert!(sb.read("BAT0/charge_behaviour").starts_with("[auto]"));
+
+    let out = sb.run(&["charge", "turbo"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Supported modes"));
+
+    sb.run_ok(&["charge", "inhibit-charge"]);
+    assert_eq!(sb.read("BAT0/charge_behaviour"), "inhibit-charge");
+}
+
+#[test]
+fn charge_checks_every_pack_before_switching() {
+    let sb = Sandbox::writable("dual");
+    std::fs::write(
+        sb.root.join("BAT1/charge_behaviour"),
+        "[auto] inhibit-charge\n",
+    )
+    .unwrap();
+
+    let out = sb.run(&["charge", "force-discharge", "--for", "1m"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("BAT1 does not support"));
+    assert!(sb.read("BAT0/charge_behaviour").starts_with("[auto]"));
+    assert!(sb.read("BAT1/charge_behaviour").starts_with("[auto]"));
+}
+
+#[test]
+fn charge_reverts_when_level_is_reached() {
+    // The wh pack is at 64%, so a drain to 70% is already done
+    let sb = Sandbox::writable("wh");
+    let out = sb.run(&["charge", "force-discharge", "--until-level", "70"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Reverted BAT0"));
+    assert_eq!(sb.read("BAT0/charge_behaviour"), "auto");
+}
+
+#[test]
+fn database_location_is_configurable() {
+    let sb = Sandbox::new("wh");
+    sb.run_ok(&["record"]);
+    let default = sb.home.path().join(".local/share/batteryctl/history.db");
+    assert!(default.exists());
+
+    let custom = sb.home.path().join("elsewhere.db");
+    sb.run_ok(&["record", "--db", custom.to_str().unwrap()]);
+    assert!(custom.exists());
+    let json = sb.run_json(&["history", "-d", "1h", "--db", custom.to_str().unwrap()]);
+    assert_eq!(json["snapshots"].as_array().unwrap().len(), 1);
+
+    let out = sb
+        .command(&["history", "-d", "1h", "--json"])
+        .env("BATTERYCTL_DB", &custom)
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(json["snapshots"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn legacy_database_is_moved_to_data_dir() {
+    let sb = Sandbox::new("wh");
+    let legacy = sb.home.path().join(".batteryctl/history.db");
+    sb.run_ok(&["record", "--db", legacy.to_str().unwrap()]);
+
+    let json = sb.run_json(&["history", "-d", "1h"]);
+    assert_eq!(json["snapshots"].as_array().unwrap().len(), 1);
+    assert!(!legacy.exists());
+    assert!(sb
+        .home
+        .path()
+        .join(".local/share/batteryctl/history.db")
+        .exists());
+}
+
+#[test]
+fn long_history_reads_rollups() {
+    let sb = Sandbox::new("dual");
+    sb.run_ok(&["record"]);
+
+    let week = sb.run_json(&["history", "-d", "7d"]);
+    assert_eq!(week["summary"]["granularity"], "hourly");
+    assert_eq!(week["snapshots"].as_array().unwrap().len(), 1);
+    let year = sb.run_json(&["history", "-d", "12m", "--battery", "BAT0"]);
+    assert_eq!(year["summary"]["granularity"], "daily");
+    assert_eq!(year["snapshots"][0]["level"], 50);
+
+    let config = sb.home.path().join("config.json");
+    std::fs::write(&config, r#"{"retention": {"raw_days": 0}}"#).unwrap();
+    let out = sb.run(&["status", "--config", config.to_str().unwrap()]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("raw_days"));
+}
+
+#[test]
+fn history_maintenance_subcommands() {
+    let sb = Sandbox::new("dual");
+    sb.run_ok(&["record"]);
+
+    let stats = sb.run_json(&["history", "stats"]);
+    // One row per pack plus the combined reading
+    assert_eq!(stats["snapshots"], 3);
+    assert_eq!(stats["packs"][1]["battery"], "BAT1");
+    assert!(stats["file_size_bytes"].as_u64().unwrap() > 0);
+    assert!(sb.run_ok(&["history", "stats"]).contains("Per Pack:"));
+
+    let check = sb.run_json(&["history", "check"]);
+    assert_eq!(check["ok"], true);
+
+    let pruned = sb.run_json(&["history", "prune", "--older-than", "90d", "--dry-run"]);
+    assert_eq!(pruned["deleted"]["raw"], 0);
+    sb.run_ok(&["history", "vacuum"]);
+    assert_eq!(sb.run_json(&["history", "stats"])["snapshots"], 3);
+
+    let out = sb.run(&["history", "prune"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn history_import_and_merge() {
+    let sb = Sandbox::new("wh");
+    sb.run_ok(&["record"]);
+    let csv = sb.home.path().join("export.csv");
+    sb.run_ok(&["history", "-d", "1h", "-o", csv.to_str().unwrap()]);
+
+    // Re-importing this machine's own export adds nothing
+    let counts = sb.run_json(&["history", "import", csv.to_str().unwrap()]);
+    assert_eq!(counts["snapshots"], 0);
+    assert_eq!(counts["duplicates"], 1);
+
+    let laptop = Sandbox::new("dual");
+    let other = sb.home.path().join("bob-laptop.db");
+    laptop.run_ok(&["record", "--db", other.to_str().unwrap()]);
+
+    let merged = sb.run_json(&["history", "merge", other.to_str().unwrap()]);
+    assert_eq!(merged["host"], "bob-laptop");
+    assert_eq!(merged["merged"]["snapshots"], 3);
+
+    let own = sb.run_json(&["history", "-d", "1h"]);
+    assert_eq!(own["snapshots"].as_array().unwrap().len(), 1);
+    assert!(sb.run_ok(&["history", "-d", "1h"]).contains("bob-laptop"));
+
+    let bob = sb.run_json(&[
+        "history",
+        "-d",
+        "1h",
+        "--host",
+        "bob-laptop",
+        "--battery",
+        "BAT1",
+    ]);
+    assert_eq!(bob["snapshots"].as_array().unwrap().len(), 1);
+    assert_eq!(bob["snapshots"][0]["host"], "bob-laptop");
+
+    let health = sb.run_json(&["health", "--host", "bob-laptop", "--history"]);
+    assert_eq!(health["battery"], "combined");
+    let out = sb.run(&["health", "--host", "nobody"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn history_exports_in_every_format() {
+    let sb = Sandbox::new("wh");
+    sb.run_ok(&["record"]);
+
+    let ndjson = sb.run_ok(&["history", "-d", "1h", "-o", "-", "--format", "ndjson"]);
+    assert_eq!(ndjson.lines().count(), 1);
+    let row: serde_json::Value = serde_json::from_str(ndjson.trim()).unwrap();
+    assert_eq!(row["battery"], "BAT0");
+
+    let influx = sb.run_ok(&["history", "-d", "1h", "-o", "-", "--format", "influx"]);
+    assert!(influx.starts_with("battery,battery=BAT0"));
+    assert!(influx.contains(" level="));
+
+    let dump = sb.home.path().join("history.sql");
+    sb.run_ok(&["history", "-d", "1h", "-o", dump.to_str().unwrap()]);
+    let sql = std::fs::read_to_string(&dump).unwrap();
+    assert!(sql.contains("CREATE TABLE IF NOT EXISTS snapshots"));
+    assert!(sql.trim_end().ends_with("COMMIT;"));
+
+    let out = sb.run(&["history", "-d", "1h", "-o", "-", "--format", "xml"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn os_history_is_imported() {
+    let sb = Sandbox::new("wh");
+    let upower = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/upower");
+    let json = sb.run_json(&["history", "import-upower", upower.to_str().unwrap()]);
+    assert_eq!(json["devices"][0]["battery"], "BAT0");
+    assert_eq!(json["devices"][0]["imported"]["snapshots"], 19);
+    assert_eq!(json["skipped"][0], "hidpp_battery_0");
+
+    let again = sb.run_json(&["history", "import-upower", upower.to_str().unwrap()]);
+    assert_eq!(again["devices"][0]["imported"]["snapshots"], 0);
+
+    // Older than the raw retention, so only the rollups are kept
+    let stats = sb.run_json(&["history", "stats"]);
+    assert!(stats["hourly_rollups"].as_u64().unwrap() > 0);
+
+    let pmset = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/pmset.log");
+    let counts = sb.run_json(&["history", "import-pmset", pmset.to_str().unwrap()]);
+    assert_eq!(counts["snapshots"], 5);
+    // 08:02 +0200 falls in an hour UPower already covered and that is rolled up
+    assert_eq!(counts["duplicates"], 1);
+}
+
+#[test]
+fn sessions_are_split_from_history() {
+    let sb = Sandbox::new("wh");
+    let now = chrono::Utc::now();
+    let rows: Vec<serde_json::Value> = [
+        (180, 90, "Discharging", 10.0),
+        (150, 80, "Discharging", 12.0),
+        (120, 70, "Discharging", 8.0),
+        (90, 71, "Charging", 40.0),
+        (60, 95, "Charging", 20.0),
+        (30, 100, "Full", 0.0),
+    ]
+    .into_iter()
+    .map(|(ago, level, state, power)| {
+        serde_json::json!({
+            "timestamp": now - chrono::Duration::minutes(ago),
+            "battery": "BAT0",
+            "level": level,
+            "is_charging": state != "Discharging",
+            "state": state,
+            "power_draw": power,
+        })
+    })
+    .collect();
+    let file = sb.home.path().join("day.json");
+    std::fs::write(&file, serde_json::to_string(&rows).unwrap()).unwrap();
+    sb.run_ok(&["history", "import", file.to_str().unwrap()]);
+
+    let json = sb.run_json(&["sessions", "-d", "7d"]);
+    let sessions = json["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0]["kind"], "discharge");
+    assert_eq!(sessions[0]["start_level"], 90);
+    assert_eq!(sessions[0]["end_level"], 71);
+    assert_eq!(sessions[0]["peak_power_watts"], 12.0);
+    assert_eq!(sessions[1]["kind"], "charge");
+    assert_eq!(sessions[1]["end_level"], 100);
+
+    let out = sb.run_ok(&["sessions", "-d", "7d"]);
+    assert!(out.contains("discharge"), "{}", out);
+    assert!(sb.run_ok(&["sessions"]).contains("Sessions"));
+}
+
+#[test]
+fn sleep_drain_is_reported() {
+    let sb = Sandbox::new("wh");
+    let now = chrono::Utc::now();
+    // Asleep on battery overnight, losing 2%/h
+    let rows: Vec<serde_json::Value> = [(600, 80), (590, 79), (110, 63), (100, 62)]
+        .into_iter()
+        .map(|(ago, level)| {
+            serde_json::json!({
+                "timestamp": now - chrono::Duration::minutes(ago),
+                "battery": "BAT0",
+                "level": level,
+                "is_charging": false,
+                "state": "Discharging",
+            })
+        })
+        .collect();
+    let file = sb.home.path().join("night.json");
+    std::fs::write(&file, serde_json::to_string(&rows).unwrap()).unwrap();
+    sb.run_ok(&["history", "import", file.to_str().unwrap()]);
+
+    let json = sb.run_json(&["sleep"]);
+    assert_eq!(json["periods"].as_array().unwrap().len(), 1);
+    assert_eq!(json["periods"][0]["source"], "gap");
+    assert_eq!(json["periods"][0]["drain_per_hour"], 2.0);
+    assert_eq!(json["abnormal"], 1);
+    assert!(sb.run_ok(&["sleep"]).contains("abnormal"));
+    assert!(sb.run_ok(&["history"]).contains("Asleep or off: 8h 00m"));
+
+    assert!(sb.run_ok(&["hook", "pre-sleep"]).contains("Recorded sleep"));
+    sb.run_ok(&["hook", "post-sleep"]);
+    assert_eq!(sb.run_json(&["history", "stats"])["snapshots"], 6);
+}
+
+#[test]
+fn stress_is_scored_from_history() {
+    let sb = Sandbox::new("wh");
+    let now = chrono::Utc::now();
+    // Plugged in at full charge all day
+    let rows: Vec<serde_json::Value> = (0..=12)
+        .map(|hour| {
+            serde_json::json!({
+                "timestamp": now - chrono::Duration::hours(24 - hour),
+                "battery": "BAT0",
+                "level": 100,
+                "is_charging": true,
+                "state": "Full",
+                "temperature_celsius": 30.0,
+            })
+        })
+        .collect();
+    let file = sb.home.path().join("desk.json");
+    std::fs::write(&file, serde_json::to_string(&rows).unwrap()).unwrap();
+    sb.run_ok(&["history", "import", file.to_str().unwrap()]);
+
+    let json = sb.run_json(&["health", "--stress"]);
+    assert_eq!(json["stress"]["high_charge_percent"], 100.0);
+    assert_eq!(json["stress"]["costliest"], "high_charge");
+    assert!(json["stress"]["factors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|f| f["factor"] == "heat"));
+    let out = sb.run_ok(&["health", "--stress"]);
+    assert!(out.contains("Costs the most:"), "{}", out);
+    assert!(sb.run_json(&["health"])["stress"].is_null());
+
+    // Nothing before the readings, and nothing older than raw retention
+    let json = sb.run_json(&["health", "--stress", "--until", "2026-01-01"]);
+    assert!(json["stress"].is_null());
+    let out = sb.run_ok(&["health", "--stress", "--until", "2026-01-01"]);
+    assert!(out.contains("Not enough readings to score"), "{}", out);
+    assert!(!sb
+        .run_ok(&["health", "--stress"])
+        .contains("retention.raw_days"));
+    let out = sb.run_ok(&["health", "--stress", "--since", "90d"]);
+    assert!(
+        out.contains("Costs the most:") && out.contains("retention.raw_days"),
+        "{}",
+        out
+    );
+    assert!(!sb.run(&["health", "--since", "7d"]).status.success());
+}
+
+#[test]
+fn history_accepts_absolute_ranges() {
+    let sb = Sandbox::new("wh");
+    let upower = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/upower");
+    sb.run_ok(&["history", "import-upower", upower.to_str().unwrap()]);
+
+    // The fixture starts on 2026-09-09 UTC
+    let range = [
+        "--since",
+        "2026-09-09T00:00:00Z",
+        "--until",
+        "2026-09-11T00:00:00Z",
+    ];
+    let json = sb.run_json(&[&["history"][..], &range].concat());
+    assert!(!json["snapshots"].as_array().unwrap().is_empty());
+    assert!(json["summary"]["period_description"]
+        .as_str()
+        .unwrap()
+        .contains(" to "));
+
+    let csv = sb.home.path().join("range.csv");
+    sb.run_ok(&[&["history", "-o", csv.to_str().unwrap()][..], &range].concat());
+    assert!(std::fs::read_to_string(&csv).unwrap().lines().count() > 1);
+
+    let before = sb.run_json(&["history", "--until", "2026-09-01", "-d", "1w"]);
+    assert!(before["snapshots"].as_array().unwrap().is_empty());
+    assert!(sb
+        .run_ok(&["history", "-d", "1d12h"])
+        .contains("Last 1 day 12 hours"));
+    assert!(!sb
+        .run(&["history", "-d", "2d", "--since", "2026-09-09"])
+        .status
+        .success());
+    sb.run_ok(&["health", "--history", "--since", "2026-09-01"]);
+}